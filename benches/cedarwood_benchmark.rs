@@ -2,11 +2,11 @@
 extern crate criterion;
 extern crate cedarwood;
 
-use cedarwood::Cedar;
+use cedarwood::{ArenaCedar, Cedar};
 use criterion::Criterion;
 
-fn build_cedar() -> Cedar {
-    let dict = vec![
+fn dict() -> Vec<&'static str> {
+    vec![
         "a",
         "ab",
         "abc",
@@ -20,13 +20,58 @@ fn build_cedar() -> Cedar {
         "中华",
         "中华人民",
         "中华人民共和国",
-    ];
-    let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+    ]
+}
+
+fn build_cedar() -> Cedar {
+    let key_values: Vec<(&str, i32)> = dict().into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
     let mut cedar = Cedar::new();
     cedar.build(&key_values);
     cedar
 }
 
+fn build_arena_cedar() -> ArenaCedar {
+    let key_values: Vec<(&str, i32)> = dict().into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+    let mut cedar = ArenaCedar::new();
+    cedar.build(&key_values);
+    cedar
+}
+
+// a dictionary where most of the keys have been erased, with the keys left in it.
+fn build_erased_cedar() -> (Cedar, Vec<String>) {
+    let keys: Vec<String> = (0..50000).map(|i| format!("{:x}", i * 7919)).collect();
+    let mut cedar = Cedar::new();
+    for (k, s) in keys.iter().enumerate() {
+        cedar.update(s, k as i32);
+    }
+    for s in keys.iter().skip(1000) {
+        cedar.erase(s);
+    }
+
+    (cedar, keys[..1000].to_vec())
+}
+
+// keys whose nodes have at most 16 children.
+fn low_fanout_keys() -> Vec<String> {
+    (0..20000).map(|i| format!("{:x}", i * 7919)).collect()
+}
+
+// keys of two printable ascii bytes, so the root and every first byte branch into 94 children.
+fn high_fanout_keys() -> Vec<String> {
+    let bytes = 0x21u8..0x7f;
+    bytes
+        .clone()
+        .flat_map(|a| bytes.clone().map(move |b| String::from_utf8(vec![a, b]).unwrap()))
+        .collect()
+}
+
+fn build_from_keys(keys: &[String]) {
+    let mut cedar = Cedar::new();
+    for (k, s) in keys.iter().enumerate() {
+        cedar.update(s, k as i32);
+    }
+}
+
 fn bench_cedar_build() {
     let _cedar = build_cedar();
 }
@@ -49,8 +94,53 @@ fn bench_common_prefix_predict() {
 fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("cedar build", |b| b.iter(bench_cedar_build));
     c.bench_function("cedar exact_match_search", |b| b.iter(bench_exact_match_search));
+
+    let keys = low_fanout_keys();
+    c.bench_function("cedar build low fanout", |b| b.iter(|| build_from_keys(&keys)));
+    let keys = high_fanout_keys();
+    c.bench_function("cedar build high fanout", |b| b.iter(|| build_from_keys(&keys)));
+
+    let keys = low_fanout_keys();
+    let key_values: Vec<(&str, i32)> = keys.iter().enumerate().map(|(k, s)| (s.as_ref(), k as i32)).collect();
+    c.bench_function("cedar build unsorted", |b| b.iter(|| Cedar::new().build(&key_values)));
+    c.bench_function("cedar build_sorted", |b| {
+        b.iter(|| Cedar::new().build_sorted(&key_values))
+    });
     c.bench_function("cedar common_prefix_search", |b| b.iter(bench_common_prefix_search));
     c.bench_function("cedar common_prefix_predict", |b| b.iter(bench_common_prefix_predict));
+
+    let cedar = build_cedar();
+    c.bench_function("cedar longest_prefix_match", |b| {
+        b.iter(|| cedar.longest_prefix_match("中华人民共和国万岁"))
+    });
+    c.bench_function("cedar common_prefix_search last", |b| {
+        b.iter(|| {
+            cedar
+                .common_prefix_search("中华人民共和国万岁")
+                .unwrap()
+                .last()
+                .cloned()
+        })
+    });
+
+    let cedar = build_cedar();
+    c.bench_function("inline exact_match_search", |b| {
+        b.iter(|| dict().iter().filter_map(|k| cedar.exact_match_search(k)).count())
+    });
+    let arena = build_arena_cedar();
+    c.bench_function("arena exact_match_search", |b| {
+        b.iter(|| dict().iter().filter_map(|k| arena.exact_match_search(k)).count())
+    });
+
+    let (cedar, keys) = build_erased_cedar();
+    c.bench_function("exact_match_search after erase", |b| {
+        b.iter(|| keys.iter().filter_map(|k| cedar.exact_match_search(k)).count())
+    });
+    let mut cedar = cedar;
+    cedar.defragment_values();
+    c.bench_function("exact_match_search after defragment_values", |b| {
+        b.iter(|| keys.iter().filter_map(|k| cedar.exact_match_search(k)).count())
+    });
 }
 
 criterion_group!(benches, criterion_benchmark);
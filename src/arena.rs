@@ -0,0 +1,240 @@
+//! The layout that keeps the values outside of the double array.
+
+use crate::{Cedar, PrefixIter, PrefixPredictIter};
+
+/// `ArenaCedar` keeps the values in a separate arena, and the terminal nodes of the trie only store the id of the
+/// value in the arena, which is assigned in the order the keys are inserted. As the double array only holds the
+/// ids, the values can be of any type `V`, and the queries return references to them. With the default `i32` the
+/// query results are identical to `Cedar`.
+///
+/// It is a wrapper around `Cedar` for the values that do not fit in its `i32`, not a faster layout: reading a
+/// value visits the terminal node for its id first, and then the arena.
+#[derive(Debug, Clone)]
+pub struct ArenaCedar<V = i32> {
+    trie: Cedar,
+    values: Vec<Option<V>>, // the value of every id, `None` once released by `erase`.
+    free: Vec<i32>,         // the ids released by `erase` to be reused.
+}
+
+impl<V> ArenaCedar<V> {
+    /// Initialize the ArenaCedar for further use.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        ArenaCedar {
+            trie: Cedar::new(),
+            values: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    /// Build the trie from the given key value pairs
+    pub fn build(&mut self, key_values: &[(&str, V)])
+    where
+        V: Clone,
+    {
+        for (key, value) in key_values {
+            self.update(key, value.clone());
+        }
+    }
+
+    /// Update the key for the value.
+    pub fn update(&mut self, key: &str, value: V) {
+        if let Some((id, _, _)) = self.trie.exact_match_search(key) {
+            self.values[id as usize] = Some(value);
+            return;
+        }
+
+        let id = match self.free.pop() {
+            Some(id) => {
+                self.values[id as usize] = Some(value);
+                id
+            }
+            None => {
+                self.values.push(Some(value));
+                (self.values.len() - 1) as i32
+            }
+        };
+        self.trie.update(key, id);
+    }
+
+    /// Delete the key from the trie, returning its value if it was there.
+    pub fn erase(&mut self, key: &str) -> Option<V> {
+        let (id, _, _) = self.trie.exact_match_search(key)?;
+        self.trie.erase(key);
+        self.free.push(id);
+        self.values[id as usize].take()
+    }
+
+    /// To check if `key` is in the dictionary, see `Cedar::exact_match_search`.
+    pub fn exact_match_search(&self, key: &str) -> Option<(&V, usize, usize)> {
+        self.trie
+            .exact_match_search(key)
+            .map(|(id, len, from)| (self.value(id), len, from))
+    }
+
+    /// To return an iterator through the common prefix in the dictionary with the `key` passed in, yielding the
+    /// references to their values, see `Cedar::common_prefix_iter`.
+    pub fn common_prefix_iter<'a>(&'a self, key: &'a str) -> ArenaPrefixIter<'a, V> {
+        ArenaPrefixIter {
+            arena: self,
+            inner: self.trie.common_prefix_iter(key),
+        }
+    }
+
+    /// To return the collection of the common prefix in the dictionary with the `key` passed in, see
+    /// `Cedar::common_prefix_search`.
+    pub fn common_prefix_search(&self, key: &str) -> Option<Vec<(&V, usize)>> {
+        self.trie
+            .common_prefix_iter(key)
+            .map(|(id, len)| Some((self.value(id), len)))
+            .collect()
+    }
+
+    /// To return an iterator through the words in the dictionary that has `key` as their prefix, yielding the
+    /// references to their values, see `Cedar::common_prefix_predict_iter`.
+    pub fn common_prefix_predict_iter<'a>(&'a self, key: &'a str) -> ArenaPrefixPredictIter<'a, V> {
+        ArenaPrefixPredictIter {
+            arena: self,
+            inner: self.trie.common_prefix_predict_iter(key),
+        }
+    }
+
+    /// To return the list of words in the dictionary that has `key` as their prefix, see
+    /// `Cedar::common_prefix_predict`.
+    pub fn common_prefix_predict(&self, key: &str) -> Option<Vec<(&V, usize)>> {
+        self.trie
+            .common_prefix_predict_iter(key)
+            .map(|(id, len)| Some((self.value(id), len)))
+            .collect()
+    }
+
+    // the ids in the trie always refer to a value, as `erase` removes the key before releasing its id.
+    fn value(&self, id: i32) -> &V {
+        self.values[id as usize].as_ref().unwrap()
+    }
+}
+
+/// Iterator for `ArenaCedar::common_prefix_iter`
+pub struct ArenaPrefixIter<'a, V> {
+    arena: &'a ArenaCedar<V>,
+    inner: PrefixIter<'a>,
+}
+
+impl<'a, V> Iterator for ArenaPrefixIter<'a, V> {
+    type Item = (&'a V, usize);
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(id, len)| (self.arena.value(id), len))
+    }
+}
+
+/// Iterator for `ArenaCedar::common_prefix_predict_iter`
+pub struct ArenaPrefixPredictIter<'a, V> {
+    arena: &'a ArenaCedar<V>,
+    inner: PrefixPredictIter<'a>,
+}
+
+impl<'a, V> Iterator for ArenaPrefixPredictIter<'a, V> {
+    type Item = (&'a V, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(id, len)| (self.arena.value(id), len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::distributions::Alphanumeric;
+    use rand::{thread_rng, Rng};
+    use std::iter;
+
+    #[test]
+    fn test_same_results_as_inline_values() {
+        let mut rng = thread_rng();
+        let mut dict: Vec<String> = Vec::with_capacity(1000);
+        for _ in 0..1000 {
+            let len = rng.gen_range(1..10);
+            let chars: Vec<u8> = iter::repeat(()).map(|()| rng.sample(Alphanumeric)).take(len).collect();
+            dict.push(String::from_utf8(chars).unwrap());
+        }
+
+        let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (s.as_ref(), k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+        let mut arena = ArenaCedar::new();
+        arena.build(&key_values);
+
+        for s in dict.iter().step_by(3) {
+            cedar.erase(s);
+            arena.erase(s);
+        }
+        cedar.update("reused", 7);
+        arena.update("reused", 7);
+
+        let copied = |result: Option<Vec<(&i32, usize)>>| result.map(|v| v.iter().map(|x| (*x.0, x.1)).collect());
+        for s in dict.iter().chain(iter::once(&String::from("reused"))) {
+            assert_eq!(
+                cedar.exact_match_search(s).map(|x| (x.0, x.1)),
+                arena.exact_match_search(s).map(|x| (*x.0, x.1))
+            );
+            assert_eq!(cedar.common_prefix_search(s), copied(arena.common_prefix_search(s)));
+            assert_eq!(cedar.common_prefix_predict(s), copied(arena.common_prefix_predict(s)));
+            assert!(cedar
+                .common_prefix_iter(s)
+                .eq(arena.common_prefix_iter(s).map(|x| (*x.0, x.1))));
+            assert!(cedar
+                .common_prefix_predict_iter(s)
+                .eq(arena.common_prefix_predict_iter(s).map(|x| (*x.0, x.1))));
+        }
+    }
+
+    #[test]
+    fn test_generic_values() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct Entry {
+            name: String,
+            freq: u64,
+        }
+
+        let entry = |name: &str, freq| Entry {
+            name: name.to_string(),
+            freq,
+        };
+        let mut arena: ArenaCedar<Entry> = ArenaCedar::new();
+        arena.build(&[("a", entry("first", 1)), ("ab", entry("second", u64::MAX))]);
+        arena.update("中华", entry("third", 3));
+
+        assert_eq!(
+            arena.exact_match_search("ab").map(|x| x.0),
+            Some(&entry("second", u64::MAX))
+        );
+        let names: Vec<&str> = arena
+            .common_prefix_search("abc")
+            .unwrap()
+            .iter()
+            .map(|x| x.0.name.as_ref())
+            .collect();
+        assert_eq!(names, vec!["first", "second"]);
+        let freqs: Vec<(u64, usize)> = arena.common_prefix_iter("abc").map(|(e, len)| (e.freq, len)).collect();
+        assert_eq!(freqs, vec![(1, 0), (u64::MAX, 1)]);
+        let mut predicted: Vec<(&str, usize)> = arena
+            .common_prefix_predict_iter("a")
+            .map(|(e, len)| (e.name.as_ref(), len))
+            .collect();
+        predicted.sort_unstable();
+        assert_eq!(predicted, vec![("first", 0), ("second", 1)]);
+        assert_eq!(arena.common_prefix_predict_iter("x").count(), 0);
+
+        // the erased value is moved out, and its id is reused.
+        assert_eq!(arena.erase("a"), Some(entry("first", 1)));
+        assert_eq!(arena.erase("a"), None);
+        arena.update("b", entry("fourth", 4));
+        assert_eq!(arena.values.len(), 3);
+        assert_eq!(arena.common_prefix_predict("").unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,443 @@
+//! The trie whose double array lives in a memory-mapped file, for the dictionaries larger than RAM.
+
+use crate::{invalid_data, Cedar, Node, Nodes, CEDAR_FLAG_REDUCED, CEDAR_FORMAT_VERSION};
+use std::convert::{TryFrom, TryInto};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::{mem, ptr, slice};
+
+// the header of the mapped file, the nodes follow it and the rest of the trie is written after the nodes by
+// `DiskCedar::flush`.
+const DISK_MAGIC: &[u8; 8] = b"CEDARMM\0";
+const HEADER_SIZE: usize = 64;
+
+// MappedNodes is the node array mapped from the file, right after the header.
+pub(crate) struct MappedNodes {
+    file: File,
+    ptr: *mut u8, // the start of the mapping, i.e. the header.
+    len: usize,   // the number of nodes.
+}
+
+// the mapping is owned by `MappedNodes` alone like the buffer of a `Vec`.
+unsafe impl Send for MappedNodes {}
+unsafe impl Sync for MappedNodes {}
+
+impl MappedNodes {
+    // To map the first `len` nodes of the file, growing it if it is shorter.
+    fn new(file: File, len: usize) -> io::Result<Self> {
+        let map_len = HEADER_SIZE + len * mem::size_of::<Node>();
+        if file.metadata()?.len() < map_len as u64 {
+            file.set_len(map_len as u64)?;
+        }
+
+        let ptr = map(&file, map_len)?;
+        Ok(MappedNodes { file, ptr, len })
+    }
+
+    fn map_len(&self) -> usize {
+        HEADER_SIZE + self.len * mem::size_of::<Node>()
+    }
+
+    fn header_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr, HEADER_SIZE) }
+    }
+
+    // To resize the file and the mapping to `len` nodes, the new nodes are default-initialized. The file is grown
+    // before the new mapping and shrunk after it, so the mapping never reaches past the end of the file, and the
+    // old mapping is only released once the new one is there, so a failure leaves the nodes as they were.
+    pub(crate) fn resize(&mut self, len: usize) -> io::Result<()> {
+        let old_len = self.len.min(len);
+        let map_len = len
+            .checked_mul(mem::size_of::<Node>())
+            .and_then(|n| n.checked_add(HEADER_SIZE))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::OutOfMemory, "too many nodes to map"))?;
+
+        if len > self.len {
+            self.file.set_len(map_len as u64)?;
+        }
+        let ptr = map(&self.file, map_len)?;
+        unsafe { libc::munmap(self.ptr as *mut libc::c_void, self.map_len()) };
+        self.ptr = ptr;
+        let shrunk = len < self.len;
+        self.len = len;
+        if shrunk {
+            self.file.set_len(map_len as u64)?;
+        }
+
+        // the region may hold the rest of the trie written by the last flush.
+        for n in &mut self[old_len..] {
+            *n = Node::default();
+        }
+
+        Ok(())
+    }
+
+    // To write the dirty pages of the mapping back to the file.
+    fn sync(&self) -> io::Result<()> {
+        if unsafe { libc::msync(self.ptr as *mut libc::c_void, self.map_len(), libc::MS_SYNC) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+}
+
+impl Deref for MappedNodes {
+    type Target = [Node];
+
+    #[inline]
+    fn deref(&self) -> &[Node] {
+        unsafe { slice::from_raw_parts(self.ptr.add(HEADER_SIZE) as *const Node, self.len) }
+    }
+}
+
+impl DerefMut for MappedNodes {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [Node] {
+        unsafe { slice::from_raw_parts_mut(self.ptr.add(HEADER_SIZE) as *mut Node, self.len) }
+    }
+}
+
+impl Drop for MappedNodes {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr as *mut libc::c_void, self.map_len()) };
+    }
+}
+
+fn map(file: &File, len: usize) -> io::Result<*mut u8> {
+    let ptr = unsafe {
+        libc::mmap(
+            ptr::null_mut(),
+            len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_SHARED,
+            file.as_raw_fd(),
+            0,
+        )
+    };
+
+    if ptr == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(ptr as *mut u8)
+}
+
+/// `DiskCedar` keeps the double array in a memory-mapped file that grows on disk, so that a dictionary larger than
+/// RAM can be built and queried. Only the node array is mapped, which is the bulk of the trie, and the rest is
+/// kept in memory and written after the nodes by `flush`.
+///
+/// The file is only consistent after `flush`, which is also called on drop. The nodes are stored in the native
+/// byte order, so the file is not portable between architectures nor between the layouts with and without the
+/// `reduced-trie` feature.
+///
+/// `DiskCedar` owns the file and its mapping, so it can be moved around like any other value without borrowing
+/// the path, and it keeps working after the file is removed from the directory.
+pub struct DiskCedar {
+    trie: Cedar,
+}
+
+impl DiskCedar {
+    /// To create an empty trie backed by the file at `path`, the file is truncated if it exists.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+
+        let mut trie = Cedar::new();
+        let mut nodes = MappedNodes::new(file, trie.capacity)?;
+        nodes.clone_from_slice(&trie.array);
+        trie.array = Nodes::Mapped(nodes);
+
+        let mut cedar = DiskCedar { trie };
+        cedar.flush()?;
+        Ok(cedar)
+    }
+
+    /// To open the trie flushed to the file at `path`, it can be queried and updated further.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+
+        let mut header = [0u8; HEADER_SIZE];
+        file.read_exact_at(&mut header, 0)?;
+
+        if &header[0..8] != DISK_MAGIC {
+            return Err(invalid_data("not a mapped cedar"));
+        }
+
+        let version = u32::from_le_bytes(header[8..12].try_into().unwrap());
+        if version != CEDAR_FORMAT_VERSION {
+            return Err(invalid_data("unsupported format version"));
+        }
+
+        let flags = u32::from_le_bytes(header[12..16].try_into().unwrap());
+        if (flags & CEDAR_FLAG_REDUCED != 0) != cfg!(feature = "reduced-trie") {
+            return Err(invalid_data("mismatched reduced-trie layout"));
+        }
+
+        let size = u64::from_le_bytes(header[16..24].try_into().unwrap());
+        let capacity = u64::from_le_bytes(header[24..32].try_into().unwrap());
+        if size == 0 || size & 0xff != 0 || capacity < size || capacity & 0xff != 0 {
+            return Err(invalid_data("invalid size"));
+        }
+
+        // both ends are checked against the file before anything is allocated for them.
+        let file_len = file.metadata()?.len();
+        let infos_offset = capacity
+            .checked_mul(mem::size_of::<Node>() as u64)
+            .and_then(|n| n.checked_add(HEADER_SIZE as u64));
+        let infos_len = usize::try_from(size).ok().and_then(Cedar::encoded_infos_len);
+        let (infos_offset, infos_len) = match (infos_offset, infos_len) {
+            (Some(offset), Some(len)) if offset.checked_add(len as u64).is_some_and(|end| end <= file_len) => {
+                (offset, len)
+            }
+            _ => return Err(invalid_data("the file was not flushed")),
+        };
+        let (size, capacity) = (size as usize, capacity as usize);
+
+        let mut buf = vec![0u8; infos_len];
+        file.read_exact_at(&mut buf, infos_offset)?;
+        let (mut n_infos, mut blocks, reject) = Cedar::decode_infos(&buf, size);
+        n_infos.resize(capacity, Default::default());
+        blocks.resize(capacity >> 8, crate::Block::new());
+
+        let mut trie = Cedar {
+            array: Nodes::Mapped(MappedNodes::new(file, capacity)?),
+            n_infos,
+            blocks,
+            reject,
+            blocks_head_full: i32::from_le_bytes(header[32..36].try_into().unwrap()),
+            blocks_head_closed: i32::from_le_bytes(header[36..40].try_into().unwrap()),
+            blocks_head_open: i32::from_le_bytes(header[40..44].try_into().unwrap()),
+            capacity,
+            size,
+            ordered: u32::from_le_bytes(header[48..52].try_into().unwrap()) != 0,
+            max_trial: i32::from_le_bytes(header[44..48].try_into().unwrap()),
+            terminal: header[52],
+            num_keys: 0,
+            automaton: Default::default(),
+        };
+        trie.check_bounds()?;
+        trie.num_keys = trie.count_keys();
+
+        Ok(DiskCedar { trie })
+    }
+
+    /// To write the header and the part of the trie kept in memory to the file, and sync the mapped nodes.
+    pub fn flush(&mut self) -> io::Result<()> {
+        let flags = if cfg!(feature = "reduced-trie") {
+            CEDAR_FLAG_REDUCED
+        } else {
+            0
+        };
+
+        let mut buf = Vec::with_capacity(Cedar::encoded_infos_len(self.trie.size).unwrap());
+        self.trie.encode_infos(&mut buf);
+
+        let trie = &mut self.trie;
+        let nodes = match &mut trie.array {
+            Nodes::Mapped(nodes) => nodes,
+            Nodes::Heap(_) => unreachable!(),
+        };
+        nodes
+            .file
+            .write_all_at(&buf, (HEADER_SIZE + trie.capacity * mem::size_of::<Node>()) as u64)?;
+
+        let header = nodes.header_mut();
+        header[0..8].copy_from_slice(DISK_MAGIC);
+        header[8..12].copy_from_slice(&CEDAR_FORMAT_VERSION.to_le_bytes());
+        header[12..16].copy_from_slice(&flags.to_le_bytes());
+        header[16..24].copy_from_slice(&(trie.size as u64).to_le_bytes());
+        header[24..32].copy_from_slice(&(trie.capacity as u64).to_le_bytes());
+        header[32..36].copy_from_slice(&trie.blocks_head_full.to_le_bytes());
+        header[36..40].copy_from_slice(&trie.blocks_head_closed.to_le_bytes());
+        header[40..44].copy_from_slice(&trie.blocks_head_open.to_le_bytes());
+        header[44..48].copy_from_slice(&trie.max_trial.to_le_bytes());
+        header[48..52].copy_from_slice(&(trie.ordered as u32).to_le_bytes());
+        header[52] = trie.terminal;
+
+        nodes.sync()
+    }
+
+    /// Update the key for the value, see `Cedar::update`. It fails if the file cannot be grown, for example when
+    /// the disk is full, and then the trie is left as it was. An empty key or one containing the terminal label
+    /// fails with `io::ErrorKind::InvalidInput` as well.
+    pub fn update(&mut self, key: &str, value: i32) -> io::Result<()> {
+        self.trie.update_reserved(key.as_bytes(), value).map(|_| ())
+    }
+
+    /// Delete the key from the trie. It returns `io::Result` like `update`, though freeing the nodes never grows
+    /// the file.
+    pub fn erase(&mut self, key: &str) -> io::Result<()> {
+        self.trie.erase(key);
+        Ok(())
+    }
+
+    /// To check if `key` is in the dictionary, see `Cedar::exact_match_search`.
+    pub fn exact_match_search(&self, key: &str) -> Option<(i32, usize, usize)> {
+        self.trie.exact_match_search(key)
+    }
+
+    /// To return the collection of the common prefix in the dictionary with the `key` passed in, see
+    /// `Cedar::common_prefix_search`.
+    pub fn common_prefix_search(&self, key: &str) -> Option<Vec<(i32, usize)>> {
+        self.trie.common_prefix_search(key)
+    }
+
+    /// To return the list of words in the dictionary that has `key` as their prefix, see
+    /// `Cedar::common_prefix_predict`.
+    pub fn common_prefix_predict(&self, key: &str) -> Option<Vec<(i32, usize)>> {
+        self.trie.common_prefix_predict(key)
+    }
+}
+
+impl Drop for DiskCedar {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::distributions::Alphanumeric;
+    use rand::{thread_rng, Rng};
+    use std::{env, fs, iter, process};
+
+    #[test]
+    fn test_build_and_reopen() {
+        let path = env::temp_dir().join(format!("cedarwood-disk-{}.bin", process::id()));
+
+        let mut rng = thread_rng();
+        let mut dict: Vec<String> = Vec::with_capacity(5000);
+        for _ in 0..5000 {
+            let len = rng.gen_range(1..20);
+            let chars: Vec<u8> = iter::repeat(()).map(|()| rng.sample(Alphanumeric)).take(len).collect();
+            dict.push(String::from_utf8(chars).unwrap());
+        }
+        let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (s.as_ref(), k as i32)).collect();
+        let mut expected = Cedar::new();
+        expected.build(&key_values);
+
+        {
+            let mut cedar = DiskCedar::create(&path).unwrap();
+            for (key, value) in key_values.iter() {
+                cedar.update(key, *value).unwrap();
+            }
+            for s in dict.iter().step_by(7) {
+                cedar.erase(s).unwrap();
+                expected.erase(s);
+            }
+
+            for s in dict.iter() {
+                assert_eq!(cedar.exact_match_search(s), expected.exact_match_search(s));
+            }
+        }
+
+        let mut cedar = DiskCedar::open(&path).unwrap();
+        for s in dict.iter() {
+            assert_eq!(cedar.exact_match_search(s), expected.exact_match_search(s));
+            assert_eq!(cedar.common_prefix_search(s), expected.common_prefix_search(s));
+            assert_eq!(cedar.common_prefix_predict(s), expected.common_prefix_predict(s));
+        }
+
+        cedar.update("reopened", 1).unwrap();
+        assert_eq!(cedar.exact_match_search("reopened").map(|x| x.0), Some(1));
+        assert_eq!(cedar.update("a\0b", 1).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+        drop(cedar);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_update_fails_to_grow() {
+        let path = env::temp_dir().join(format!("cedarwood-full-{}.bin", process::id()));
+        let keys: Vec<String> = (0..1000).map(|i| format!("{:x}", i * 7919)).collect();
+        let mut cedar = DiskCedar::create(&path).unwrap();
+        for s in keys[..100].iter() {
+            cedar.update(s, 0).unwrap();
+        }
+
+        // the file cannot be resized through a read-only handle, like on a full disk.
+        match &mut cedar.trie.array {
+            Nodes::Mapped(nodes) => nodes.file = File::open(&path).unwrap(),
+            Nodes::Heap(_) => unreachable!(),
+        }
+
+        let failed = 100 + keys[100..].iter().position(|s| cedar.update(s, 0).is_err()).unwrap();
+        assert_eq!(cedar.trie.validate(), Ok(()));
+        assert!(cedar.exact_match_search(&keys[failed]).is_none());
+        for s in keys[..failed].iter() {
+            assert_eq!(cedar.exact_match_search(s).map(|x| x.0), Some(0));
+        }
+
+        drop(cedar);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_open_corrupted() {
+        let path = env::temp_dir().join(format!("cedarwood-corrupted-{}.bin", process::id()));
+        let mut cedar = DiskCedar::create(&path).unwrap();
+        for (k, s) in ["a", "ab", "中华"].iter().enumerate() {
+            cedar.update(s, k as i32).unwrap();
+        }
+        drop(cedar);
+        let flushed = fs::read(&path).unwrap();
+
+        // the capacity wraps the offset of the infos around, the size is far beyond the file, and the base of the
+        // root points out of the nodes.
+        #[cfg(feature = "reduced-trie")]
+        let root_base = (-0x7fff_0000i32).to_le_bytes();
+        #[cfg(not(feature = "reduced-trie"))]
+        let root_base = 0x7fff_0000i32.to_le_bytes();
+        let corruptions: [(usize, &[u8]); 4] = [
+            (24, &(1u64 << 61).to_le_bytes()),
+            (24, &(u64::MAX << 8).to_le_bytes()),
+            (16, &(1u64 << 44).to_le_bytes()),
+            (HEADER_SIZE, &root_base),
+        ];
+        for &(offset, bytes) in corruptions.iter() {
+            let mut corrupted = flushed.clone();
+            corrupted[offset..offset + bytes.len()].copy_from_slice(bytes);
+            fs::write(&path, &corrupted).unwrap();
+            assert!(DiskCedar::open(&path).is_err());
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_owned_after_removal() {
+        let key_values = [("a", 0), ("ab", 1), ("中华", 2)];
+        let cedar = {
+            let path = env::temp_dir().join(format!("cedarwood-owned-{}.bin", process::id()));
+            let mut cedar = DiskCedar::create(&path).unwrap();
+            for (key, value) in key_values.iter() {
+                cedar.update(key, *value).unwrap();
+            }
+            drop(cedar);
+
+            let cedar = DiskCedar::open(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+            cedar
+        };
+
+        let cedar = std::thread::spawn(move || {
+            for (key, value) in key_values.iter() {
+                assert_eq!(cedar.exact_match_search(key).map(|x| x.0), Some(*value));
+            }
+            cedar
+        })
+        .join()
+        .unwrap();
+        assert_eq!(cedar.common_prefix_search("abc").unwrap(), vec![(0, 0), (1, 1)]);
+    }
+}
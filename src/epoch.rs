@@ -0,0 +1,108 @@
+//! The layout that stores an epoch for every key, for snapshot reads of a dictionary being updated.
+
+use crate::Cedar;
+
+/// `EpochCedar` stamps every key with the epoch of its last update, stored next to the value in two side vectors
+/// indexed by a terminal id as only one i32 fits in a terminal node. The epoch increases by one with each `update`,
+/// so a reader holding a snapshot epoch can ignore the keys updated after it.
+#[derive(Debug, Clone)]
+pub struct EpochCedar {
+    trie: Cedar,
+    values: Vec<i32>,
+    epochs: Vec<u64>,
+    free: Vec<i32>, // the terminal ids released by `erase` to be reused.
+    epoch: u64,     // the epoch of the last update.
+}
+
+impl EpochCedar {
+    /// Initialize the EpochCedar for further use.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        EpochCedar {
+            trie: Cedar::new(),
+            values: Vec::new(),
+            epochs: Vec::new(),
+            free: Vec::new(),
+            epoch: 0,
+        }
+    }
+
+    /// Update the key for the value, stamping it with the next epoch, which is returned.
+    pub fn update(&mut self, key: &str, value: i32) -> u64 {
+        self.epoch += 1;
+
+        if let Some((t, _, _)) = self.trie.exact_match_search(key) {
+            self.values[t as usize] = value;
+            self.epochs[t as usize] = self.epoch;
+            return self.epoch;
+        }
+
+        let t = match self.free.pop() {
+            Some(t) => {
+                self.values[t as usize] = value;
+                self.epochs[t as usize] = self.epoch;
+                t
+            }
+            None => {
+                self.values.push(value);
+                self.epochs.push(self.epoch);
+                (self.values.len() - 1) as i32
+            }
+        };
+        self.trie.update(key, t);
+
+        self.epoch
+    }
+
+    /// Delete the key from the trie.
+    pub fn erase(&mut self, key: &str) {
+        if let Some((t, _, _)) = self.trie.exact_match_search(key) {
+            self.trie.erase(key);
+            self.free.push(t);
+        }
+    }
+
+    /// To return the epoch of the last update, 0 if there has been none.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// To return the value of `key` and the epoch it was last updated at, if it is in the dictionary.
+    pub fn value_and_epoch(&self, key: &str) -> Option<(i32, u64)> {
+        self.trie
+            .exact_match_search(key)
+            .map(|(t, _, _)| (self.values[t as usize], self.epochs[t as usize]))
+    }
+
+    /// To return the value of `key` if it is in the dictionary and was last updated at or before `epoch`.
+    pub fn value_at_epoch(&self, key: &str, epoch: u64) -> Option<i32> {
+        self.value_and_epoch(key).filter(|x| x.1 <= epoch).map(|x| x.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_and_epoch() {
+        let mut cedar = EpochCedar::new();
+        assert_eq!(cedar.epoch(), 0);
+
+        assert_eq!(cedar.update("apple", 1), 1);
+        assert_eq!(cedar.update("banana", 2), 2);
+        let snapshot = cedar.epoch();
+        assert_eq!(cedar.update("apple", 3), 3);
+
+        assert_eq!(cedar.value_and_epoch("apple"), Some((3, 3)));
+        assert_eq!(cedar.value_and_epoch("banana"), Some((2, 2)));
+        assert_eq!(cedar.value_at_epoch("apple", snapshot), None);
+        assert_eq!(cedar.value_at_epoch("banana", snapshot), Some(2));
+
+        cedar.erase("banana");
+        assert_eq!(cedar.value_and_epoch("banana"), None);
+        assert_eq!(cedar.update("cherry", 4), 4);
+        assert_eq!(cedar.value_and_epoch("cherry"), Some((4, 4)));
+        assert!(cedar.value_and_epoch("apple").unwrap().1 < cedar.value_and_epoch("cherry").unwrap().1);
+    }
+}
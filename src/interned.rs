@@ -0,0 +1,105 @@
+//! The layout that interns the values into a dictionary, for the keys sharing a small set of values.
+
+use crate::Cedar;
+use std::collections::HashMap;
+
+/// `InternedCedar` stores every distinct value once in a side vector, and the terminal of every key holds the
+/// index of its value there instead. With few distinct values the indices are small, so `write_to_packed` of the
+/// trie stores them in a byte or two however large the values are.
+///
+/// The erased values stay in the dictionary, as another key may still refer to them.
+#[derive(Debug, Clone)]
+pub struct InternedCedar {
+    trie: Cedar,
+    values: Vec<i32>,
+    indices: HashMap<i32, i32>, // the index of every value in `values`.
+}
+
+impl InternedCedar {
+    /// Initialize the InternedCedar for further use.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        InternedCedar {
+            trie: Cedar::new(),
+            values: Vec::new(),
+            indices: HashMap::new(),
+        }
+    }
+
+    /// Build the trie from the given key value pairs
+    pub fn build(&mut self, key_values: &[(&str, i32)]) {
+        for (key, value) in key_values {
+            self.update(key, *value);
+        }
+    }
+
+    /// Update the key for the value, interning the value if it is new.
+    pub fn update(&mut self, key: &str, value: i32) {
+        let values = &mut self.values;
+        let index = *self.indices.entry(value).or_insert_with(|| {
+            values.push(value);
+            (values.len() - 1) as i32
+        });
+        self.trie.update(key, index);
+    }
+
+    /// Delete the key from the trie.
+    pub fn erase(&mut self, key: &str) {
+        self.trie.erase(key);
+    }
+
+    /// To look up `key` like `Cedar::exact_match_search`, with the value resolved through the dictionary.
+    pub fn exact_match_search(&self, key: &str) -> Option<(i32, usize, usize)> {
+        self.trie
+            .exact_match_search(key)
+            .map(|(index, len, to)| (self.values[index as usize], len, to))
+    }
+
+    /// To return the distinct values stored so far, in the order they were first seen.
+    pub fn values(&self) -> &[i32] {
+        &self.values
+    }
+
+    /// To return the trie holding the indices into `values`, for the serialization.
+    pub fn trie(&self) -> &Cedar {
+        &self.trie
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interned_values() {
+        let categories = [1_000_000, 70_000, 1 << 30];
+        let dict: Vec<String> = (0..1000).map(|i| format!("{:x}", i * 7919)).collect();
+        let key_values: Vec<(&str, i32)> = dict
+            .iter()
+            .enumerate()
+            .map(|(k, s)| (s.as_ref(), categories[k % 3]))
+            .collect();
+
+        let mut cedar = InternedCedar::new();
+        cedar.build(&key_values);
+        assert_eq!(cedar.values(), &categories[..]);
+        for (key, value) in key_values.iter() {
+            assert_eq!(cedar.exact_match_search(key).map(|x| x.0), Some(*value));
+        }
+        assert_eq!(cedar.exact_match_search("xyz"), None);
+
+        // the indices fit in a byte, unlike the values themselves.
+        let mut plain = Cedar::new();
+        plain.build(&key_values);
+        let (mut interned, mut raw) = (Vec::new(), Vec::new());
+        cedar.trie().write_to_packed(&mut interned).unwrap();
+        plain.write_to_packed(&mut raw).unwrap();
+        assert!(interned.len() < raw.len());
+
+        cedar.erase(&dict[3]);
+        assert_eq!(cedar.exact_match_search(&dict[3]), None);
+        cedar.update(&dict[4], 70_000);
+        assert_eq!(cedar.exact_match_search(&dict[4]).map(|x| x.0), Some(70_000));
+        assert_eq!(cedar.values().len(), 3);
+    }
+}
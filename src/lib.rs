@@ -61,11 +61,48 @@
 //! assert_eq!(vec![4], result);
 //! ```
 
+mod arena;
+#[cfg(all(feature = "mmap", unix))]
+mod disk;
+mod epoch;
+mod interned;
+mod map;
+mod range;
+mod ranked;
+mod shared;
+mod view;
+
+pub use arena::{ArenaCedar, ArenaPrefixIter, ArenaPrefixPredictIter};
+#[cfg(all(feature = "mmap", unix))]
+pub use disk::DiskCedar;
+pub use epoch::EpochCedar;
+pub use interned::InternedCedar;
+pub use map::{CedarMap, Entry, OccupiedEntry, VacantEntry};
+pub use range::RangeCedar;
+pub use ranked::RankedCedar;
+pub use shared::SharedCedar;
+pub use view::OwnedCedarView;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use smallvec::SmallVec;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::convert::TryInto;
 use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::iter::FromIterator;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
 
 /// NInfo stores the information about the trie
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 struct NInfo {
     sibling: u8, // the index of right sibling, it is 0 if it doesn't have a sibling.
     child: u8,   // the index of the first child
@@ -74,6 +111,8 @@ struct NInfo {
 /// Node contains the array of `base` and `check` as specified in the paper: "An efficient implementation of trie structures"
 /// https://dl.acm.org/citation.cfm?id=146691
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(C)]
 struct Node {
     base_: i32, // if it is a negative value, then it stores the value of previous index that is free.
     check: i32, // if it is a negative value, then it stores the value of next index that is free.
@@ -89,8 +128,99 @@ impl Node {
     }
 }
 
+// Nodes is the storage of the double array, it is a `Vec` unless the trie is backed by a file in `DiskCedar`.
+enum Nodes {
+    Heap(Vec<Node>),
+    #[cfg(all(feature = "mmap", unix))]
+    Mapped(disk::MappedNodes),
+}
+
+impl Nodes {
+    // To return the number of nodes allocated on the heap, the mapped nodes are not counted.
+    fn heap_capacity(&self) -> usize {
+        match self {
+            Nodes::Heap(v) => v.capacity(),
+            #[cfg(all(feature = "mmap", unix))]
+            Nodes::Mapped(_) => 0,
+        }
+    }
+
+    // To grow the storage to `len` nodes, the new nodes are default-initialized. Only the mapped nodes can fail,
+    // when the file cannot be grown.
+    fn resize(&mut self, len: usize) -> io::Result<()> {
+        match self {
+            Nodes::Heap(v) => {
+                v.resize(len, Default::default());
+                Ok(())
+            }
+            #[cfg(all(feature = "mmap", unix))]
+            Nodes::Mapped(m) => m.resize(len),
+        }
+    }
+
+    // To cut the storage down to `len` nodes, releasing the memory or the file space beyond.
+    fn shrink_to(&mut self, len: usize) -> io::Result<()> {
+        match self {
+            Nodes::Heap(v) => {
+                v.truncate(len);
+                v.shrink_to_fit();
+                Ok(())
+            }
+            #[cfg(all(feature = "mmap", unix))]
+            Nodes::Mapped(m) => m.resize(len),
+        }
+    }
+}
+
+impl Deref for Nodes {
+    type Target = [Node];
+
+    #[inline]
+    fn deref(&self) -> &[Node] {
+        match self {
+            Nodes::Heap(v) => v,
+            #[cfg(all(feature = "mmap", unix))]
+            Nodes::Mapped(m) => m,
+        }
+    }
+}
+
+impl DerefMut for Nodes {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [Node] {
+        match self {
+            Nodes::Heap(v) => v,
+            #[cfg(all(feature = "mmap", unix))]
+            Nodes::Mapped(m) => m,
+        }
+    }
+}
+
+// the clone of a mapped trie lives on the heap.
+impl Clone for Nodes {
+    fn clone(&self) -> Self {
+        Nodes::Heap(self.to_vec())
+    }
+}
+
+// the nodes are serialized as a sequence whatever the storage, and always deserialized onto the heap.
+#[cfg(feature = "serde")]
+impl Serialize for Nodes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.deref().serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Nodes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::deserialize(deserializer).map(Nodes::Heap)
+    }
+}
+
 /// Block stores the linked-list pointers and the stats info for blocks.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 struct Block {
     prev: i32,   // previous block's index, 3 bytes width
     next: i32,   // next block's index, 3 bytes width
@@ -121,10 +251,29 @@ enum BlockType {
     Full,   // The block's slots are fully used.
 }
 
+// The Aho-Corasick automaton over the trie for `scan_fast`, indexed by node id. `fail` is the node of the longest
+// proper suffix of the path to the node that is also in the trie, `output` is the nearest node on the failure chain
+// where a key ends, or u32::MAX if there is none, and `depth` is the length of the path.
+#[derive(Debug, Clone)]
+struct Automaton {
+    fail: Vec<u32>,
+    output: Vec<u32>,
+    depth: Vec<u32>,
+}
+
 /// `Cedar` holds all of the information about double array trie.
+///
+/// The node ids returned by e.g. `exact_match_search` and `find_from` are stable across the growth of the array,
+/// as new blocks are only appended. They can still change when an insertion conflicts with the children of an
+/// existing node and relocates them, and when a key is erased.
+///
+/// With the `serde` feature `Cedar` can be serialized whole as well, as an alternative to `write_to` for the
+/// formats of serde. The deserialized trie is trusted like the one of `read_from`, and `validate` checks it. Like
+/// `write_to`, the layouts with and without the `reduced-trie` feature are not interchangeable.
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Cedar {
-    array: Vec<Node>, // storing the `base` and `check` info from the original paper.
+    array: Nodes, // storing the `base` and `check` info from the original paper.
     n_infos: Vec<NInfo>,
     blocks: Vec<Block>,
     reject: Vec<i16>,
@@ -134,7 +283,11 @@ pub struct Cedar {
     capacity: usize,
     size: usize,
     ordered: bool,
-    max_trial: i32, // the parameter for cedar, it could be tuned for more, but the default is 1.
+    max_trial: i32,  // the parameter for cedar, it could be tuned for more, but the default is 1.
+    terminal: u8,    // the byte that never occurs in the keys, it is swapped with the label 0 of the terminal slot.
+    num_keys: usize, // the number of keys holding a value, kept up to date by `update_` and `erase__`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    automaton: OnceLock<Automaton>, // the failure links of `scan_fast`, built on demand and dropped on mutation.
 }
 
 impl fmt::Debug for Cedar {
@@ -143,10 +296,128 @@ impl fmt::Debug for Cedar {
     }
 }
 
+// the pairs are inserted with `update` one by one like in `build`, so a later value of a key replaces the earlier.
+impl<'a> FromIterator<(&'a str, i32)> for Cedar {
+    fn from_iter<I: IntoIterator<Item = (&'a str, i32)>>(iter: I) -> Self {
+        let mut cedar = Cedar::new();
+        cedar.extend(iter);
+        cedar
+    }
+}
+
+impl FromIterator<(String, i32)> for Cedar {
+    fn from_iter<I: IntoIterator<Item = (String, i32)>>(iter: I) -> Self {
+        let mut cedar = Cedar::new();
+        for (key, value) in iter {
+            cedar.update(&key, value);
+        }
+        cedar
+    }
+}
+
+impl<'a> Extend<(&'a str, i32)> for Cedar {
+    fn extend<I: IntoIterator<Item = (&'a str, i32)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.update(key, value);
+        }
+    }
+}
+
+/// Whether the crate is built with the `reduced-trie` feature, which stores the values of the leaves in the leaf
+/// nodes themselves. The serialized tries of the two layouts are not interchangeable.
+pub const REDUCED_TRIE: bool = cfg!(feature = "reduced-trie");
+
 #[allow(dead_code)]
-const CEDAR_VALUE_LIMIT: i32 = std::i32::MAX - 1;
+const CEDAR_VALUE_LIMIT: i32 = i32::MAX - 1;
 const CEDAR_NO_VALUE: i32 = -1;
 
+// the number of builds timed for each candidate of `auto_tune_max_trial`, and the margin of density within which
+// the candidates tie.
+const AUTO_TUNE_RUNS: usize = 5;
+const AUTO_TUNE_DENSITY_TOLERANCE: f64 = 0.01;
+
+// the header of the serialized format, and the bit in `flags` marking the reduced-trie layout.
+const CEDAR_MAGIC: &[u8; 8] = b"CEDARWD\0";
+const CEDAR_FORMAT_VERSION: u32 = 1;
+const CEDAR_FLAG_REDUCED: u32 = 1;
+// the bit in `flags` marking the failure links of `scan_fast` following the infos.
+const CEDAR_FLAG_AUTOMATON: u32 = 2;
+// the bits in `flags` marking the nodes packed by `write_to_packed` with the values narrowed to u8 or u16.
+const CEDAR_FLAG_VALUES_U8: u32 = 4;
+const CEDAR_FLAG_VALUES_U16: u32 = 8;
+
+// the op codes of the records in the operation log.
+const LOG_OP_UPDATE: u8 = 0;
+const LOG_OP_ERASE: u8 = 1;
+
+// the number of entries sorted in memory by `build_external_sorted` before they are spilled to a run file.
+const EXTERNAL_SORT_RUN_LEN: usize = 1 << 20;
+
+// the list of the child labels collected by `resolve`, inlined up to 32 labels and spilled to the heap beyond.
+type ChildLabels = SmallVec<[u8; 32]>;
+
+/// A class of bytes to be matched at one position of the pattern in `pattern_search`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ByteClass {
+    Exact(u8),    // only the given byte.
+    Any,          // any byte.
+    Set(Vec<u8>), // any of the given bytes.
+}
+
+impl ByteClass {
+    fn matches(&self, label: u8) -> bool {
+        match self {
+            ByteClass::Exact(b) => *b == label,
+            ByteClass::Any => true,
+            ByteClass::Set(set) => set.contains(&label),
+        }
+    }
+}
+
+/// The key found by `exact_match_search_meta`, with its length in both bytes and chars.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match {
+    pub value: i32,
+    pub byte_len: usize,
+    pub char_len: usize,
+    pub node_id: usize, // the node where the key ends, like the third field of `exact_match_search`.
+}
+
+/// The summary of the size of a trie returned by `stats`, for the capacity planning.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CedarStats {
+    pub num_keys: usize,
+    pub num_nodes: usize, // the nodes in use excluding the root, like `num_nodes`.
+    pub capacity: usize,  // the nodes allocated.
+    pub size: usize,      // the nodes in the blocks handed out so far, a multiple of 256.
+    pub num_blocks: usize,
+    pub estimated_bytes: usize, // the bytes allocated on the heap by the backing vectors, like `heap_size`.
+}
+
+/// The error of the fallible operations. The queries check every transition so that a trie loaded from untrusted
+/// input cannot make them panic, and the long builds can be called off.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CedarError {
+    /// The double array refers to a node outside of the region in use, or its links do not form a tree.
+    Corrupted,
+    /// The build was cancelled before all of the keys were inserted.
+    Cancelled,
+    /// The key is empty or contains the terminal label, so it cannot be inserted.
+    InvalidKey,
+}
+
+impl fmt::Display for CedarError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CedarError::Corrupted => write!(f, "the double array is corrupted"),
+            CedarError::Cancelled => write!(f, "the build is cancelled"),
+            CedarError::InvalidKey => write!(f, "the key is empty or contains the terminal label"),
+        }
+    }
+}
+
+impl std::error::Error for CedarError {}
+
 /// Iterator for `common_prefix_search`
 #[derive(Clone)]
 pub struct PrefixIter<'a> {
@@ -183,6 +454,173 @@ impl<'a> Iterator for PrefixIter<'a> {
     }
 }
 
+/// Iterator for `common_prefix_scan`
+#[derive(Clone)]
+pub struct ScanIter<'a> {
+    cedar: &'a Cedar,
+    text: &'a [u8],
+    base: usize, // the position where the current `inner` starts.
+    inner: PrefixIter<'a>,
+}
+
+impl<'a> Iterator for ScanIter<'a> {
+    type Item = (i32, usize, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((value, i)) = self.inner.next() {
+                return Some((value, self.base, self.base + i + 1));
+            }
+
+            // no more matches starting at `base`, so we move on to the next byte.
+            self.base += 1;
+            if self.base >= self.text.len() {
+                return None;
+            }
+
+            self.inner = PrefixIter {
+                cedar: self.cedar,
+                key: &self.text[self.base..],
+                from: 0,
+                i: 0,
+            };
+        }
+    }
+}
+
+/// Iterator for `common_prefix_scan_annotated`
+#[derive(Clone)]
+pub struct AnnotatedScanIter<'a> {
+    inner: std::iter::Peekable<ScanIter<'a>>,
+}
+
+impl<'a> Iterator for AnnotatedScanIter<'a> {
+    type Item = (i32, usize, usize, bool);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (value, start, end) = self.inner.next()?;
+
+        // the matches from the same start come out from the shortest to the longest.
+        let has_longer = matches!(self.inner.peek(), Some(&(_, s, _)) if s == start);
+        Some((value, start, end, has_longer))
+    }
+}
+
+/// Iterator for `common_prefix_scan_longest`
+#[derive(Clone)]
+pub struct LongestScanIter<'a> {
+    cedar: &'a Cedar,
+    text: &'a [u8],
+    base: usize, // the position where the next match is looked for.
+}
+
+impl<'a> Iterator for LongestScanIter<'a> {
+    type Item = (i32, usize, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.base < self.text.len() {
+            let start = self.base;
+            match self.cedar.longest_prefix_match_(&self.text[start..]) {
+                Some((value, len)) => {
+                    // the next match starts after this one, so the matches never overlap.
+                    self.base += len;
+                    return Some((value, start, start + len));
+                }
+                None => self.base += 1,
+            }
+        }
+
+        None
+    }
+}
+
+/// Iterator for `try_common_prefix_iter`
+#[derive(Clone)]
+pub struct TryPrefixIter<'a> {
+    cedar: &'a Cedar,
+    key: &'a [u8],
+    from: usize,
+    i: usize,
+}
+
+impl<'a> Iterator for TryPrefixIter<'a> {
+    type Item = Result<(i32, usize), CedarError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.i < self.key.len() {
+            match self.cedar.try_find(&self.key[self.i..=self.i], &mut self.from) {
+                Ok(Some(value)) => {
+                    self.i += 1;
+                    if value != CEDAR_NO_VALUE {
+                        return Some(Ok((value, self.i - 1)));
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    // nothing is yielded after the corruption.
+                    self.i = self.key.len();
+                    return Some(Err(e));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// Iterator for `try_common_prefix_predict_iter`
+#[derive(Clone)]
+pub struct TryPrefixPredictIter<'a> {
+    cedar: &'a Cedar,
+    key: &'a [u8],
+    from: usize,
+    p: usize,
+    root: usize,
+    started: bool,
+    done: bool,
+}
+
+impl<'a> Iterator for TryPrefixPredictIter<'a> {
+    type Item = Result<(i32, usize), CedarError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        // every call moves the cursor to the next leaf first, starting from the first leaf below the prefix.
+        let leaf = if self.started {
+            self.cedar.try_next(self.from, self.p, self.root)
+        } else {
+            self.started = true;
+            match self.cedar.try_find(self.key, &mut self.from) {
+                Ok(Some(_)) => {
+                    self.root = self.from;
+                    self.cedar.try_begin(self.from, self.p)
+                }
+                Ok(None) => Ok((None, self.from, self.p)),
+                Err(e) => Err(e),
+            }
+        };
+
+        match leaf {
+            Ok((Some(value), from, p)) => {
+                self.from = from;
+                self.p = p;
+                Some(Ok((value, p)))
+            }
+            Ok((None, _, _)) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
 /// Iterator for `common_prefix_predict`
 #[derive(Clone)]
 pub struct PrefixPredictIter<'a> {
@@ -192,6 +630,7 @@ pub struct PrefixPredictIter<'a> {
     p: usize,
     root: usize,
     value: Option<i32>,
+    started: bool,
 }
 
 impl<'a> PrefixPredictIter<'a> {
@@ -216,7 +655,9 @@ impl<'a> Iterator for PrefixPredictIter<'a> {
     type Item = (i32, usize);
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.from == 0 && self.p == 0 {
+        if !self.started {
+            self.started = true;
+
             // To locate the prefix's position first, if it doesn't exist then that means we
             // don't have do anything. `from` would serve as the cursor.
             if self.cedar.find(self.key, &mut self.from).is_some() {
@@ -237,119 +678,671 @@ impl<'a> Iterator for PrefixPredictIter<'a> {
     }
 }
 
-#[allow(clippy::cast_lossless)]
-impl Cedar {
-    /// Initialize the Cedar for further use.
-    #[allow(clippy::new_without_default)]
-    pub fn new() -> Self {
-        let mut array: Vec<Node> = Vec::with_capacity(256);
-        let n_infos: Vec<NInfo> = (0..256).map(|_| Default::default()).collect();
-        let mut blocks: Vec<Block> = vec![Block::new(); 1];
-        let reject: Vec<i16> = (0..=256).map(|i| i + 1).collect();
-
-        #[cfg(feature = "reduced-trie")]
-        array.push(Node { base_: -1, check: -1 });
-        #[cfg(not(feature = "reduced-trie"))]
-        array.push(Node { base_: 0, check: -1 });
+/// Iterator for `ranked_completions`
+#[derive(Clone)]
+pub struct RankedCompletionsIter<'a> {
+    completions: PrefixPredictIter<'a>,
+    rank: usize,
+}
 
-        for i in 1..256 {
-            // make `base_` point to the previous element, and make `check` point to the next element
-            array.push(Node {
-                base_: -(i - 1),
-                check: -(i + 1),
-            })
-        }
+impl<'a> Iterator for RankedCompletionsIter<'a> {
+    type Item = (usize, i32, usize);
 
-        // make them link as a cyclic doubly-linked list
-        array[1].base_ = -255;
-        array[255].check = -1;
+    fn next(&mut self) -> Option<Self::Item> {
+        let (value, len) = self.completions.next()?;
+        self.rank += 1;
+        Some((self.rank - 1, value, len))
+    }
+}
 
-        blocks[0].e_head = 1;
+/// Cursor for `predict_session`, it remembers the node reached by the bytes pushed so far so that the
+/// completions can be queried after every keystroke without walking the prefix again.
+#[derive(Clone)]
+pub struct PredictSession<'a> {
+    cedar: &'a Cedar,
+    from: Option<usize>, // `None` once the prefix has left the trie.
+}
 
-        Cedar {
-            array,
-            n_infos,
-            blocks,
-            reject,
-            blocks_head_full: 0,
-            blocks_head_closed: 0,
-            blocks_head_open: 0,
-            capacity: 256,
-            size: 256,
-            ordered: true,
-            max_trial: 1,
-        }
+impl<'a> PredictSession<'a> {
+    /// To extend the prefix by `label`, returning whether the prefix still exists in the trie.
+    pub fn push_byte(&mut self, label: u8) -> bool {
+        self.from = self.from.and_then(|from| self.cedar.step(from, label));
+        self.from.is_some()
     }
 
-    /// Build the double array trie from the given key value pairs
-    #[allow(dead_code)]
-    pub fn build(&mut self, key_values: &[(&str, i32)]) {
-        for (key, value) in key_values {
-            self.update(key, *value);
+    /// To return up to `limit` completions of the current prefix, as the value and the length of the remaining
+    /// part of the key like `common_prefix_predict`.
+    pub fn completions(&self, limit: usize) -> Vec<(i32, usize)> {
+        match self.from {
+            Some(root) => {
+                let (value, from, p) = self.cedar.begin(root, 0);
+                PrefixPredictIter {
+                    cedar: self.cedar,
+                    key: &[],
+                    from,
+                    p,
+                    root,
+                    value,
+                    started: true,
+                }
+                .take(limit)
+                .collect()
+            }
+            None => Vec::new(),
         }
     }
+}
 
-    /// Update the key for the value, it is public interface that works on &str
-    pub fn update(&mut self, key: &str, value: i32) {
-        let from = 0;
-        let pos = 0;
-        self.update_(key.as_bytes(), value, from, pos);
-    }
+/// Iterator for `keys`
+#[derive(Clone)]
+pub struct KeysIter<'a> {
+    cedar: &'a Cedar,
+    stack: Vec<(usize, usize, u8)>, // the nodes to visit, with their depth and the label leading to them.
+    key: Vec<u8>,
+}
 
-    // Update the key for the value, it is internal interface that works on &[u8] and cursor.
-    fn update_(&mut self, key: &[u8], value: i32, mut from: usize, mut pos: usize) -> i32 {
-        if from == 0 && key.is_empty() {
-            panic!("failed to insert zero-length key");
-        }
+impl<'a> KeysIter<'a> {
+    // To move to the next key, returning the node id of the key as well.
+    fn next_entry(&mut self) -> Option<(Vec<u8>, i32, usize)> {
+        while let Some((from, depth, label)) = self.stack.pop() {
+            // the key buffer holds the path to the parent, so we replace the tail with the label we took.
+            self.key.truncate(depth.saturating_sub(1));
+            if depth > 0 {
+                self.key.push(label);
+            }
 
-        while pos < key.len() {
-            #[cfg(feature = "reduced-trie")]
-            {
-                let val_ = self.array[from].base_;
-                if val_ >= 0 && val_ != CEDAR_VALUE_LIMIT {
-                    let to = self.follow(from, 0);
-                    self.array[to as usize].base_ = val_;
+            // push the children in reverse so that the smallest label is visited first.
+            let start = self.stack.len();
+            for (c, to) in self.cedar.children(from) {
+                if c != 0 {
+                    self.stack.push((to, depth + 1, self.cedar.label(c)));
                 }
             }
+            self.stack[start..].reverse();
 
-            from = self.follow(from, key[pos]) as usize;
-            pos += 1;
+            if let Some(value) = self.cedar.terminal_value(from) {
+                return Some((self.key.clone(), value, from));
+            }
         }
 
-        #[cfg(feature = "reduced-trie")]
-        let to = if self.array[from].base_ >= 0 {
-            from as i32
-        } else {
-            self.follow(from, 0)
-        };
+        None
+    }
+}
 
-        #[cfg(feature = "reduced-trie")]
-        {
-            if self.array[to as usize].base_ == CEDAR_VALUE_LIMIT {
-                self.array[to as usize].base_ = 0;
+impl<'a> Iterator for KeysIter<'a> {
+    type Item = (Vec<u8>, i32);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_entry().map(|(key, value, _)| (key, value))
+    }
+}
+
+/// Iterator for `bfs`
+#[derive(Clone)]
+pub struct BfsIter<'a> {
+    cedar: &'a Cedar,
+    queue: VecDeque<(usize, Vec<u8>)>,
+}
+
+impl<'a> Iterator for BfsIter<'a> {
+    type Item = (usize, Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (from, path) = self.queue.pop_front()?;
+
+        for (c, to) in self.cedar.children(from) {
+            if c != 0 {
+                let mut child_path = path.clone();
+                child_path.push(self.cedar.label(c));
+                self.queue.push_back((to, child_path));
             }
         }
 
-        #[cfg(not(feature = "reduced-trie"))]
-        let to = self.follow(from, 0);
-
-        self.array[to as usize].base_ = value;
-        self.array[to as usize].base_
+        Some((from, path))
     }
+}
 
-    // To move in the trie by following the `label`, and insert the node if the node is not there,
-    // it is used by the `update` to populate the trie.
-    #[inline]
-    fn follow(&mut self, from: usize, label: u8) -> i32 {
-        let base = self.array[from].base();
+/// Iterator for `leaf_keys`
+#[derive(Clone)]
+pub struct LeafKeysIter<'a> {
+    keys: KeysIter<'a>,
+}
 
-        #[allow(unused_assignments)]
-        let mut to = 0;
+impl<'a> Iterator for LeafKeysIter<'a> {
+    type Item = (Vec<u8>, i32);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((key, value, from)) = self.keys.next_entry() {
+            if self.keys.cedar.children(from).all(|(c, _)| c == 0) {
+                return Some((key, value));
+            }
+        }
+
+        None
+    }
+}
+
+/// Iterator for `prefix_stream`
+#[derive(Clone)]
+pub struct PrefixStream<'a> {
+    keys: Option<KeysIter<'a>>,
+}
+
+impl<'a> Iterator for PrefixStream<'a> {
+    type Item = (Vec<u8>, u64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.keys.as_mut()?.next().map(|(key, value)| (key, value as u64))
+    }
+}
+
+// Iterator over the `(label, node)` of the children of a node, following the sibling chain. The label-0 child is
+// the terminal slot that holds the value.
+struct Children<'a> {
+    cedar: &'a Cedar,
+    from: usize,
+    base: i32,
+    c: Option<u8>,
+}
+
+impl<'a> Iterator for Children<'a> {
+    type Item = (u8, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(c) = self.c {
+            let to = (self.base ^ (c as i32)) as usize;
+            if to >= self.cedar.size {
+                break;
+            }
+
+            let sibling = self.cedar.n_infos[to].sibling;
+            self.c = if sibling == 0 { None } else { Some(sibling) };
+
+            // the virtual root shows up as a label 0 that is not owned by `from`.
+            if self.cedar.array[to].check == self.from as i32 {
+                return Some((c, to));
+            }
+        }
+
+        self.c = None;
+        None
+    }
+}
+
+#[allow(clippy::cast_lossless)]
+impl Cedar {
+    /// Initialize the Cedar for further use.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        let mut cedar = Cedar {
+            array: Nodes::Heap(Vec::with_capacity(256)),
+            n_infos: Vec::with_capacity(256),
+            blocks: Vec::with_capacity(1),
+            reject: Vec::with_capacity(257),
+            blocks_head_full: 0,
+            blocks_head_closed: 0,
+            blocks_head_open: 0,
+            capacity: 256,
+            size: 256,
+            ordered: true,
+            max_trial: 1,
+            terminal: 0,
+            num_keys: 0,
+            automaton: OnceLock::new(),
+        };
+        cedar.clear();
+        cedar
+    }
+
+    /// Initialize the Cedar with the parameters of the layout. `max_trial` is how many times a block is probed for
+    /// the free slots before it is skipped, which is 1 for `new`, and a larger value can pack the nodes denser at
+    /// the cost of the build time, though it depends on the keys. `ordered` keeps the siblings sorted by their
+    /// labels, so that the iterations such as `common_prefix_predict` and `keys` come in lexicographical order.
+    /// Without it a new child is put in front of its siblings instead, so they come in the reverse order of their
+    /// insertion, and the inserts are cheaper.
+    pub fn with_options(max_trial: i32, ordered: bool) -> Self {
+        assert!(max_trial > 0, "max_trial has to be positive");
+        let mut cedar = Cedar::new();
+        cedar.max_trial = max_trial;
+        cedar.ordered = ordered;
+        cedar
+    }
+
+    /// Initialize the Cedar with `terminal` as the byte reserved for the terminal slots instead of 0, so that the
+    /// keys can contain 0 but never `terminal` itself. For example 0xff never occurs in UTF-8. The keys with the
+    /// two bytes swapped are in lexicographical order.
+    ///
+    /// # Panics
+    ///
+    /// The infallible inserts such as `update` and `build` panic on a key containing `terminal`, while `try_update`
+    /// and `try_build` return `CedarError::InvalidKey` for it.
+    pub fn with_terminal(terminal: u8) -> Self {
+        let mut cedar = Cedar::new();
+        cedar.terminal = terminal;
+        cedar
+    }
+
+    // To map a byte of the key to the label in the trie and back, with `terminal` and 0 swapped.
+    #[inline]
+    fn label(&self, c: u8) -> u8 {
+        if c == 0 {
+            self.terminal
+        } else if c == self.terminal {
+            0
+        } else {
+            c
+        }
+    }
+
+    /// Build the double array trie from the given key value pairs
+    #[allow(dead_code)]
+    pub fn build(&mut self, key_values: &[(&str, i32)]) {
+        for (key, value) in key_values {
+            self.update(key, *value);
+        }
+    }
+
+    /// Build the double array trie from the given key value pairs like `build`, checking all of the keys first and
+    /// returning `CedarError::InvalidKey` without inserting any of them if one is empty or contains the terminal
+    /// label. The keys are not checked again as they are inserted.
+    pub fn try_build(&mut self, key_values: &[(&str, i32)]) -> Result<(), CedarError> {
+        for (key, _) in key_values {
+            self.check_key(key.as_bytes())?;
+        }
+        for (key, value) in key_values {
+            self.update_with_(key.as_bytes(), 0, 0, |_| *value)
+                .expect("failed to grow the nodes");
+        }
+        Ok(())
+    }
+
+    /// Build the double array trie from the given key value pairs like `build`, inserting them in lexicographical
+    /// order of the keys. The pairs are inserted as they are if they are already sorted, otherwise a sorted copy is
+    /// made first. The siblings then arrive in order and `resolve` relocates fewer of them, which made the build
+    /// of the 20,000 unordered keys of the `cedar build_sorted` benchmark about 14% faster than `build`, the sort
+    /// included. A key given more than once ends up with its last value like in `build`.
+    pub fn build_sorted(&mut self, key_values: &[(&str, i32)]) {
+        if key_values.windows(2).all(|w| w[0].0 <= w[1].0) {
+            self.build(key_values);
+        } else {
+            // the sort is stable, so the duplicated keys keep their order and the last value is the one left.
+            let mut sorted = key_values.to_vec();
+            sorted.sort_by_key(|kv| kv.0);
+            self.build(&sorted);
+        }
+    }
+
+    /// Build the double array trie from the given key value pairs like `build`, checking `cancel` before every key
+    /// and returning `CedarError::Cancelled` once it is set. The keys inserted so far stay in the trie, which is
+    /// as consistent as after a `build` of them alone.
+    pub fn build_cancellable(&mut self, key_values: &[(&str, i32)], cancel: &AtomicBool) -> Result<(), CedarError> {
+        for (key, value) in key_values {
+            if cancel.load(Ordering::Relaxed) {
+                return Err(CedarError::Cancelled);
+            }
+            self.update(key, *value);
+        }
+
+        Ok(())
+    }
+
+    /// Build the double array trie from the given key value pairs like `build`, returning the largest number of
+    /// nodes allocated along the way. The node array doubles whenever it is full, so the peak is what the build
+    /// costs in memory at its worst, and it is never below the capacity at the end.
+    pub fn build_tracking_peak(&mut self, key_values: &[(&str, i32)]) -> usize {
+        let mut peak = self.capacity;
+        for (key, value) in key_values {
+            self.update(key, *value);
+            peak = peak.max(self.capacity);
+        }
+
+        peak
+    }
+
+    /// Build the double array trie from the given key value pairs like `build`, reporting the keys that were already
+    /// in the dictionary or earlier in `key_values` as `(key, old, new)`. The later value still replaces the older
+    /// one, so the trie is the same as after `build`.
+    #[allow(clippy::type_complexity)]
+    pub fn build_checked<'a>(&mut self, key_values: &[(&'a str, i32)]) -> Result<(), Vec<(&'a str, i32, i32)>> {
+        let mut conflicts = Vec::new();
+        for &(key, value) in key_values {
+            if let Some((old, _, _)) = self.exact_match_search(key) {
+                conflicts.push((key, old, value));
+            }
+            self.update(key, value);
+        }
+
+        if conflicts.is_empty() {
+            Ok(())
+        } else {
+            Err(conflicts)
+        }
+    }
+
+    /// To set the `max_trial` of the search for free blocks by building `sample` with each of 1, 2, 4, 8 and 16,
+    /// returning the one picked. A block is probed at most `max_trial` times before it is skipped, so a larger
+    /// value can pack the nodes denser at the cost of the build time. Each value is timed by the median of a few
+    /// builds, and among the ones taking at most twice the time of the fastest the densest is picked, counting
+    /// the nodes in use over the blocks in use. The densities within `AUTO_TUNE_DENSITY_TOLERANCE` of the best
+    /// are a tie, which goes to the smaller value.
+    ///
+    /// The result is not deterministic, as it depends on the timings: the same sample can give another
+    /// `max_trial`, and so another layout and serialized output, from run to run or from machine to machine.
+    pub fn auto_tune_max_trial(&mut self, sample: &[(&str, i32)]) -> i32 {
+        let mut trials = Vec::new();
+        for &max_trial in [1, 2, 4, 8, 16].iter() {
+            let mut times = Vec::with_capacity(AUTO_TUNE_RUNS);
+            let mut density = 0.0;
+            for _ in 0..AUTO_TUNE_RUNS {
+                let mut cedar = Cedar::with_options(max_trial, self.ordered);
+                cedar.terminal = self.terminal;
+                let start = std::time::Instant::now();
+                cedar.build(sample);
+                times.push(start.elapsed());
+                density = cedar.num_nodes() as f64 / cedar.size as f64;
+            }
+
+            times.sort_unstable();
+            trials.push((max_trial, times[AUTO_TUNE_RUNS / 2], density));
+        }
+
+        self.max_trial = Cedar::pick_max_trial(&trials);
+        self.max_trial
+    }
+
+    // To pick the `max_trial` of `auto_tune_max_trial` from the `(max_trial, median time, density)` of each
+    // candidate, in increasing order of `max_trial`.
+    fn pick_max_trial(trials: &[(i32, std::time::Duration, f64)]) -> i32 {
+        let fastest = trials.iter().map(|t| t.1).min().unwrap();
+        let candidates: Vec<_> = trials.iter().filter(|t| t.1 <= fastest * 2).collect();
+        let densest = candidates.iter().map(|t| t.2).fold(0.0, f64::max);
+
+        candidates
+            .iter()
+            .find(|t| t.2 >= densest - AUTO_TUNE_DENSITY_TOLERANCE)
+            .unwrap()
+            .0
+    }
+
+    /// Build the double array trie from the given keys, with the value of each key computed by `f`.
+    pub fn build_with<F: Fn(&str) -> i32>(&mut self, keys: &[&str], f: F) {
+        for key in keys {
+            self.update(key, f(key));
+        }
+    }
+
+    /// To remove every key, resetting the trie including the `reject` heuristics to the state of `new`. The
+    /// terminal label and the options of `with_options` are kept, and so are the allocations of the vectors, so
+    /// that a trie reused across batches does not grow its arrays again.
+    pub fn clear(&mut self) {
+        // the vectors are cut back to the first block and refilled, which keeps their capacity.
+        // only the mapped nodes of `DiskCedar` can fail to resize, and they are never cleared.
+        self.array.resize(256).expect("failed to resize the nodes");
+        self.n_infos.clear();
+        self.n_infos.resize(256, Default::default());
+        self.blocks.clear();
+        self.blocks.push(Block::new());
+        self.reject.clear();
+        self.reject.extend((0..=256).map(|i| i + 1));
+
+        #[cfg(feature = "reduced-trie")]
+        let root = Node { base_: -1, check: -1 };
+        #[cfg(not(feature = "reduced-trie"))]
+        let root = Node { base_: 0, check: -1 };
+        self.array[0] = root;
+
+        for i in 1..256 {
+            // make `base_` point to the previous element, and make `check` point to the next element
+            self.array[i as usize] = Node {
+                base_: -(i - 1),
+                check: -(i + 1),
+            };
+        }
+
+        // make them link as a cyclic doubly-linked list
+        self.array[1].base_ = -255;
+        self.array[255].check = -1;
+
+        self.blocks[0].e_head = 1;
+
+        self.blocks_head_full = 0;
+        self.blocks_head_closed = 0;
+        self.blocks_head_open = 0;
+        self.capacity = 256;
+        self.size = 256;
+        self.num_keys = 0;
+        self.automaton.take();
+    }
+
+    /// To rebuild the trie from its keys in lexicographical order, so that the layout is the same as a fresh build
+    /// of the same keys, dropping what the history of updates and erasures left behind. The node ids change.
+    pub fn rebuild(&mut self) {
+        let key_values: Vec<(Vec<u8>, i32)> = self.keys().collect();
+        self.clear();
+        for (key, value) in key_values {
+            self.update_(&key, value, 0, 0);
+        }
+    }
+
+    /// To build a new trie from the keys for which `f` returns a value, mapped to that value, so that the entries
+    /// are filtered and transformed in a single pass like `Iterator::filter_map`. The trie itself is left as is.
+    pub fn map_rebuild<F: Fn(&[u8], i32) -> Option<i32>>(&self, f: F) -> Cedar {
+        let mut cedar = Cedar::with_terminal(self.terminal);
+        for (key, value) in self.keys() {
+            if let Some(value) = f(&key, value) {
+                cedar.update_(&key, value, 0, 0);
+            }
+        }
+
+        cedar
+    }
+
+    /// Build the double array trie from the entries of `map`.
+    pub fn from_map(map: &HashMap<String, i32>) -> Self {
+        let mut cedar = Cedar::new();
+        for (key, value) in map {
+            cedar.update(key, *value);
+        }
+
+        cedar
+    }
+
+    /// Build the double array trie by inserting the `priority` key value pairs before the `rest`. The keys
+    /// inserted earlier tend to occupy the lower node ids, which keeps the hot keys in the first blocks.
+    pub fn build_prioritized(&mut self, priority: &[(&str, i32)], rest: &[(&str, i32)]) {
+        self.build(priority);
+        self.build(rest);
+    }
+
+    /// Build the double array trie from the given key value pairs with `namespace` prepended to every key, so they
+    /// are looked up as `namespace + key`. The dictionaries of several tenants can then share one trie.
+    pub fn build_namespaced(&mut self, namespace: &str, key_values: &[(&str, i32)]) {
+        let mut buf = namespace.as_bytes().to_vec();
+        for (key, value) in key_values {
+            buf.truncate(namespace.len());
+            buf.extend_from_slice(key.as_bytes());
+            self.update_(&buf, *value, 0, 0);
+        }
+    }
+
+    /// Update the key for the value, it is public interface that works on &str
+    ///
+    /// # Panics
+    ///
+    /// If `key` is empty or contains the terminal label, which is '\0' unless set by `with_terminal`. Use
+    /// `try_update` for the keys that are not known to be valid.
+    pub fn update(&mut self, key: &str, value: i32) {
+        self.update_bytes(key.as_bytes(), value)
+    }
+
+    /// Update the key for the value like `update`, returning `CedarError::InvalidKey` instead of panicking if
+    /// `key` is empty or contains the terminal label, in which case the trie is left as it was.
+    pub fn try_update(&mut self, key: &str, value: i32) -> Result<(), CedarError> {
+        self.check_key(key.as_bytes())?;
+        self.update_with_(key.as_bytes(), 0, 0, |_| value)
+            .expect("failed to grow the nodes");
+        Ok(())
+    }
+
+    /// Update the raw bytes `key` for the value like `update`, for the keys that are not UTF-8 such as the tokens
+    /// of a tokenizer. They can be any bytes but the terminal label, which is 0 unless set by `with_terminal`.
+    ///
+    /// # Panics
+    ///
+    /// If `key` is empty or contains the terminal label, like `update`.
+    pub fn update_bytes(&mut self, key: &[u8], value: i32) {
+        let from = 0;
+        let pos = 0;
+        self.update_(key, value, from, pos);
+    }
+
+    /// To return the value of `key` if it is in the dictionary, otherwise to insert the value computed by `f` and
+    /// return it, like the `entry().or_insert_with()` of a map.
+    pub fn get_or_insert_with<F: FnOnce() -> i32>(&mut self, key: &str, f: F) -> i32 {
+        match self.exact_match_search(key) {
+            Some((value, _, _)) => value,
+            None => {
+                let value = f();
+                self.update(key, value);
+                value
+            }
+        }
+    }
+
+    /// To update the value of `key` to the one returned by `f`, which is called with the current value or `None` if
+    /// the key is absent, like `*map.entry(key).or_default() += 1` of a map. The trie is walked once, compared to
+    /// the `exact_match_search` and `update` pair.
+    ///
+    /// # Panics
+    ///
+    /// If `key` is empty or contains the terminal label, like `update`.
+    pub fn update_with<F: FnOnce(Option<i32>) -> i32>(&mut self, key: &str, f: F) -> i32 {
+        self.assert_key(key.as_bytes(), 0);
+        self.update_with_(key.as_bytes(), 0, 0, f)
+            .expect("failed to grow the nodes")
+    }
+
+    // Update the key for the value, it is internal interface that works on &[u8] and cursor.
+    fn update_(&mut self, key: &[u8], value: i32, from: usize, pos: usize) -> i32 {
+        self.assert_key(key, from);
+        // only the mapped nodes can fail to grow, and `DiskCedar` calls `update_reserved` for the error instead.
+        self.update_with_(key, from, pos, |_| value)
+            .expect("failed to grow the nodes")
+    }
+
+    // Update the key for the value like `update_bytes`, reserving the blocks the insert may need before any change,
+    // so that a failure to grow the nodes leaves the trie as it was. Every step of the insert adds at most one block,
+    // and the reduced trie can take two steps for a byte, to move the value of a leaf out of the way.
+    #[cfg(all(feature = "mmap", unix))]
+    fn update_reserved(&mut self, key: &[u8], value: i32) -> io::Result<i32> {
+        self.check_key(key)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        #[cfg(feature = "reduced-trie")]
+        let num_steps = 2 * key.len() + 1;
+        #[cfg(not(feature = "reduced-trie"))]
+        let num_steps = key.len() + 1;
+
+        self.reserve_blocks(num_steps)?;
+        self.update_with_(key, 0, 0, |_| value)
+    }
+
+    // To check that `key` can be inserted, since the empty key and the terminal label would clash with the value
+    // of the parent in its terminal slot.
+    fn check_key(&self, key: &[u8]) -> Result<(), CedarError> {
+        if key.is_empty() || key.contains(&self.terminal) {
+            Err(CedarError::InvalidKey)
+        } else {
+            Ok(())
+        }
+    }
+
+    // To panic on the keys `check_key` rejects, for the infallible inserts. A key continued from a node other than
+    // the root can be empty.
+    fn assert_key(&self, key: &[u8], from: usize) {
+        if from == 0 && key.is_empty() {
+            panic!("failed to insert zero-length key");
+        }
+
+        if key.contains(&self.terminal) {
+            panic!("failed to insert a key containing the terminal label");
+        }
+    }
+
+    // Update the key for the value computed from the current one, with the same cursor as `update_`. The key is
+    // checked by the callers. It fails only if the nodes cannot be grown, which `update_reserved` rules out ahead.
+    fn update_with_<F: FnOnce(Option<i32>) -> i32>(
+        &mut self,
+        key: &[u8],
+        mut from: usize,
+        mut pos: usize,
+        f: F,
+    ) -> io::Result<i32> {
+        self.automaton.take();
+        while pos < key.len() {
+            #[cfg(feature = "reduced-trie")]
+            {
+                let val_ = self.array[from].base_;
+                if val_ >= 0 && val_ != CEDAR_VALUE_LIMIT {
+                    let to = self.follow(from, 0)?;
+                    self.array[to as usize].base_ = val_;
+                }
+            }
+
+            from = self.follow(from, self.label(key[pos]))? as usize;
+            pos += 1;
+        }
+
+        let current = self.terminal_value(from);
+        let had_value = current.is_some();
+        let value = f(current);
+
+        #[cfg(feature = "reduced-trie")]
+        let to = if self.array[from].base_ >= 0 {
+            from as i32
+        } else {
+            self.follow(from, 0)?
+        };
+
+        #[cfg(feature = "reduced-trie")]
+        {
+            if self.array[to as usize].base_ == CEDAR_VALUE_LIMIT {
+                self.array[to as usize].base_ = 0;
+            }
+        }
+
+        #[cfg(not(feature = "reduced-trie"))]
+        let to = self.follow(from, 0)?;
+
+        self.array[to as usize].base_ = value;
+
+        // only a new key counts, and storing `CEDAR_NO_VALUE` leaves no value behind like in `find`.
+        match (had_value, self.terminal_value(from).is_some()) {
+            (false, true) => self.num_keys += 1,
+            (true, false) => self.num_keys -= 1,
+            _ => {}
+        }
+
+        Ok(self.array[to as usize].base_)
+    }
+
+    // To move in the trie by following the `label`, and insert the node if the node is not there,
+    // it is used by the `update` to populate the trie.
+    #[inline]
+    fn follow(&mut self, from: usize, label: u8) -> io::Result<i32> {
+        let base = self.array[from].base();
+
+        #[allow(unused_assignments)]
+        let mut to = 0;
 
         // the node is not there
         if base < 0 || self.array[(base ^ (label as i32)) as usize].check < 0 {
-            // allocate a e node
-            to = self.pop_e_node(base, label, from as i32);
+            // allocate a e node, a new block is added if `from` has no children yet.
+            let e = if base < 0 {
+                self.find_place()?
+            } else {
+                base ^ (label as i32)
+            };
+            to = self.pop_e_node(base, e, label, from as i32);
             let branch: i32 = to ^ (label as i32);
 
             // maintain the info in ninfo
@@ -359,11 +1352,11 @@ impl Cedar {
             to = base ^ (label as i32);
             if self.array[to as usize].check != (from as i32) {
                 // call `resolve` to relocate.
-                to = self.resolve(from, base, label);
+                to = self.resolve(from, base, label)?;
             }
         }
 
-        to
+        Ok(to)
     }
 
     // Find key from double array trie, with `from` as the cursor to traverse the nodes.
@@ -381,8 +1374,14 @@ impl Cedar {
                 }
             }
 
-            to = (self.array[*from].base() ^ (key[pos] as i32)) as usize;
-            if self.array[to as usize].check != (*from as i32) {
+            // the terminal label never occurs in the keys.
+            let label = self.label(key[pos]);
+            if label == 0 {
+                return None;
+            }
+
+            to = (self.array[*from].base() ^ (label as i32)) as usize;
+            if self.array[to].check != (*from as i32) {
                 return None;
             }
 
@@ -413,7 +1412,30 @@ impl Cedar {
 
     /// Delete the key from the trie, the public interface that works on &str
     pub fn erase(&mut self, key: &str) {
-        self.erase_(key.as_bytes())
+        self.erase_bytes(key.as_bytes())
+    }
+
+    /// Delete the raw bytes `key` from the trie like `erase`.
+    pub fn erase_bytes(&mut self, key: &[u8]) {
+        self.erase_(key)
+    }
+
+    /// Delete the batch of `keys` from the trie like `erase`, skipping the keys that are not in the dictionary, and
+    /// return the number of keys deleted. The keys are deleted one after another for now, so the batch is the same
+    /// as a loop of `erase`, but a faster way through the batch can come without any change to the callers.
+    pub fn erase_many(&mut self, keys: &[&str]) -> usize {
+        let mut count = 0;
+        for key in keys {
+            let mut from = 0;
+            if let Some(v) = self.find(key.as_bytes(), &mut from) {
+                if v != CEDAR_NO_VALUE {
+                    self.erase__(from);
+                    count += 1;
+                }
+            }
+        }
+
+        count
     }
 
     // Delete the key from the trie, the internal interface that works on &[u8]
@@ -428,7 +1450,10 @@ impl Cedar {
         }
     }
 
-    fn erase__(&mut self, mut from: usize) {
+    // Delete the key ending at `from`, returning the node whose sibling chain the deletion stopped at.
+    fn erase__(&mut self, mut from: usize) -> usize {
+        self.automaton.take();
+        self.num_keys -= 1;
         #[cfg(feature = "reduced-trie")]
         let mut e: i32 = if self.array[from].base_ >= 0 {
             from as i32
@@ -467,950 +1492,5589 @@ impl Cedar {
                 break;
             }
         }
+
+        e as usize
     }
 
-    /// To check if `key` is in the dictionary.
-    pub fn exact_match_search(&self, key: &str) -> Option<(i32, usize, usize)> {
-        let key = key.as_bytes();
+    /// Delete the key from the trie like `erase`, and then try to relocate the remaining children of the node where
+    /// the deletion stopped into a free region of a block with a lower index. It keeps the live nodes packed toward
+    /// the front of the array for dictionaries with churn. Note that the node ids of the relocated children change.
+    pub fn erase_and_compact(&mut self, key: &str) {
         let mut from = 0;
 
-        if let Some(value) = self.find(key, &mut from) {
-            if value == CEDAR_NO_VALUE {
-                return None;
+        if let Some(v) = self.find(key.as_bytes(), &mut from) {
+            if v != CEDAR_NO_VALUE {
+                let parent = self.erase__(from);
+                self.compact(parent);
             }
-
-            Some((value, key.len(), from))
-        } else {
-            None
         }
     }
 
-    /// To return an iterator to iterate through the common prefix in the dictionary with the `key` passed in.
-    pub fn common_prefix_iter<'a>(&'a self, key: &'a str) -> PrefixIter<'a> {
-        let key = key.as_bytes();
-
-        PrefixIter {
-            cedar: self,
-            key,
-            from: 0,
-            i: 0,
+    /// To relocate the children of every node into a free region of a block with a lower index when there is one,
+    /// from the root down, so that the live nodes and their values are packed toward the front of the array after
+    /// heavy erasure. Unlike a rebuild the array is not shrunk, and the node ids of the relocated nodes change.
+    pub fn defragment_values(&mut self) {
+        let mut queue: VecDeque<usize> = self.children(0).filter(|&(c, _)| c != 0).map(|(_, to)| to).collect();
+
+        // the children are only enumerated after being relocated, so the ids in the queue stay valid.
+        while let Some(from) = queue.pop_front() {
+            self.compact(from);
+            let children: SmallVec<[usize; 256]> =
+                self.children(from).filter(|&(c, _)| c != 0).map(|(_, to)| to).collect();
+            queue.extend(children);
         }
     }
 
-    /// To return the collection of the common prefix in the dictionary with the `key` passed in.
-    pub fn common_prefix_search(&self, key: &str) -> Option<Vec<(i32, usize)>> {
-        self.common_prefix_iter(key).map(Some).collect()
-    }
-
-    /// To return an iterator to iterate through the list of words in the dictionary that has `key` as their prefix.
-    pub fn common_prefix_predict_iter<'a>(&'a self, key: &'a str) -> PrefixPredictIter<'a> {
-        let key = key.as_bytes();
+    // To relocate the children of `from` to a block before the current one if there is room for them, returning
+    // whether they have been moved.
+    fn compact(&mut self, from: usize) -> bool {
+        self.automaton.take();
+        // the children of the root are in the fixed block 0.
+        if from == 0 {
+            return false;
+        }
 
-        PrefixPredictIter {
-            cedar: self,
-            key,
-            from: 0,
-            p: 0,
-            root: 0,
-            value: None,
+        let base_old = self.array[from].base();
+        let children: SmallVec<[u8; 256]> = self.children(from).map(|(c, _)| c).collect();
+        if children.is_empty() {
+            return false;
         }
-    }
 
-    /// To return the list of words in the dictionary that has `key` as their prefix.
-    pub fn common_prefix_predict(&self, key: &str) -> Option<Vec<(i32, usize)>> {
-        self.common_prefix_predict_iter(key).map(Some).collect()
-    }
+        let base = match self.find_base_before(&children, base_old >> 8) {
+            Some(base) => base,
+            None => return false,
+        };
 
-    // To get the cursor of the first leaf node starting by `from`
-    fn begin(&self, mut from: usize, mut p: usize) -> (Option<i32>, usize, usize) {
-        let base = self.array[from].base();
-        let mut c = self.n_infos[from].child;
+        for (i, &label) in children.iter().enumerate() {
+            let to = self.pop_e_node(base, base ^ (label as i32), label, from as i32);
+            let to_ = base_old ^ (label as i32);
 
-        if from == 0 {
-            c = self.n_infos[(base ^ (c as i32)) as usize].sibling;
+            self.n_infos[to as usize].sibling = children.get(i + 1).cloned().unwrap_or(0);
+            self.n_infos[to as usize].child = self.n_infos[to_ as usize].child;
+            self.array[to as usize].base_ = self.array[to_ as usize].base_;
 
-            // if no sibling couldn be found from the virtual root, then we are done.
-            if c == 0 {
-                return (None, from, p);
-            }
-        }
+            #[cfg(feature = "reduced-trie")]
+            let has_child = self.array[to as usize].base_ < 0 && label != 0;
+            #[cfg(not(feature = "reduced-trie"))]
+            let has_child = self.array[to as usize].base_ > 0 && label != 0;
 
-        // recursively traversing down to look for the first leaf.
-        while c != 0 {
-            from = (self.array[from].base() ^ (c as i32)) as usize;
-            c = self.n_infos[from].child;
-            p += 1;
-        }
+            // the grandchildren have to point to the new position of their parent.
+            if has_child {
+                let mut c = self.n_infos[to as usize].child;
+                loop {
+                    let idx = (self.array[to as usize].base() ^ (c as i32)) as usize;
+                    self.array[idx].check = to;
+                    c = self.n_infos[idx].sibling;
 
-        #[cfg(feature = "reduced-trie")]
-        {
-            if self.array[from].base_ >= 0 {
-                return (Some(self.array[from].base_), from, p);
+                    if c == 0 {
+                        break;
+                    }
+                }
             }
-        }
 
-        // To return the value of the leaf.
-        let v = self.array[(self.array[from].base() ^ (c as i32)) as usize].base_;
-        (Some(v), from, p)
-    }
-
-    // To move the cursor from one leaf to the next for the common_prefix_predict.
-    fn next(&self, mut from: usize, mut p: usize, root: usize) -> (Option<i32>, usize, usize) {
-        #[allow(unused_assignments)]
-        let mut c: u8 = 0;
+            self.push_e_node(to_);
+        }
 
         #[cfg(feature = "reduced-trie")]
         {
-            if self.array[from].base_ < 0 {
-                c = self.n_infos[(self.array[from].base()) as usize].sibling;
-            }
+            self.array[from].base_ = -base - 1;
         }
+
         #[cfg(not(feature = "reduced-trie"))]
         {
-            c = self.n_infos[(self.array[from].base()) as usize].sibling;
+            self.array[from].base_ = base;
         }
 
-        // traversing up until there is a sibling or it has reached the root.
-        while c == 0 && from != root {
-            c = self.n_infos[from as usize].sibling;
-            from = self.array[from as usize].check as usize;
+        true
+    }
 
-            p -= 1;
-        }
+    // To look for a base in the blocks before `limit` so that all of the `children` land on free slots. The block 0
+    // is left to the children of the root, as its `num` counts the root as a free slot.
+    fn find_base_before(&self, children: &[u8], limit: i32) -> Option<i32> {
+        for idx in 1..limit.max(1) as usize {
+            let block = &self.blocks[idx];
+            if (block.num as usize) < children.len() {
+                continue;
+            }
 
-        if c != 0 {
-            // it has a sibling so we leverage on `begin` to traverse the subtree down again.
-            from = (self.array[from].base() ^ (c as i32)) as usize;
-            let (v_, from_, p_) = self.begin(from, p + 1);
-            (v_, from_, p_)
-        } else {
-            // no more work since we couldn't find anything.
-            (None, from, p)
+            let mut e = block.e_head;
+            for _ in 0..block.num {
+                let base = e ^ (children[0] as i32);
+                if children
+                    .iter()
+                    .all(|&c| self.array[(base ^ (c as i32)) as usize].check < 0)
+                {
+                    return Some(base);
+                }
+
+                e = -self.array[e as usize].check;
+            }
         }
+
+        None
     }
 
-    // pop a block at idx from the linked-list of type `from`, specially handled if it is the last
-    // one in the linked-list.
-    fn pop_block(&mut self, idx: i32, from: BlockType, last: bool) {
-        let head: &mut i32 = match from {
-            BlockType::Open => &mut self.blocks_head_open,
-            BlockType::Closed => &mut self.blocks_head_closed,
-            BlockType::Full => &mut self.blocks_head_full,
-        };
+    /// To check if `key` is in the dictionary.
+    pub fn exact_match_search(&self, key: &str) -> Option<(i32, usize, usize)> {
+        self.exact_match_search_bytes(key.as_bytes())
+    }
 
-        if last {
-            *head = 0;
-        } else {
-            let b = self.blocks[idx as usize].clone();
-            self.blocks[b.prev as usize].next = b.next;
-            self.blocks[b.next as usize].prev = b.prev;
+    /// To check if the raw bytes `key` is in the dictionary. The bytes are not validated as UTF-8, so they can cut
+    /// through a multibyte character and are matched byte by byte against the stored keys.
+    pub fn exact_match_search_bytes(&self, key: &[u8]) -> Option<(i32, usize, usize)> {
+        let mut from = 0;
 
-            if idx == *head {
-                *head = b.next;
+        if let Some(value) = self.find(key, &mut from) {
+            if value == CEDAR_NO_VALUE {
+                return None;
             }
+
+            Some((value, key.len(), from))
+        } else {
+            None
         }
     }
 
-    // return the block at idx to the linked-list of `to`, specially handled if the linked-list is
-    // empty
-    fn push_block(&mut self, idx: i32, to: BlockType, empty: bool) {
-        let head: &mut i32 = match to {
-            BlockType::Open => &mut self.blocks_head_open,
-            BlockType::Closed => &mut self.blocks_head_closed,
-            BlockType::Full => &mut self.blocks_head_full,
-        };
+    /// To check if `key` is in the dictionary, for the set membership where the value is not needed.
+    pub fn contains_key(&self, key: &str) -> bool {
+        let mut from = 0;
+        self.find(key.as_bytes(), &mut from)
+            .is_some_and(|value| value != CEDAR_NO_VALUE)
+    }
 
-        if empty {
-            self.blocks[idx as usize].next = idx;
-            self.blocks[idx as usize].prev = idx;
-            *head = idx;
-        } else {
-            self.blocks[idx as usize].prev = self.blocks[*head as usize].prev;
-            self.blocks[idx as usize].next = *head;
+    /// To look up `key` like `exact_match_search`, returning the match as a `Match` that also has the length of
+    /// the key in chars, for the interfaces that count the characters.
+    pub fn exact_match_search_meta(&self, key: &str) -> Option<Match> {
+        self.exact_match_search(key).map(|(value, byte_len, node_id)| Match {
+            value,
+            byte_len,
+            char_len: key.chars().count(),
+            node_id,
+        })
+    }
 
-            let t = self.blocks[*head as usize].prev;
-            self.blocks[t as usize].next = idx;
-            self.blocks[*head as usize].prev = idx;
-            *head = idx;
+    /// To check if `key` is in the dictionary like `exact_match_search`, with every transition checked so that a
+    /// corrupted trie gives `Err` instead of a panic.
+    pub fn try_exact_match_search(&self, key: &str) -> Result<Option<(i32, usize, usize)>, CedarError> {
+        let mut from = 0;
+        match self.try_find(key.as_bytes(), &mut from)? {
+            Some(value) if value != CEDAR_NO_VALUE => Ok(Some((value, key.len(), from))),
+            _ => Ok(None),
         }
     }
 
-    /// Reallocate more spaces so that we have more free blocks.
-    fn add_block(&mut self) -> i32 {
-        if self.size == self.capacity {
-            self.capacity += self.capacity;
+    /// To iterate through the common prefix in the dictionary with the `key` passed in like `common_prefix_iter`,
+    /// with every transition checked. A corrupted trie yields an `Err`, after which the iteration ends.
+    pub fn try_common_prefix_iter<'a>(&'a self, key: &'a str) -> TryPrefixIter<'a> {
+        TryPrefixIter {
+            cedar: self,
+            key: key.as_bytes(),
+            from: 0,
+            i: 0,
+        }
+    }
 
-            self.array.resize(self.capacity, Default::default());
-            self.n_infos.resize(self.capacity, Default::default());
-            self.blocks.resize(self.capacity >> 8, Block::new());
+    /// To iterate through the words in the dictionary that has `key` as their prefix like
+    /// `common_prefix_predict_iter`, with every transition checked. A corrupted trie yields an `Err`, after which
+    /// the iteration ends.
+    pub fn try_common_prefix_predict_iter<'a>(&'a self, key: &'a str) -> TryPrefixPredictIter<'a> {
+        TryPrefixPredictIter {
+            cedar: self,
+            key: key.as_bytes(),
+            from: 0,
+            p: 0,
+            root: 0,
+            started: false,
+            done: false,
         }
+    }
 
-        self.blocks[self.size >> 8].e_head = self.size as i32;
+    /// To return an iterator to iterate through the common prefix in the dictionary with the `key` passed in.
+    pub fn common_prefix_iter<'a>(&'a self, key: &'a str) -> PrefixIter<'a> {
+        self.common_prefix_iter_bytes(key.as_bytes())
+    }
 
-        // make it a doubley linked list
-        self.array[self.size] = Node {
-            base_: -((self.size as i32) + 255),
-            check: -((self.size as i32) + 1),
-        };
+    /// To iterate through the common prefix like `common_prefix_iter` with the raw bytes `key`. No UTF-8
+    /// validation is done, and the reported offsets are byte offsets which are not guaranteed to be on a char
+    /// boundary.
+    pub fn common_prefix_iter_bytes<'a>(&'a self, key: &'a [u8]) -> PrefixIter<'a> {
+        PrefixIter {
+            cedar: self,
+            key,
+            from: 0,
+            i: 0,
+        }
+    }
 
-        for i in (self.size + 1)..(self.size + 255) {
-            self.array[i] = Node {
-                base_: -(i as i32 - 1),
-                check: -(i as i32 + 1),
-            };
+    /// To check if `key` is in the dictionary like `exact_match_search`, together with the number of node
+    /// transitions taken, which is the cost of the query.
+    pub fn exact_match_search_profiled(&self, key: &str) -> (Option<i32>, usize) {
+        let mut from = 0;
+        for (i, &label) in key.as_bytes().iter().enumerate() {
+            match self.step(from, label) {
+                Some(to) => from = to,
+                None => return (None, i),
+            }
         }
 
-        self.array[self.size + 255] = Node {
-            base_: -((self.size as i32) + 254),
-            check: -(self.size as i32),
-        };
+        (self.terminal_value(from), key.len())
+    }
 
-        let is_empty = self.blocks_head_open == 0;
-        let idx = (self.size >> 8) as i32;
-        debug_assert!(self.blocks[idx as usize].num > 1);
-        self.push_block(idx, BlockType::Open, is_empty);
+    /// To return the common prefixes like `common_prefix_search`, together with the number of node transitions
+    /// taken.
+    pub fn common_prefix_search_profiled(&self, key: &str) -> (Vec<(i32, usize)>, usize) {
+        let mut result = Vec::new();
+        let mut from = 0;
+        for (i, &label) in key.as_bytes().iter().enumerate() {
+            match self.step(from, label) {
+                Some(to) => from = to,
+                None => return (result, i),
+            }
 
-        self.size += 256;
+            if let Some(value) = self.terminal_value(from) {
+                result.push((value, i));
+            }
+        }
 
-        ((self.size >> 8) - 1) as i32
+        (result, key.len())
     }
 
-    // transfer the block at idx from the linked-list of `from` to the linked-list of `to`,
-    // specially handle the case where the destination linked-list is empty.
-    fn transfer_block(&mut self, idx: i32, from: BlockType, to: BlockType, to_block_empty: bool) {
-        let is_last = idx == self.blocks[idx as usize].next; //it's the last one if the next points to itself
-        let is_empty = to_block_empty && (self.blocks[idx as usize].num != 0);
-
-        self.pop_block(idx, from, is_last);
-        self.push_block(idx, to, is_empty);
+    /// To return the collection of the common prefix in the dictionary with the `key` passed in.
+    pub fn common_prefix_search(&self, key: &str) -> Option<Vec<(i32, usize)>> {
+        self.common_prefix_iter(key).map(Some).collect()
     }
 
-    /// Mark an edge `e` as used in a trie node.
-    fn pop_e_node(&mut self, base: i32, label: u8, from: i32) -> i32 {
-        let e: i32 = if base < 0 {
-            self.find_place()
-        } else {
-            base ^ (label as i32)
-        };
-
-        let idx = e >> 8;
-        let n = self.array[e as usize].clone();
-
-        self.blocks[idx as usize].num -= 1;
-        // move the block at idx to the correct linked-list depending the free slots it still have.
-        if self.blocks[idx as usize].num == 0 {
-            if idx != 0 {
-                self.transfer_block(idx, BlockType::Closed, BlockType::Full, self.blocks_head_full == 0);
-            }
-        } else {
-            self.array[(-n.base_) as usize].check = n.check;
-            self.array[(-n.check) as usize].base_ = n.base_;
+    /// To return the longest key in the dictionary that is a prefix of `key`, as its value and its length in bytes,
+    /// for the greedy tokenization. Note that the length is one more than the offset of the last byte returned by
+    /// `common_prefix_search`. The walk stops at the first byte without a transition, and nothing is allocated.
+    pub fn longest_prefix_match(&self, key: &str) -> Option<(i32, usize)> {
+        self.longest_prefix_match_(key.as_bytes())
+    }
 
-            if e == self.blocks[idx as usize].e_head {
-                self.blocks[idx as usize].e_head = -n.check;
+    // the longest match on the raw bytes, as the scan can start in the middle of a character.
+    fn longest_prefix_match_(&self, key: &[u8]) -> Option<(i32, usize)> {
+        let mut from = 0;
+        let mut longest = None;
+        for (i, &label) in key.iter().enumerate() {
+            match self.step(from, label) {
+                Some(to) => from = to,
+                None => break,
             }
 
-            if idx != 0 && self.blocks[idx as usize].num == 1 && self.blocks[idx as usize].trial != self.max_trial {
-                self.transfer_block(idx, BlockType::Open, BlockType::Closed, self.blocks_head_closed == 0);
+            if let Some(value) = self.terminal_value(from) {
+                longest = Some((value, i + 1));
             }
         }
 
-        #[cfg(feature = "reduced-trie")]
-        {
-            self.array[e as usize].base_ = CEDAR_VALUE_LIMIT;
-            self.array[e as usize].check = from;
-            if base < 0 {
-                self.array[from as usize].base_ = -(e ^ (label as i32)) - 1;
-            }
+        longest
+    }
+
+    /// To return the collection of the common prefix in the dictionary with the `key` passed in, like
+    /// `common_prefix_search` but with the index of the last character of the match in `key` instead of the index
+    /// of its last byte.
+    pub fn common_prefix_search_chars(&self, key: &str) -> Vec<(i32, usize)> {
+        let bytes = key.as_bytes();
+        let mut chars = 0;
+        let mut end = 0;
+        self.common_prefix_iter(key)
+            .map(|(value, last)| {
+                // count the leading bytes of the characters up to and including the last byte of the match.
+                chars += bytes[end..=last].iter().filter(|&&b| b & 0xc0 != 0x80).count();
+                end = last + 1;
+                (value, chars - 1)
+            })
+            .collect()
+    }
+
+    /// To return the collection of the common prefix in the dictionary with the raw bytes `key` passed in, see
+    /// `common_prefix_iter_bytes`.
+    pub fn common_prefix_search_bytes(&self, key: &[u8]) -> Option<Vec<(i32, usize)>> {
+        self.common_prefix_iter_bytes(key).map(Some).collect()
+    }
+
+    /// To return at most `max` of the common prefixes like `common_prefix_search`, and whether there were more
+    /// matches left out. The traversal stops right after the match past the cap.
+    pub fn common_prefix_search_max(&self, key: &str, max: usize) -> (Vec<(i32, usize)>, bool) {
+        let mut result: Vec<(i32, usize)> = self.common_prefix_iter(key).take(max.saturating_add(1)).collect();
+        let truncated = result.len() > max;
+        result.truncate(max);
+
+        (result, truncated)
+    }
+
+    /// To return the shortest key in the dictionary that is a prefix of `key`, as the first item of
+    /// `common_prefix_iter`. The traversal stops at the first match.
+    pub fn shortest_prefix_match(&self, key: &str) -> Option<(i32, usize)> {
+        self.common_prefix_iter(key).next()
+    }
+
+    /// To return an iterator over every key in the dictionary that occurs in `text`, including the overlapping
+    /// ones, as the value and the byte range `start..end` in `text`. The matches are ordered by `start` and then
+    /// by length.
+    pub fn common_prefix_scan<'a>(&'a self, text: &'a str) -> ScanIter<'a> {
+        let text = text.as_bytes();
+
+        ScanIter {
+            cedar: self,
+            text,
+            base: 0,
+            inner: PrefixIter {
+                cedar: self,
+                key: text,
+                from: 0,
+                i: 0,
+            },
         }
+    }
 
-        #[cfg(not(feature = "reduced-trie"))]
-        {
-            if label != 0 {
-                self.array[e as usize].base_ = -1;
-            } else {
-                self.array[e as usize].base_ = 0;
-            }
-            self.array[e as usize].check = from;
-            if base < 0 {
-                self.array[from as usize].base_ = e ^ (label as i32);
+    /// To return the byte ranges of `text` covered by the keys in the dictionary, with the overlapping and adjacent
+    /// matches of `common_prefix_scan` merged into maximal spans in order, like a highlighter or a redactor needs.
+    pub fn scan_merged_spans(&self, text: &str) -> Vec<(usize, usize)> {
+        let mut spans: Vec<(usize, usize)> = Vec::new();
+        // the matches come ordered by `start`, so a match either extends the last span or starts a new one.
+        for (_, start, end) in self.common_prefix_scan(text) {
+            match spans.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => spans.push((start, end)),
             }
         }
 
-        e
+        spans
     }
 
-    /// Mark an edge `e` as free in a trie node.
-    fn push_e_node(&mut self, e: i32) {
-        let idx = e >> 8;
-        self.blocks[idx as usize].num += 1;
+    /// To return every key in the dictionary that occurs in `text` like `common_prefix_scan`, in a single pass over
+    /// `text` with the Aho-Corasick failure links. The matches are ordered by `end` and then from the longest. The
+    /// failure links are built on the first call after a mutation, see `build_scan_automaton`.
+    pub fn scan_fast(&self, text: &str) -> Vec<(i32, usize, usize)> {
+        let automaton = self.automaton.get_or_init(|| self.build_automaton());
 
-        if self.blocks[idx as usize].num == 1 {
-            self.blocks[idx as usize].e_head = e;
-            self.array[e as usize] = Node { base_: -e, check: -e };
+        let mut matches = Vec::new();
+        let mut from = 0;
+        for (i, &label) in text.as_bytes().iter().enumerate() {
+            from = loop {
+                if let Some(to) = self.step(from, label) {
+                    break to;
+                }
+                if from == 0 {
+                    break 0;
+                }
+                from = automaton.fail[from] as usize;
+            };
 
-            if idx != 0 {
-                // Move the block from 'Full' to 'Closed' since it has one free slot now.
-                self.transfer_block(idx, BlockType::Full, BlockType::Closed, self.blocks_head_closed == 0);
+            let mut x = if self.terminal_value(from).is_some() {
+                from as u32
+            } else {
+                automaton.output[from]
+            };
+            while x != u32::MAX {
+                let value = self.terminal_value(x as usize).unwrap();
+                matches.push((value, i + 1 - automaton.depth[x as usize] as usize, i + 1));
+                x = automaton.output[x as usize];
             }
-        } else {
-            let prev = self.blocks[idx as usize].e_head;
-
-            let next = -self.array[prev as usize].check;
+        }
 
-            // Insert to the edge immediately after the e_head
-            self.array[e as usize] = Node {
-                base_: -prev,
-                check: -next,
-            };
+        matches
+    }
 
-            self.array[prev as usize].check = -e;
-            self.array[next as usize].base_ = -e;
+    /// To build the failure links of `scan_fast` ahead of the first scan. Once built they are also serialized by
+    /// `write_to`, so that a loaded trie can scan without recomputing them. Any mutation drops them.
+    pub fn build_scan_automaton(&self) {
+        self.automaton.get_or_init(|| self.build_automaton());
+    }
 
-            // Move the block from 'Closed' to 'Open' since it has more than one free slot now.
-            if self.blocks[idx as usize].num == 2 || self.blocks[idx as usize].trial == self.max_trial {
-                debug_assert!(self.blocks[idx as usize].num > 1);
-                if idx != 0 {
-                    self.transfer_block(idx, BlockType::Closed, BlockType::Open, self.blocks_head_open == 0);
+    // To compute the failure links level by level from the root, the failure of a node is found by following the
+    // failures of its parent until one of them has the same label.
+    fn build_automaton(&self) -> Automaton {
+        let mut fail = vec![0u32; self.size];
+        let mut output = vec![u32::MAX; self.size];
+        let mut depth = vec![0u32; self.size];
+
+        let mut queue: VecDeque<usize> = VecDeque::new();
+        queue.push_back(0);
+        while let Some(from) = queue.pop_front() {
+            for (c, to) in self.children(from) {
+                if c == 0 {
+                    continue;
                 }
-            }
 
-            // Reset the trial stats
-            self.blocks[idx as usize].trial = 0;
-        }
+                let label = self.label(c);
+                let f = if from == 0 {
+                    0
+                } else {
+                    let mut f = fail[from] as usize;
+                    loop {
+                        if let Some(t) = self.step(f, label) {
+                            break t;
+                        }
+                        if f == 0 {
+                            break 0;
+                        }
+                        f = fail[f] as usize;
+                    }
+                };
 
-        if self.blocks[idx as usize].reject < self.reject[self.blocks[idx as usize].num as usize] {
-            self.blocks[idx as usize].reject = self.reject[self.blocks[idx as usize].num as usize];
+                fail[to] = f as u32;
+                output[to] = if f != 0 && self.terminal_value(f).is_some() {
+                    f as u32
+                } else {
+                    output[f]
+                };
+                depth[to] = depth[from] + 1;
+                queue.push_back(to);
+            }
         }
 
-        self.n_infos[e as usize] = Default::default();
+        Automaton { fail, output, depth }
     }
 
-    // push the `label` into the sibling chain
-    fn push_sibling(&mut self, from: usize, base: i32, label: u8, has_child: bool) {
-        let keep_order: bool = if self.ordered {
-            label > self.n_infos[from].child
-        } else {
-            self.n_infos[from].child == 0
-        };
+    /// To scan like `common_prefix_scan`, with a flag telling whether a longer match exists at the same `start`,
+    /// so that a single pass can serve both the greedy and the all-matches consumers.
+    pub fn common_prefix_scan_annotated<'a>(&'a self, text: &'a str) -> AnnotatedScanIter<'a> {
+        AnnotatedScanIter {
+            inner: self.common_prefix_scan(text).peekable(),
+        }
+    }
 
-        let sibling: u8;
-        {
-            let mut c: &mut u8 = &mut self.n_infos[from as usize].child;
-            if has_child && keep_order {
-                loop {
-                    let code = *c as i32;
-                    c = &mut self.n_infos[(base ^ code) as usize].sibling;
+    /// To scan `text` like `common_prefix_scan` by the maximal munch, for the tokenization. At every position the
+    /// longest match is taken and the scan resumes after its end, so the matches never overlap. A position without
+    /// any match is skipped by a byte.
+    pub fn common_prefix_scan_longest<'a>(&'a self, text: &'a str) -> LongestScanIter<'a> {
+        LongestScanIter {
+            cedar: self,
+            text: text.as_bytes(),
+            base: 0,
+        }
+    }
 
-                    if !(self.ordered && (*c != 0) && (*c < label)) {
-                        break;
-                    }
-                }
+    /// To return the common prefix in the dictionary with the `key` passed in like `common_prefix_search`, as the
+    /// node id, the value and the byte length of each match.
+    pub fn common_prefix_search_verbose(&self, key: &str) -> Vec<(usize, i32, usize)> {
+        let key = key.as_bytes();
+        let mut result = Vec::new();
+        let mut from = 0;
+
+        for i in 0..key.len() {
+            match self.find(&key[i..=i], &mut from) {
+                Some(CEDAR_NO_VALUE) => continue,
+                Some(value) => result.push((from, value, i + 1)),
+                None => break,
             }
-            sibling = *c;
+        }
 
-            *c = label;
+        result
+    }
+
+    /// To return an iterator to iterate through the list of words in the dictionary that has `key` as their prefix.
+    pub fn common_prefix_predict_iter<'a>(&'a self, key: &'a str) -> PrefixPredictIter<'a> {
+        let key = key.as_bytes();
+
+        PrefixPredictIter {
+            cedar: self,
+            key,
+            from: 0,
+            p: 0,
+            root: 0,
+            value: None,
+            started: false,
         }
+    }
 
-        self.n_infos[(base ^ (label as i32)) as usize].sibling = sibling;
+    /// To return the list of words in the dictionary that has `key` as their prefix.
+    pub fn common_prefix_predict(&self, key: &str) -> Option<Vec<(i32, usize)>> {
+        self.common_prefix_predict_iter(key).map(Some).collect()
     }
 
-    // remove the `label` from the sibling chain.
-    #[allow(dead_code)]
-    fn pop_sibling(&mut self, from: i32, base: i32, label: u8) {
-        let mut c: *mut u8 = &mut self.n_infos[from as usize].child;
-        unsafe {
-            while *c != label {
-                let code = *c as i32;
-                c = &mut self.n_infos[(base ^ code) as usize].sibling;
-            }
+    /// To return the first `limit` words of `common_prefix_predict`, for the autocompletion of a short prefix
+    /// with a large subtree. The traversal stops once `limit` words are found.
+    pub fn common_prefix_predict_limit(&self, key: &str, limit: usize) -> Option<Vec<(i32, usize)>> {
+        self.common_prefix_predict_iter(key).take(limit).map(Some).collect()
+    }
 
-            let code = label as i32;
-            *c = self.n_infos[(base ^ code) as usize].sibling;
+    /// To return an iterator over the completions of `prefix` like `common_prefix_predict_iter`, each with its rank
+    /// among them counting from 0. The rank is the position in lexicographical order if the trie is ordered, and
+    /// in the order of the children otherwise.
+    pub fn ranked_completions<'a>(&'a self, prefix: &'a str) -> RankedCompletionsIter<'a> {
+        RankedCompletionsIter {
+            completions: self.common_prefix_predict_iter(prefix),
+            rank: 0,
         }
     }
 
-    // Loop through the siblings to see which one reached the end first, which means it is the one
-    // with smaller in children size, and we should try ti relocate the smaller one.
-    fn consult(&self, base_n: i32, base_p: i32, mut c_n: u8, mut c_p: u8) -> bool {
-        loop {
-            c_n = self.n_infos[(base_n ^ (c_n as i32)) as usize].sibling;
-            c_p = self.n_infos[(base_p ^ (c_p as i32)) as usize].sibling;
+    /// To return both the keys in the dictionary that are prefixes of `key`, like `common_prefix_search`, and the
+    /// keys that extend `key`, like `common_prefix_predict` without `key` itself, in a single traversal.
+    #[allow(clippy::type_complexity)]
+    pub fn related_keys(&self, key: &str) -> (Vec<(i32, usize)>, Vec<(i32, usize)>) {
+        let mut prefixes = Vec::new();
+        let mut from = 0;
+        for (i, &label) in key.as_bytes().iter().enumerate() {
+            from = match self.step(from, label) {
+                Some(to) => to,
+                None => return (prefixes, Vec::new()),
+            };
 
-            if !(c_n != 0 && c_p != 0) {
-                break;
+            if let Some(value) = self.terminal_value(from) {
+                prefixes.push((value, i));
             }
         }
 
-        c_p != 0
-    }
+        let (value, to, p) = self.begin(from, 0);
+        let extensions = PrefixPredictIter {
+            cedar: self,
+            key: &[],
+            from: to,
+            p,
+            root: from,
+            value,
+            started: true,
+        }
+        .filter(|&(_, len)| len > 0)
+        .collect();
 
-    // Collect the list of the children, and push the label as well if it is not terminal node.
-    fn set_child(&self, base: i32, mut c: u8, label: u8, not_terminal: bool) -> SmallVec<[u8; 256]> {
-        let mut child: SmallVec<[u8; 256]> = SmallVec::new();
+        (prefixes, extensions)
+    }
 
-        if c == 0 {
-            child.push(c);
-            c = self.n_infos[(base ^ (c as i32)) as usize].sibling;
+    /// To traverse `key` starting from the node `from`, returning the node id reached if every transition exists.
+    /// The root is the node 0, and the node ids are the ones returned by e.g. `exact_match_search`. A `from` that
+    /// is not a node in use gives `None`.
+    pub fn find_from(&self, from: usize, key: &[u8]) -> Option<usize> {
+        if !self.is_node(from) {
+            return None;
         }
 
-        if self.ordered {
-            while c != 0 && c <= label {
-                child.push(c);
-                c = self.n_infos[(base ^ (c as i32)) as usize].sibling;
+        key.iter().try_fold(from, |from, &label| self.step(from, label))
+    }
+
+    /// To return the node reached by consuming `prefix` from the root, whether or not a key ends there. It is the
+    /// root of the subtree of the keys starting with `prefix`, to be traversed with `find_from` and `value_at`.
+    pub fn prefix_node(&self, prefix: &str) -> Option<usize> {
+        self.find_from(0, prefix.as_bytes())
+    }
+
+    /// To return the `(label, node)` of every transition taken while following `key` from the root, stopping at
+    /// the first byte that has no transition. It is meant for diagnosing unexpected misses.
+    pub fn trace(&self, key: &str) -> Vec<(u8, usize)> {
+        let mut from = 0;
+        let mut path = Vec::new();
+        for &label in key.as_bytes() {
+            match self.step(from, label) {
+                Some(to) => {
+                    path.push((label, to));
+                    from = to;
+                }
+                None => break,
             }
         }
 
-        if not_terminal {
-            child.push(label);
+        path
+    }
+
+    /// To return the byte length of the deepest node of the trie that is on the paths of both `a` and `b`. Unlike
+    /// the common prefix of the two strings, it stops where the trie has no transition for the shared bytes.
+    pub fn shared_prefix_len(&self, a: &str, b: &str) -> usize {
+        let mut from = 0;
+        let mut len = 0;
+        for (&x, &y) in a.as_bytes().iter().zip(b.as_bytes()) {
+            if x != y {
+                break;
+            }
+
+            match self.step(from, x) {
+                Some(to) => from = to,
+                None => break,
+            }
+            len += 1;
         }
 
-        while c != 0 {
-            child.push(c);
-            c = self.n_infos[(base ^ (c as i32)) as usize].sibling;
+        len
+    }
+
+    /// To return the value of the key ending at the node `from`, if the node is terminal. A `from` that is not a
+    /// node in use gives `None`.
+    pub fn value_at(&self, from: usize) -> Option<i32> {
+        if !self.is_node(from) {
+            return None;
         }
 
-        child
+        self.terminal_value(from)
     }
 
-    // For the case where only one free slot is needed
-    fn find_place(&mut self) -> i32 {
-        if self.blocks_head_closed != 0 {
-            return self.blocks[self.blocks_head_closed as usize].e_head;
+    /// To start a session for incremental prediction, with an empty prefix.
+    pub fn predict_session(&self) -> PredictSession<'_> {
+        PredictSession {
+            cedar: self,
+            from: Some(0),
         }
+    }
 
-        if self.blocks_head_open != 0 {
-            return self.blocks[self.blocks_head_open as usize].e_head;
+    /// To return an iterator over every key in the dictionary with its value, in lexicographical order if the
+    /// trie is ordered.
+    pub fn keys(&self) -> KeysIter<'_> {
+        KeysIter {
+            cedar: self,
+            stack: vec![(0, 0, 0)],
+            key: Vec::new(),
         }
+    }
 
-        // the block is not enough, resize it and allocate it.
-        self.add_block() << 8
+    /// To return the `n`-th key in the order of `keys` with its value, counting from 0, for paginating a sorted
+    /// dump. It walks the first `n` keys, descending directly would need the number of keys below every node.
+    pub fn nth_key(&self, n: usize) -> Option<(Vec<u8>, i32)> {
+        self.keys().nth(n)
     }
 
-    // For the case where multiple free slots are needed.
-    fn find_places(&mut self, child: &[u8]) -> i32 {
-        let mut idx = self.blocks_head_open;
+    /// To return the position of `key` in the order of `keys`, counting from 0, if it is in the dictionary. It walks
+    /// the keys before it, while on the trie returned by `assign_ranks` the rank is the value of the key.
+    pub fn rank_of(&self, key: &str) -> Option<usize> {
+        let key = key.as_bytes();
+        self.keys().position(|(k, _)| k == key)
+    }
 
-        // we still have available 'Open' blocks.
-        if idx != 0 {
-            debug_assert!(self.blocks[idx as usize].num > 1);
-            let bz = self.blocks[self.blocks_head_open as usize].prev;
-            let nc = child.len() as i16;
+    /// To return a copy of the trie with the value of every key replaced by its position in the order of `keys`,
+    /// so that the keys are mapped to `0..n` monotonically like a minimal perfect hash over the key set.
+    pub fn assign_ranks(&self) -> Cedar {
+        let mut cedar = Cedar::with_terminal(self.terminal);
+        for (rank, (key, _)) in self.keys().enumerate() {
+            cedar.update_(&key, rank as i32, 0, 0);
+        }
 
-            loop {
-                // only proceed if the free slots are more than the number of children. Also, we
-                // save the minimal number of attempts to fail in the `reject`, it only worths to
-                // try out this block if the number of children is less than that number.
-                if self.blocks[idx as usize].num >= nc && nc < self.blocks[idx as usize].reject {
-                    let mut e = self.blocks[idx as usize].e_head;
-                    loop {
-                        let base = e ^ (child[0] as i32);
+        cedar
+    }
 
-                        let mut i = 1;
-                        // iterate through the children to see if they are available: (check < 0)
-                        while self.array[(base ^ (child[i] as i32)) as usize].check < 0 {
-                            if i == child.len() - 1 {
-                                // we have found the available block.
-                                self.blocks[idx as usize].e_head = e;
-                                return e;
-                            }
-                            i += 1;
-                        }
+    /// To return the value of `key` widened to u64, like `get` of the `Map` in the `fst` crate.
+    pub fn get(&self, key: &str) -> Option<u64> {
+        self.exact_match_search(key).map(|(value, _, _)| value as u64)
+    }
 
-                        // we save the next free block's information in `check`
-                        e = -self.array[e as usize].check;
-                        if e == self.blocks[idx as usize].e_head {
-                            break;
-                        }
-                    }
-                }
+    /// To return the keys starting with `prefix` and their values widened to u64 in lexicographical order, like
+    /// the stream of a `starts_with` search over a `Map` in the `fst` crate.
+    pub fn prefix_stream(&self, prefix: &str) -> PrefixStream<'_> {
+        let prefix = prefix.as_bytes();
+        let keys = match (self.find_from(0, prefix), prefix.split_last()) {
+            (Some(from), Some((&label, rest))) => Some(KeysIter {
+                cedar: self,
+                stack: vec![(from, prefix.len(), label)],
+                key: rest.to_vec(),
+            }),
+            (Some(_), None) => Some(self.keys()),
+            (None, _) => None,
+        };
 
-                // we broke out of the loop, that means we failed. We save the information in
-                // `reject` for future pruning.
-                self.blocks[idx as usize].reject = nc;
-                if self.blocks[idx as usize].reject < self.reject[self.blocks[idx as usize].num as usize] {
-                    // put this stats into the global array of information as well.
-                    self.reject[self.blocks[idx as usize].num as usize] = self.blocks[idx as usize].reject;
-                }
+        PrefixStream { keys }
+    }
 
-                let idx_ = self.blocks[idx as usize].next;
+    /// To call `f` with every key starting with `prefix` and its value, in the same order as `prefix_stream`. The
+    /// key is borrowed from a single buffer reused across the calls, so nothing is allocated per key.
+    pub fn for_each_completion<F: FnMut(&[u8], i32)>(&self, prefix: &str, mut f: F) {
+        if let Some(from) = self.find_from(0, prefix.as_bytes()) {
+            let mut key = prefix.as_bytes().to_vec();
+            self.for_each_completion_(from, &mut key, &mut f);
+        }
+    }
 
-                self.blocks[idx as usize].trial += 1;
+    fn for_each_completion_<F: FnMut(&[u8], i32)>(&self, from: usize, key: &mut Vec<u8>, f: &mut F) {
+        if let Some(value) = self.terminal_value(from) {
+            f(key, value);
+        }
 
-                // move this block to the 'Closed' block list since it has reached the max_trial
-                if self.blocks[idx as usize].trial == self.max_trial {
-                    self.transfer_block(idx, BlockType::Open, BlockType::Closed, self.blocks_head_closed == 0);
-                }
+        for (label, to) in self.children(from) {
+            if label != 0 {
+                key.push(self.label(label));
+                self.for_each_completion_(to, key, f);
+                key.pop();
+            }
+        }
+    }
 
-                // we have finsihed one round of this cyclic doubly-linked-list.
-                if idx == bz {
-                    break;
-                }
+    /// To return the distinct values of the keys starting with `prefix`, like the categories present under it. The
+    /// subtree is walked without spelling out the keys.
+    pub fn prefix_values(&self, prefix: &str) -> HashSet<i32> {
+        let mut values = HashSet::new();
+        let mut stack: Vec<usize> = self.find_from(0, prefix.as_bytes()).into_iter().collect();
+        while let Some(from) = stack.pop() {
+            if let Some(value) = self.terminal_value(from) {
+                values.insert(value);
+            }
+            stack.extend(self.children(from).filter(|&(label, _)| label != 0).map(|(_, to)| to));
+        }
 
-                // going to the next in this linked list group
-                idx = idx_;
+        values
+    }
+
+    /// To return the byte after `prefix` that leads to the most keys, for suggesting how to extend what has been
+    /// typed, preferring the smaller byte on a tie. It is None when no key extends `prefix`.
+    pub fn suggest_next_byte(&self, prefix: &str) -> Option<u8> {
+        let from = self.find_from(0, prefix.as_bytes())?;
+        self.children(from)
+            .filter(|&(label, _)| label != 0)
+            .map(|(label, to)| (self.num_keys_below(to), self.label(label)))
+            .max_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)))
+            .map(|(_, byte)| byte)
+    }
+
+    // To count the keys in the subtree of `from`, including the one ending at `from`.
+    fn num_keys_below(&self, from: usize) -> usize {
+        let mut count = 0;
+        let mut stack = vec![from];
+        while let Some(from) = stack.pop() {
+            if self.terminal_value(from).is_some() {
+                count += 1;
             }
+            stack.extend(self.children(from).filter(|&(label, _)| label != 0).map(|(_, to)| to));
         }
 
-        self.add_block() << 8
+        count
     }
 
-    // resolve the conflict by moving one of the the nodes to a free block.
-    fn resolve(&mut self, mut from_n: usize, base_n: i32, label_n: u8) -> i32 {
-        let to_pn = base_n ^ (label_n as i32);
+    /// To return a set of prefixes such that every key in the dictionary has exactly one of them as a prefix. There
+    /// is one prefix for each distinct first byte of the keys, extended down the trie as long as the keys under it
+    /// share it, i.e. cut at the first node that branches or is a key itself. They are in lexicographical order.
+    pub fn prefix_cover(&self) -> Vec<Vec<u8>> {
+        let mut cover = Vec::new();
+        for (label, to) in self.children(0) {
+            if label == 0 {
+                continue;
+            }
 
-        // the `base` and `from` for the conflicting one.
-        let from_p = self.array[to_pn as usize].check;
-        let base_p = self.array[from_p as usize].base();
+            let mut from = to;
+            let mut prefix = vec![self.label(label)];
+            loop {
+                let mut children = self.children(from);
+                match (children.next(), children.next()) {
+                    (Some((c, to)), None) if c != 0 => {
+                        prefix.push(self.label(c));
+                        from = to;
+                    }
+                    _ => break,
+                }
+            }
+            cover.push(prefix);
+        }
 
-        // whether to replace siblings of newly added
-        let flag = self.consult(
-            base_n,
-            base_p,
-            self.n_infos[from_n as usize].child,
-            self.n_infos[from_p as usize].child,
-        );
+        cover
+    }
 
-        // collect the list of children for the block that we are going to relocate.
-        let children = if flag {
-            self.set_child(base_n, self.n_infos[from_n as usize].child, label_n, true)
-        } else {
-            self.set_child(base_p, self.n_infos[from_p as usize].child, 255, false)
+    /// To return an iterator over the keys that no other key in the dictionary extends, with their values, in the
+    /// same order as `keys`.
+    pub fn leaf_keys(&self) -> LeafKeysIter<'_> {
+        LeafKeysIter { keys: self.keys() }
+    }
+
+    /// To return the keys that differ from `baseline` in lexicographical order, with the new value for the keys
+    /// added or changed and `None` for the keys removed, by merging the key iterations of the two tries. Applying
+    /// them to `baseline` with `update` and `erase` gives the same dictionary, which serves delta replication.
+    pub fn changed_since(&self, baseline: &Cedar) -> Vec<(Vec<u8>, Option<i32>)> {
+        let sorted = |cedar: &Cedar| {
+            let mut keys: Vec<(Vec<u8>, i32)> = cedar.keys().collect();
+            // a custom terminal swaps the order of the bytes 0 and `terminal`, so the keys are in byte order
+            // only for the ordered trie with the default terminal.
+            if !(cedar.ordered && cedar.terminal == 0) {
+                keys.sort_unstable();
+            }
+            keys
         };
+        let (ours, theirs) = (sorted(self), sorted(baseline));
+
+        let mut changes = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < ours.len() || j < theirs.len() {
+            let order = match (ours.get(i), theirs.get(j)) {
+                (Some(a), Some(b)) => a.0.cmp(&b.0),
+                (Some(_), None) => std::cmp::Ordering::Less,
+                _ => std::cmp::Ordering::Greater,
+            };
 
-        // decide which algorithm to allocate free block depending on the number of children we
-        // have.
-        let mut base = if children.len() == 1 {
-            self.find_place()
+            match order {
+                std::cmp::Ordering::Less => {
+                    changes.push((ours[i].0.clone(), Some(ours[i].1)));
+                    i += 1;
+                }
+                std::cmp::Ordering::Greater => {
+                    changes.push((theirs[j].0.clone(), None));
+                    j += 1;
+                }
+                std::cmp::Ordering::Equal => {
+                    if ours[i].1 != theirs[j].1 {
+                        changes.push((ours[i].0.clone(), Some(ours[i].1)));
+                    }
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+
+        changes
+    }
+
+    /// To return the keys that are in both `self` and `other`, with their values in `self`, in the same order as
+    /// `keys`. The two tries are walked together, so only the paths they share are visited.
+    pub fn keys_intersection(&self, other: &Cedar) -> Vec<(Vec<u8>, i32)> {
+        let mut result = Vec::new();
+        self.keys_intersection_(other, 0, 0, &mut Vec::new(), &mut result);
+        result
+    }
+
+    fn keys_intersection_(
+        &self,
+        other: &Cedar,
+        from: usize,
+        other_from: usize,
+        key: &mut Vec<u8>,
+        result: &mut Vec<(Vec<u8>, i32)>,
+    ) {
+        if let Some(value) = self.terminal_value(from) {
+            if other.terminal_value(other_from).is_some() {
+                result.push((key.clone(), value));
+            }
+        }
+
+        for (label, to) in self.children(from) {
+            if label == 0 {
+                continue;
+            }
+
+            let label = self.label(label);
+            if let Some(other_to) = other.step(other_from, label) {
+                key.push(label);
+                self.keys_intersection_(other, to, other_to, key, result);
+                key.pop();
+            }
+        }
+    }
+
+    /// To return the Jaccard index of the key sets of `self` and `other`, the number of the keys in both divided by
+    /// the number of the distinct keys in either, ignoring the values. Two empty tries are identical with 1.0.
+    pub fn jaccard_similarity(&self, other: &Cedar) -> f64 {
+        let both = self.keys_intersection(other).len();
+        let either = self.keys().count() + other.keys().count() - both;
+        if either == 0 {
+            1.0
+        } else {
+            both as f64 / either as f64
+        }
+    }
+
+    /// To return the keys containing `infix` anywhere, with their values, in the same order as `keys`. Every key
+    /// of the dictionary is enumerated and searched, so it is O(total key bytes) rather than a trie query.
+    pub fn keys_containing(&self, infix: &str) -> Vec<(Vec<u8>, i32)> {
+        let infix = infix.as_bytes();
+        self.keys()
+            .filter(|(key, _)| infix.is_empty() || key.windows(infix.len()).any(|w| w == infix))
+            .collect()
+    }
+
+    /// To return an iterator over the nodes of the trie in breadth-first order from the root, with the path of
+    /// labels leading to each of them. The terminal slots holding the values are not themselves visited.
+    pub fn bfs(&self) -> BfsIter<'_> {
+        BfsIter {
+            cedar: self,
+            queue: VecDeque::from(vec![(0, Vec::new())]),
+        }
+    }
+
+    /// To check if every proper prefix of every key is also a key in the dictionary. The prefixes are taken byte
+    /// by byte, so a key with a multibyte character is never prefix-closed.
+    pub fn is_prefix_closed(&self) -> bool {
+        // every node but the root is on the path of some key, so all of them have to be terminal.
+        self.bfs().skip(1).all(|(from, _)| self.terminal_value(from).is_some())
+    }
+
+    /// To return up to `n` keys chosen uniformly at random by a reservoir sample over `keys`. The same `seed`
+    /// gives the same sample for the same trie.
+    pub fn sample_keys(&self, n: usize, seed: u64) -> Vec<Vec<u8>> {
+        let mut state = seed;
+        let mut sample: Vec<Vec<u8>> = Vec::with_capacity(n);
+        for (i, (key, _)) in self.keys().enumerate() {
+            if i < n {
+                sample.push(key);
+            } else {
+                let j = (splitmix64(&mut state) % (i as u64 + 1)) as usize;
+                if j < n {
+                    sample[j] = key;
+                }
+            }
+        }
+
+        sample
+    }
+
+    /// To return every key in the dictionary with its value and the node id it ends at, the same id as the one
+    /// returned by `exact_match_search`.
+    pub fn entries_with_ids(&self) -> Vec<(Vec<u8>, i32, usize)> {
+        let mut iter = self.keys();
+        let mut entries = Vec::new();
+        while let Some(entry) = iter.next_entry() {
+            entries.push(entry);
+        }
+
+        entries
+    }
+
+    /// To return the keys matching `pattern` with their values, where every key has exactly one byte for each of
+    /// the byte classes in the pattern. Only the children matching the class are followed at each depth.
+    pub fn pattern_search(&self, pattern: &[ByteClass]) -> Vec<(Vec<u8>, i32)> {
+        let mut result = Vec::new();
+        let mut key = Vec::with_capacity(pattern.len());
+        self.pattern_search_(0, pattern, &mut key, &mut result);
+        result
+    }
+
+    fn pattern_search_(&self, from: usize, pattern: &[ByteClass], key: &mut Vec<u8>, result: &mut Vec<(Vec<u8>, i32)>) {
+        let class = match pattern.first() {
+            Some(class) => class,
+            None => {
+                if let Some(value) = self.terminal_value(from) {
+                    result.push((key.clone(), value));
+                }
+                return;
+            }
+        };
+
+        if let ByteClass::Exact(label) = class {
+            if let Some(to) = self.step(from, *label) {
+                key.push(*label);
+                self.pattern_search_(to, &pattern[1..], key, result);
+                key.pop();
+            }
+            return;
+        }
+
+        for (label, to) in self.children(from) {
+            let label_ = self.label(label);
+            if label != 0 && class.matches(label_) {
+                key.push(label_);
+                self.pattern_search_(to, &pattern[1..], key, result);
+                key.pop();
+            }
+        }
+    }
+
+    /// To return the keys with the same length as `key` that differ from it in at most `max_bits` bits, with
+    /// their values. The branches are pruned as soon as the bits differing so far exceed the budget.
+    pub fn hamming_search(&self, key: &[u8], max_bits: usize) -> Vec<(Vec<u8>, i32)> {
+        let mut result = Vec::new();
+        let mut path = Vec::with_capacity(key.len());
+        self.hamming_search_(0, key, max_bits, &mut path, &mut result);
+        result
+    }
+
+    fn hamming_search_(
+        &self,
+        from: usize,
+        key: &[u8],
+        budget: usize,
+        path: &mut Vec<u8>,
+        result: &mut Vec<(Vec<u8>, i32)>,
+    ) {
+        let expected = match key.first() {
+            Some(expected) => *expected,
+            None => {
+                if let Some(value) = self.terminal_value(from) {
+                    result.push((path.clone(), value));
+                }
+                return;
+            }
+        };
+
+        for (label, to) in self.children(from) {
+            let label_ = self.label(label);
+            let bits = (label_ ^ expected).count_ones() as usize;
+            if label != 0 && bits <= budget {
+                path.push(label_);
+                self.hamming_search_(to, &key[1..], budget - bits, path, result);
+                path.pop();
+            }
+        }
+    }
+
+    /// To return the keys of exactly `len` bytes with their values, in the same order as `keys`. The traversal
+    /// never goes deeper than `len`, which suits the dictionaries of fixed-width codes.
+    pub fn keys_of_len(&self, len: usize) -> Vec<(Vec<u8>, i32)> {
+        let mut result = Vec::new();
+        let mut key = Vec::with_capacity(len);
+        self.keys_of_len_(0, len, &mut key, &mut result);
+        result
+    }
+
+    fn keys_of_len_(&self, from: usize, len: usize, key: &mut Vec<u8>, result: &mut Vec<(Vec<u8>, i32)>) {
+        if key.len() == len {
+            if let Some(value) = self.terminal_value(from) {
+                result.push((key.clone(), value));
+            }
+            return;
+        }
+
+        for (label, to) in self.children(from) {
+            if label != 0 {
+                key.push(self.label(label));
+                self.keys_of_len_(to, len, key, result);
+                key.pop();
+            }
+        }
+    }
+
+    /// To return the keys that are `key` with two adjacent bytes swapped, with their values, in the order of the
+    /// position swapped. It catches the typos like "teh" for "the" with one exact match per position, and `key`
+    /// itself is not included, nor the swaps of two equal bytes.
+    pub fn transposition_search(&self, key: &str) -> Vec<(Vec<u8>, i32)> {
+        let mut swapped = key.as_bytes().to_vec();
+        let mut result = Vec::new();
+        for i in 1..swapped.len() {
+            if swapped[i - 1] == swapped[i] {
+                continue;
+            }
+
+            swapped.swap(i - 1, i);
+            let mut from = 0;
+            match self.find(&swapped, &mut from) {
+                Some(value) if value != CEDAR_NO_VALUE => result.push((swapped.clone(), value)),
+                _ => {}
+            }
+            swapped.swap(i - 1, i);
+        }
+
+        result
+    }
+
+    /// To return up to `limit` keys whose initial segment is within the edit distance `max_distance` of `prefix`,
+    /// with their values, for the completion that tolerates typos in what has been typed. Every trie path is
+    /// matched against `prefix` with a row of the Levenshtein table, pruned once the whole row exceeds the budget,
+    /// and the completions are collected at the shallowest node within it.
+    pub fn fuzzy_predict(&self, prefix: &str, max_distance: usize, limit: usize) -> Vec<(Vec<u8>, i32)> {
+        let prefix = prefix.as_bytes();
+        let row: Vec<usize> = (0..=prefix.len()).collect();
+        let mut result = Vec::new();
+        self.fuzzy_predict_(0, prefix, &row, max_distance, limit, &mut Vec::new(), &mut result);
+        result
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn fuzzy_predict_(
+        &self,
+        from: usize,
+        prefix: &[u8],
+        row: &[usize],
+        max_distance: usize,
+        limit: usize,
+        key: &mut Vec<u8>,
+        result: &mut Vec<(Vec<u8>, i32)>,
+    ) {
+        if result.len() >= limit {
+            return;
+        }
+
+        // the path is close enough to the whole prefix, so everything below it is a completion.
+        if row[prefix.len()] <= max_distance {
+            self.for_each_completion_(from, key, &mut |k, v| {
+                if result.len() < limit {
+                    result.push((k.to_vec(), v));
+                }
+            });
+            return;
+        }
+
+        for (label, to) in self.children(from) {
+            if label == 0 {
+                continue;
+            }
+
+            let label = self.label(label);
+            let mut next = Vec::with_capacity(row.len());
+            next.push(row[0] + 1);
+            for (i, &c) in prefix.iter().enumerate() {
+                let cost = if c == label { 0 } else { 1 };
+                next.push((row[i] + cost).min(row[i + 1] + 1).min(next[i] + 1));
+            }
+
+            if next.iter().any(|&d| d <= max_distance) {
+                key.push(label);
+                self.fuzzy_predict_(to, prefix, &next, max_distance, limit, key, result);
+                key.pop();
+            }
+        }
+    }
+
+    /// To return the keys within the edit distance `max_distance` of `key`, as their values, the keys and their
+    /// distances, for the lookups that tolerate typos. Like `fuzzy_predict` every trie path carries a row of the
+    /// Levenshtein table against `key`, and a branch is pruned once the smallest distance in its row exceeds the
+    /// budget, so only a small part of the trie is visited for a small `max_distance`.
+    pub fn fuzzy_search(&self, key: &str, max_distance: usize) -> Vec<(i32, Vec<u8>, usize)> {
+        let key = key.as_bytes();
+        let row: Vec<usize> = (0..=key.len()).collect();
+        let mut result = Vec::new();
+        self.fuzzy_search_(0, key, &row, max_distance, &mut Vec::new(), &mut result);
+        result
+    }
+
+    // To collect the keys below `from` for `fuzzy_search`, returning the number of nodes visited.
+    fn fuzzy_search_(
+        &self,
+        from: usize,
+        key: &[u8],
+        row: &[usize],
+        max_distance: usize,
+        path: &mut Vec<u8>,
+        result: &mut Vec<(i32, Vec<u8>, usize)>,
+    ) -> usize {
+        let distance = row[key.len()];
+        if distance <= max_distance {
+            if let Some(value) = self.terminal_value(from) {
+                result.push((value, path.clone(), distance));
+            }
+        }
+
+        let mut visited = 1;
+        for (label, to) in self.children(from) {
+            if label == 0 {
+                continue;
+            }
+
+            let label = self.label(label);
+            let mut next = Vec::with_capacity(row.len());
+            next.push(row[0] + 1);
+            for (i, &c) in key.iter().enumerate() {
+                let cost = if c == label { 0 } else { 1 };
+                next.push((row[i] + cost).min(row[i + 1] + 1).min(next[i] + 1));
+            }
+
+            if next.iter().any(|&d| d <= max_distance) {
+                path.push(label);
+                visited += self.fuzzy_search_(to, key, &next, max_distance, path, result);
+                path.pop();
+            }
+        }
+
+        visited
+    }
+
+    /// To return the number of keys at each byte depth, where the index `d` holds the number of keys that are `d`
+    /// bytes long.
+    pub fn depth_histogram(&self) -> Vec<usize> {
+        let mut histogram = Vec::new();
+        for (key, _) in self.keys() {
+            if histogram.len() <= key.len() {
+                histogram.resize(key.len() + 1, 0);
+            }
+            histogram[key.len()] += 1;
+        }
+
+        histogram
+    }
+
+    /// To return the keys that share their value with some other key, grouped by the value. Values held by a
+    /// single key are omitted.
+    pub fn duplicate_values(&self) -> HashMap<i32, Vec<Vec<u8>>> {
+        let mut groups: HashMap<i32, Vec<Vec<u8>>> = HashMap::new();
+        for (key, value) in self.keys() {
+            groups.entry(value).or_default().push(key);
+        }
+
+        groups.retain(|_, keys| keys.len() > 1);
+        groups
+    }
+
+    /// To return the number of keys in the dictionary, which is kept up to date by the updates and the erasures.
+    pub fn len(&self) -> usize {
+        self.num_keys
+    }
+
+    /// To check if there is no key in the dictionary.
+    pub fn is_empty(&self) -> bool {
+        self.num_keys == 0
+    }
+
+    /// To return the number of nodes in use, excluding the root.
+    pub fn num_nodes(&self) -> usize {
+        self.array[..self.size].iter().filter(|n| n.check >= 0).count()
+    }
+
+    /// To check the structure of the double array, returning `Err` at the first inconsistency: every node in use
+    /// has to be the child its parent can reach, the sibling chains have to list exactly those children, the free
+    /// lists have to hold as many slots as their blocks count, every block but the block 0 with a free slot has to
+    /// be in exactly one of the 'Open' and 'Closed' lists, and no node may be orphaned. It is meant for testing and
+    /// for checking untrusted input.
+    pub fn validate(&self) -> Result<(), CedarError> {
+        let corrupted = Err(CedarError::Corrupted);
+        let num_blocks = self.size >> 8;
+        if self.size == 0
+            || self.size & 0xff != 0
+            || self.size > self.capacity
+            || self.array.len() < self.size
+            || self.n_infos.len() < self.size
+            || self.blocks.len() < num_blocks
+            || self.check_bounds().is_err()
+        {
+            return corrupted;
+        }
+
+        // the terminal slots hold a value instead of a base, so they have no children to check.
+        let mut num_children = vec![0usize; self.size];
+        let mut is_terminal = vec![false; self.size];
+        for (i, n) in self.array[..self.size].iter().enumerate().skip(1) {
+            let check = n.check;
+            if check < 0 {
+                continue;
+            }
+
+            let parent = self.checked(check)?;
+            #[cfg(feature = "reduced-trie")]
+            {
+                if self.array[parent].base_ >= 0 {
+                    return corrupted;
+                }
+            }
+            let base = self.array[parent].base();
+            if base < 0 || (base ^ i as i32) & !0xff != 0 {
+                return corrupted;
+            }
+            num_children[parent] += 1;
+            is_terminal[i] = base == i as i32;
+        }
+
+        for (from, &n) in num_children.iter().enumerate() {
+            let is_node = from == 0 || (self.array[from].check >= 0 && !is_terminal[from]);
+            if is_node && self.children(from).take(257).count() != n {
+                return corrupted;
+            }
+        }
+
+        // the free list of a block is cyclic, and the `num` of the block 0 counts the root as free.
+        for (idx, block) in self.blocks[..num_blocks].iter().enumerate() {
+            let mut free = 0;
+            let mut e = block.e_head as usize;
+            while block.num > 0 && free < 256 && e >> 8 == idx && self.array[e].check < 0 {
+                free += 1;
+                e = -self.array[e].check as usize;
+                if e == block.e_head as usize {
+                    break;
+                }
+            }
+
+            let expected = if idx == 0 { block.num - 1 } else { block.num };
+            if free != expected.max(0) as usize {
+                return corrupted;
+            }
+        }
+
+        // the 'Full' list is never walked, and like in cedar it can be linked through the block 0.
+        let mut listed = vec![false; num_blocks];
+        for &head in [self.blocks_head_closed, self.blocks_head_open].iter() {
+            if head == 0 {
+                continue;
+            }
+
+            let mut idx = head;
+            loop {
+                let i = idx as usize;
+                if idx <= 0
+                    || i >= num_blocks
+                    || listed[i]
+                    || self.blocks[i].num == 0
+                    || self.blocks[self.blocks[i].next as usize].prev != idx
+                {
+                    return corrupted;
+                }
+                listed[i] = true;
+
+                idx = self.blocks[i].next;
+                if idx == head {
+                    break;
+                }
+            }
+        }
+        if (1..num_blocks).any(|i| self.blocks[i].num > 0 && !listed[i]) {
+            return corrupted;
+        }
+
+        if !self.find_orphans().is_empty() {
+            return corrupted;
+        }
+
+        Ok(())
+    }
+
+    /// To return the nodes that are neither reachable from the root nor on the free list of their block, which
+    /// would be leaked by the erasure. The reachable nodes are found breadth-first from the root along with the
+    /// terminal slots, and the free ones by walking the free list of every block. It is meant for testing.
+    pub fn find_orphans(&self) -> Vec<usize> {
+        let mut seen = vec![false; self.size];
+
+        let mut queue: VecDeque<usize> = VecDeque::new();
+        queue.push_back(0);
+        seen[0] = true;
+        while let Some(from) = queue.pop_front() {
+            // the terminal slots hold a value instead of a base.
+            for (c, to) in self.children(from) {
+                if !seen[to] {
+                    seen[to] = true;
+                    if c != 0 {
+                        queue.push_back(to);
+                    }
+                }
+            }
+        }
+
+        // the `num` of the block 0 counts the root as free, so the walk stops at a slot in use as well.
+        for (idx, block) in self.blocks[..(self.size >> 8)].iter().enumerate() {
+            if block.num == 0 {
+                continue;
+            }
+
+            let mut e = block.e_head as usize;
+            for _ in 0..256 {
+                if e >> 8 != idx || self.array[e].check >= 0 || seen[e] {
+                    break;
+                }
+                seen[e] = true;
+                e = -self.array[e].check as usize;
+            }
+        }
+
+        (0..self.size).filter(|&i| !seen[i]).collect()
+    }
+
+    /// To return the node with the most outgoing labels and the number of them, counted on the sibling chains
+    /// without the terminal slots. The lowest node id wins a tie, and the empty trie reports the root with 0.
+    pub fn max_fanout_node(&self) -> (usize, usize) {
+        (0..self.size)
+            .filter(|&i| i == 0 || self.array[i].check >= 0)
+            .map(|i| (i, self.children(i).filter(|&(c, _)| c != 0).count()))
+            .fold((0, 0), |best, x| if x.1 > best.1 { x } else { best })
+    }
+
+    /// To count how many transitions of the trie are labelled with each byte, leaving out the terminal slots. The
+    /// frequent bytes are the candidates to be given the small labels by a remapping of the alphabet.
+    pub fn label_histogram(&self) -> [usize; 256] {
+        let mut histogram = [0; 256];
+        for i in 1..self.size {
+            let parent = self.array[i].check;
+            if parent < 0 {
+                continue;
+            }
+
+            let label = (self.array[parent as usize].base() ^ i as i32) as u8;
+            if label != 0 {
+                histogram[self.label(label) as usize] += 1;
+            }
+        }
+
+        histogram
+    }
+
+    /// To check without inserting whether `update` of `key` would have to append a block to the array, because the
+    /// new nodes it needs outnumber the free slots in the blocks. It is a lower bound, the insertion can still
+    /// append a block when the free slots are scattered so that no block fits the children relocated by a conflict.
+    pub fn would_resize(&self, key: &str) -> bool {
+        let key = key.as_bytes();
+        let mut from = 0;
+        let mut pos = 0;
+        while pos < key.len() {
+            match self.step(from, key[pos]) {
+                Some(to) => from = to,
+                None => break,
+            }
+            pos += 1;
+        }
+
+        let need = if pos < key.len() {
+            // every new node but the first one is placed as the first child of its parent, and so is the terminal
+            // slot of the last one unless it holds the value itself.
+            let chain = key.len() - pos - 1;
+            let terminal = if cfg!(feature = "reduced-trie") { 0 } else { 1 };
+            self.placement_cost(from, self.label(key[pos])) + chain + terminal
+        } else if self.terminal_value(from).is_none() {
+            self.placement_cost(from, 0)
+        } else {
+            0
+        };
+
+        let free: usize = self.blocks[1..(self.size >> 8)].iter().map(|b| b.num as usize).sum();
+        need > free
+    }
+
+    // To return the number of free slots taken from the free lists when adding the child `label` to `from`.
+    fn placement_cost(&self, from: usize, label: u8) -> usize {
+        #[cfg(feature = "reduced-trie")]
+        let is_leaf = self.array[from].base_ >= 0;
+        #[cfg(not(feature = "reduced-trie"))]
+        let is_leaf = self.array[from].base() < 0;
+
+        // the first child of a leaf is placed anywhere, and a conflict relocates the children along with the new one.
+        if is_leaf {
+            return 1;
+        }
+
+        let to = (self.array[from].base() ^ (label as i32)) as usize;
+        if to < self.size && self.array[to].check < 0 {
+            0
+        } else {
+            self.children(from).count() + 1
+        }
+    }
+
+    /// To check if the trie uses the reduced-trie layout. The layout is selected at compile time for now, so it is
+    /// the same as `REDUCED_TRIE` for every trie.
+    pub fn is_reduced(&self) -> bool {
+        REDUCED_TRIE
+    }
+
+    /// To return the ratio of the nodes in use to the slots allocated, a low ratio after a build suggests that the
+    /// array is sparsely packed.
+    pub fn fill_ratio(&self) -> f64 {
+        self.num_nodes() as f64 / self.capacity as f64
+    }
+
+    /// To return the number of bytes allocated on the heap by the backing vectors.
+    pub fn heap_size(&self) -> usize {
+        self.array.heap_capacity() * mem::size_of::<Node>()
+            + self.n_infos.capacity() * mem::size_of::<NInfo>()
+            + self.blocks.capacity() * mem::size_of::<Block>()
+            + self.reject.capacity() * mem::size_of::<i16>()
+    }
+
+    /// To return the key and node counts of the trie with its allocated sizes, in a single `CedarStats`.
+    pub fn stats(&self) -> CedarStats {
+        CedarStats {
+            num_keys: self.num_keys,
+            num_nodes: self.num_nodes(),
+            capacity: self.capacity,
+            size: self.size,
+            num_blocks: self.size >> 8,
+            estimated_bytes: self.heap_size(),
+        }
+    }
+
+    /// To clone the trie with the backing vectors sized to the region in use, instead of copying the spare
+    /// capacity reserved for growth.
+    pub fn clone_shrunk(&self) -> Self {
+        Cedar {
+            array: Nodes::Heap(self.array[..self.size].to_vec()),
+            n_infos: self.n_infos[..self.size].to_vec(),
+            blocks: self.blocks[..(self.size >> 8)].to_vec(),
+            reject: self.reject.clone(),
+            blocks_head_full: self.blocks_head_full,
+            blocks_head_closed: self.blocks_head_closed,
+            blocks_head_open: self.blocks_head_open,
+            capacity: self.size,
+            size: self.size,
+            ordered: self.ordered,
+            max_trial: self.max_trial,
+            terminal: self.terminal,
+            num_keys: self.num_keys,
+            automaton: self.automaton.clone(),
+        }
+    }
+
+    /// To release the trailing blocks that neither hold a node in use nor the children of one, which a trie keeps
+    /// at its peak size after most of its keys are erased. The blocks are detached from the 'Open' list, where the
+    /// blocks without any node end up, and the backing vectors are truncated and shrunk. The node ids are kept.
+    pub fn shrink_to_fit(&mut self) {
+        // the children of a node are in the block of its base, so that block is needed even without the node.
+        let mut last = 0;
+        for i in (0..self.size).filter(|&i| i == 0 || self.array[i].check >= 0) {
+            last = last.max(i >> 8);
+            let base = self.array[i].base();
+            if !self.holds_value(i) && base >= 0 {
+                last = last.max(base as usize >> 8);
+            }
+        }
+
+        let num_blocks = last + 1;
+        for idx in (num_blocks..(self.size >> 8)).rev() {
+            debug_assert_eq!(self.blocks[idx].num, 256);
+            let is_last = self.blocks[idx].next == idx as i32;
+            self.pop_block(idx as i32, BlockType::Open, is_last);
+        }
+
+        self.size = num_blocks << 8;
+        self.capacity = self.size;
+        // only the mapped nodes of `DiskCedar` can fail to resize, and they are never shrunk.
+        self.array.shrink_to(self.size).expect("failed to resize the nodes");
+        self.n_infos.truncate(self.size);
+        self.n_infos.shrink_to_fit();
+        self.blocks.truncate(num_blocks);
+        self.blocks.shrink_to_fit();
+        self.automaton.take();
+    }
+
+    /// To return a copy of the trie like `clone_shrunk` behind a handle that its clones share without copying the
+    /// arrays again, for serving the same dictionary to many readers.
+    pub fn share(&self) -> SharedCedar {
+        SharedCedar::new(self.clone_shrunk())
+    }
+
+    /// To serialize the whole double array into `w`, so that it can be loaded back by `read_from` without
+    /// rebuilding. The format is little-endian and it is not portable between the layouts with and without the
+    /// `reduced-trie` feature. The failure links of `scan_fast` are included if they have been built.
+    pub fn write_to<W: Write>(&self, w: W) -> io::Result<()> {
+        self.write_to_(w, false)
+    }
+
+    /// To return the number of bytes `write_to` writes, without serializing. It is an upper bound for
+    /// `write_to_packed`.
+    pub fn serialized_len(&self) -> usize {
+        let automaton_len = self.automaton.get().map_or(0, |a| {
+            (a.fail.len() + a.output.len() + a.depth.len()) * mem::size_of::<u32>()
+        });
+
+        48 + self.size * 8 + Cedar::encoded_infos_len(self.size).unwrap() + automaton_len
+    }
+
+    /// To serialize like `write_to`, with the values stored in the narrowest of u8 and u16 that fits all of them
+    /// and the width marked in the header, so that `read_from` widens them back to i32. It shrinks the files of
+    /// the dictionaries with small values like the indices of an enumeration, and it is the same as `write_to`
+    /// when a value does not fit in u16.
+    pub fn write_to_packed<W: Write>(&self, w: W) -> io::Result<()> {
+        self.write_to_(w, true)
+    }
+
+    fn write_to_<W: Write>(&self, mut w: W, packed: bool) -> io::Result<()> {
+        let value_nodes: Vec<bool> = (0..self.size).map(|i| self.holds_value(i)).collect();
+        let width = if !packed {
+            4
+        } else {
+            let fits = |max: i32| (0..self.size).all(|i| !value_nodes[i] || (0..=max).contains(&self.array[i].base_));
+            if fits(u8::MAX as i32) {
+                1
+            } else if fits(u16::MAX as i32) {
+                2
+            } else {
+                4
+            }
+        };
+
+        let mut flags = if cfg!(feature = "reduced-trie") {
+            CEDAR_FLAG_REDUCED
         } else {
-            self.find_places(&children)
+            0
         };
+        if self.automaton.get().is_some() {
+            flags |= CEDAR_FLAG_AUTOMATON;
+        }
+        match width {
+            1 => flags |= CEDAR_FLAG_VALUES_U8,
+            2 => flags |= CEDAR_FLAG_VALUES_U16,
+            _ => {}
+        }
+
+        let mut buf: Vec<u8> = Vec::with_capacity(self.serialized_len());
+        buf.extend_from_slice(CEDAR_MAGIC);
+        buf.extend_from_slice(&CEDAR_FORMAT_VERSION.to_le_bytes());
+        buf.extend_from_slice(&flags.to_le_bytes());
+        buf.extend_from_slice(&(self.size as u64).to_le_bytes());
+        buf.extend_from_slice(&self.blocks_head_full.to_le_bytes());
+        buf.extend_from_slice(&self.blocks_head_closed.to_le_bytes());
+        buf.extend_from_slice(&self.blocks_head_open.to_le_bytes());
+        buf.extend_from_slice(&self.max_trial.to_le_bytes());
+        buf.extend_from_slice(&(self.ordered as u32).to_le_bytes());
+        buf.extend_from_slice(&(self.terminal as u32).to_le_bytes());
+
+        if width == 4 {
+            for n in &self.array[..self.size] {
+                buf.extend_from_slice(&n.base_.to_le_bytes());
+                buf.extend_from_slice(&n.check.to_le_bytes());
+            }
+        } else {
+            // the packed nodes are the checks, the bitmap of the nodes holding a value, the bases of the other
+            // nodes and then the narrowed values.
+            for n in &self.array[..self.size] {
+                buf.extend_from_slice(&n.check.to_le_bytes());
+            }
+            for bits in value_nodes.chunks(8) {
+                buf.push(bits.iter().rev().fold(0, |byte, &bit| (byte << 1) | bit as u8));
+            }
+            for (n, _) in self.array[..self.size].iter().zip(&value_nodes).filter(|x| !*x.1) {
+                buf.extend_from_slice(&n.base_.to_le_bytes());
+            }
+            for (n, _) in self.array[..self.size].iter().zip(&value_nodes).filter(|x| *x.1) {
+                buf.extend_from_slice(&n.base_.to_le_bytes()[..width]);
+            }
+        }
+
+        self.encode_infos(&mut buf);
+
+        if let Some(automaton) = self.automaton.get() {
+            for links in [&automaton.fail, &automaton.output, &automaton.depth].iter() {
+                for x in links.iter() {
+                    buf.extend_from_slice(&x.to_le_bytes());
+                }
+            }
+        }
+
+        w.write_all(&buf)
+    }
+
+    // To check if the `base_` of the node `i` is a value rather than a base, that is a leaf of the reduced trie
+    // or a terminal slot.
+    fn holds_value(&self, i: usize) -> bool {
+        let check = self.array[i].check;
+        if i == 0 || check < 0 {
+            return false;
+        }
+
+        #[cfg(feature = "reduced-trie")]
+        return self.array[i].base_ >= 0;
+        #[cfg(not(feature = "reduced-trie"))]
+        return self.array[check as usize].base() == i as i32;
+    }
+
+    // To count the nodes holding the value of a key by a scan of the array, for the tries loaded from a file.
+    fn count_keys(&self) -> usize {
+        (0..self.size)
+            .filter(|&i| self.holds_value(i))
+            .filter(|&i| self.array[i].base_ != CEDAR_NO_VALUE && self.array[i].base_ != CEDAR_VALUE_LIMIT)
+            .count()
+    }
+
+    // To check that every index stored in the header fields, the blocks and the nodes stays within the array, so
+    // that a trie loaded from a corrupted file is rejected up front instead of panicking on the first traversal.
+    // The bases of the nodes in use are checked for every label, and the free nodes for their links.
+    fn check_bounds(&self) -> io::Result<()> {
+        let size = self.size as i64;
+        let num_blocks = (self.size >> 8) as i64;
+        let in_blocks = |idx: i32| (0..num_blocks).contains(&(idx as i64));
+        if ![self.blocks_head_full, self.blocks_head_closed, self.blocks_head_open]
+            .iter()
+            .all(|&head| in_blocks(head))
+        {
+            return Err(invalid_data("block list head out of range"));
+        }
+        if self.max_trial <= 0 {
+            return Err(invalid_data("invalid max_trial"));
+        }
+        if self.reject.len() != 257 || self.reject.iter().any(|&r| !(0..=257).contains(&r)) {
+            return Err(invalid_data("reject out of range"));
+        }
+
+        for b in &self.blocks[..num_blocks as usize] {
+            if !in_blocks(b.prev)
+                || !in_blocks(b.next)
+                || !(0..=256).contains(&b.num)
+                || !(0..=257).contains(&b.reject)
+                || !(0..size).contains(&(b.e_head as i64))
+            {
+                return Err(invalid_data("block out of range"));
+            }
+        }
+
+        // the parents come first, as `holds_value` looks at the parent of a node.
+        for (i, n) in self.array[..self.size].iter().enumerate() {
+            let in_range = if i == 0 || n.check >= 0 {
+                (n.check as i64) < size
+            } else {
+                -(n.check as i64) < size && n.base_ <= 0 && -(n.base_ as i64) < size
+            };
+            if !in_range {
+                return Err(invalid_data("node out of range"));
+            }
+        }
+
+        for (i, n) in self.array[..self.size].iter().enumerate() {
+            if (i == 0 || n.check >= 0) && !self.holds_value(i) {
+                #[cfg(feature = "reduced-trie")]
+                let base = -(n.base_ as i64 + 1);
+                #[cfg(not(feature = "reduced-trie"))]
+                let base = n.base_ as i64;
+                if base >= 0 && base | 0xff >= size {
+                    return Err(invalid_data("base out of range"));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // To check the failure links loaded for `scan_fast` against the trie. The depth of every node has to be one more
+    // than its parent's, and the `fail` and `output` links have to go to a node of a smaller depth, an `output`
+    // link to one where a key ends, so that the scan never leaves the array nor follows the links forever.
+    fn check_automaton(&self, automaton: &Automaton) -> bool {
+        let Automaton { fail, output, depth } = automaton;
+        let is_node = |i: usize| {
+            let check = self.array[i].check;
+            i == 0 || (check >= 0 && self.array[check as usize].base() != i as i32)
+        };
+
+        if depth[0] != 0 || fail[0] != 0 || output[0] != u32::MAX {
+            return false;
+        }
+
+        (1..self.size).all(|i| {
+            let (f, o) = (fail[i] as usize, output[i]);
+            let parent_depth = if is_node(i) {
+                depth[self.array[i].check as usize].checked_add(1)
+            } else {
+                Some(0)
+            };
+
+            parent_depth == Some(depth[i])
+                && f < self.size
+                && (f == 0 || (is_node(f) && depth[f] < depth[i]))
+                && (o == u32::MAX
+                    || ((o as usize) < self.size
+                        && is_node(o as usize)
+                        && depth[o as usize] < depth[i]
+                        && self.terminal_value(o as usize).is_some()))
+        })
+    }
+
+    // To append the `n_infos`, the `blocks` and the `reject` of the region in use to `buf`, they follow the nodes
+    // in the serialized format.
+    fn encode_infos(&self, buf: &mut Vec<u8>) {
+        for n in &self.n_infos[..self.size] {
+            buf.push(n.sibling);
+            buf.push(n.child);
+        }
+
+        for b in &self.blocks[..(self.size >> 8)] {
+            buf.extend_from_slice(&b.prev.to_le_bytes());
+            buf.extend_from_slice(&b.next.to_le_bytes());
+            buf.extend_from_slice(&b.num.to_le_bytes());
+            buf.extend_from_slice(&b.reject.to_le_bytes());
+            buf.extend_from_slice(&b.trial.to_le_bytes());
+            buf.extend_from_slice(&b.e_head.to_le_bytes());
+        }
+
+        for r in &self.reject {
+            buf.extend_from_slice(&r.to_le_bytes());
+        }
+    }
+
+    // To return the number of bytes written by `encode_infos` for `size` nodes, or `None` on the overflow that
+    // only the `size` of a corrupted header can cause.
+    fn encoded_infos_len(size: usize) -> Option<usize> {
+        size.checked_mul(2)?
+            .checked_add((size >> 8).checked_mul(20)?)?
+            .checked_add(257 * 2)
+    }
+
+    // To decode the output of `encode_infos` for `size` nodes.
+    fn decode_infos(buf: &[u8], size: usize) -> (Vec<NInfo>, Vec<Block>, Vec<i16>) {
+        let (n_infos_buf, rest) = buf.split_at(size * 2);
+        let (blocks_buf, reject_buf) = rest.split_at((size >> 8) * 20);
+
+        let n_infos: Vec<NInfo> = n_infos_buf
+            .chunks_exact(2)
+            .map(|c| NInfo {
+                sibling: c[0],
+                child: c[1],
+            })
+            .collect();
+
+        let blocks: Vec<Block> = blocks_buf
+            .chunks_exact(20)
+            .map(|c| Block {
+                prev: i32::from_le_bytes(c[0..4].try_into().unwrap()),
+                next: i32::from_le_bytes(c[4..8].try_into().unwrap()),
+                num: i16::from_le_bytes(c[8..10].try_into().unwrap()),
+                reject: i16::from_le_bytes(c[10..12].try_into().unwrap()),
+                trial: i32::from_le_bytes(c[12..16].try_into().unwrap()),
+                e_head: i32::from_le_bytes(c[16..20].try_into().unwrap()),
+            })
+            .collect();
+
+        let reject: Vec<i16> = reject_buf
+            .chunks_exact(2)
+            .map(|c| i16::from_le_bytes(c.try_into().unwrap()))
+            .collect();
+
+        (n_infos, blocks, reject)
+    }
+
+    /// To load the double array written by `write_to`. A truncated or corrupted input gives `Err`, and the lengths
+    /// in the header are never trusted for an allocation before the bytes are there.
+    pub fn read_from<R: Read>(mut r: R) -> io::Result<Self> {
+        let mut header = [0u8; 48];
+        r.read_exact(&mut header)?;
+
+        if &header[0..8] != CEDAR_MAGIC {
+            return Err(invalid_data("not a serialized cedar"));
+        }
+
+        let version = u32::from_le_bytes(header[8..12].try_into().unwrap());
+        if version != CEDAR_FORMAT_VERSION {
+            return Err(invalid_data("unsupported format version"));
+        }
+
+        let flags = u32::from_le_bytes(header[12..16].try_into().unwrap());
+        if (flags & CEDAR_FLAG_REDUCED != 0) != cfg!(feature = "reduced-trie") {
+            return Err(invalid_data("mismatched reduced-trie layout"));
+        }
+
+        let size: usize = u64::from_le_bytes(header[16..24].try_into().unwrap())
+            .try_into()
+            .map_err(|_| invalid_data("invalid size"))?;
+        if size == 0 || size & 0xff != 0 {
+            return Err(invalid_data("invalid size"));
+        }
+        let overflow = || invalid_data("invalid size");
+
+        let blocks_head_full = i32::from_le_bytes(header[24..28].try_into().unwrap());
+        let blocks_head_closed = i32::from_le_bytes(header[28..32].try_into().unwrap());
+        let blocks_head_open = i32::from_le_bytes(header[32..36].try_into().unwrap());
+        let max_trial = i32::from_le_bytes(header[36..40].try_into().unwrap());
+        let ordered = u32::from_le_bytes(header[40..44].try_into().unwrap()) != 0;
+        let terminal: u8 = u32::from_le_bytes(header[44..48].try_into().unwrap())
+            .try_into()
+            .map_err(|_| invalid_data("invalid terminal label"))?;
+
+        let width = if flags & CEDAR_FLAG_VALUES_U8 != 0 {
+            1
+        } else if flags & CEDAR_FLAG_VALUES_U16 != 0 {
+            2
+        } else {
+            4
+        };
+
+        let array: Vec<Node> = if width == 4 {
+            let buf = read_len(&mut r, size.checked_mul(8).ok_or_else(overflow)?)?;
+            buf.chunks_exact(8)
+                .map(|c| Node {
+                    base_: i32::from_le_bytes(c[0..4].try_into().unwrap()),
+                    check: i32::from_le_bytes(c[4..8].try_into().unwrap()),
+                })
+                .collect()
+        } else {
+            let len = size.checked_mul(4).and_then(|n| n.checked_add(size / 8));
+            let buf = read_len(&mut r, len.ok_or_else(overflow)?)?;
+            let (checks, bitmap) = buf.split_at(size * 4);
+            let value_nodes: Vec<bool> = (0..size).map(|i| bitmap[i / 8] & (1 << (i % 8)) != 0).collect();
+            let num_values = value_nodes.iter().filter(|&&bit| bit).count();
+
+            let bases = read_len(&mut r, (size - num_values) * 4)?;
+            let values = read_len(&mut r, num_values * width)?;
+            let mut bases = bases.chunks_exact(4).map(|c| i32::from_le_bytes(c.try_into().unwrap()));
+            let mut values = values.chunks_exact(width).map(|c| {
+                let mut v = [0u8; 4];
+                v[..width].copy_from_slice(c);
+                i32::from_le_bytes(v)
+            });
+
+            checks
+                .chunks_exact(4)
+                .zip(value_nodes)
+                .map(|(c, is_value)| Node {
+                    base_: if is_value { values.next() } else { bases.next() }.unwrap(),
+                    check: i32::from_le_bytes(c.try_into().unwrap()),
+                })
+                .collect()
+        };
+
+        let buf = read_len(&mut r, Cedar::encoded_infos_len(size).ok_or_else(overflow)?)?;
+        let (n_infos, blocks, reject) = Cedar::decode_infos(&buf, size);
+
+        // the failure links are optional, without them `scan_fast` builds them on the first call.
+        let mut automaton = None;
+        if flags & CEDAR_FLAG_AUTOMATON != 0 {
+            let buf = read_len(&mut r, size.checked_mul(12).ok_or_else(overflow)?)?;
+            let mut links = buf.chunks_exact(size * 4).map(|c| {
+                c.chunks_exact(4)
+                    .map(|x| u32::from_le_bytes(x.try_into().unwrap()))
+                    .collect()
+            });
+            automaton = Some(Automaton {
+                fail: links.next().unwrap(),
+                output: links.next().unwrap(),
+                depth: links.next().unwrap(),
+            });
+        }
+
+        let mut cedar = Cedar {
+            array: Nodes::Heap(array),
+            n_infos,
+            blocks,
+            reject,
+            blocks_head_full,
+            blocks_head_closed,
+            blocks_head_open,
+            capacity: size,
+            size,
+            ordered,
+            max_trial,
+            terminal,
+            num_keys: 0,
+            automaton: OnceLock::new(),
+        };
+        cedar.check_bounds()?;
+        cedar.num_keys = cedar.count_keys();
+
+        // the corrupted failure links are dropped, and `scan_fast` builds them again.
+        if let Some(automaton) = automaton.filter(|a| cedar.check_automaton(a)) {
+            let _ = cedar.automaton.set(automaton);
+        }
+
+        Ok(cedar)
+    }
+
+    /// To check that the dictionary survives `write_to` and `write_to_packed` followed by `read_from`, panicking
+    /// with the keys that differ after the round trip. It is meant for the tests of the users, to make sure their
+    /// dictionaries persist as they are.
+    pub fn assert_roundtrip(&self) {
+        for &packed in [false, true].iter() {
+            let mut buf = Vec::new();
+            self.write_to_(&mut buf, packed).expect("failed to serialize");
+            let loaded = Cedar::read_from(&buf[..]).expect("failed to deserialize");
+
+            let changes = loaded.changed_since(self);
+            if !changes.is_empty() {
+                let diff: Vec<String> = changes
+                    .iter()
+                    .map(|(key, value)| {
+                        let key = String::from_utf8_lossy(key);
+                        match (self.exact_match_search_bytes(key.as_bytes()), value) {
+                            (Some((old, _, _)), Some(new)) => format!("{:?}: {} -> {}", key, old, new),
+                            (Some((old, _, _)), None) => format!("{:?}: {} -> missing", key, old),
+                            (None, Some(new)) => format!("{:?}: missing -> {}", key, new),
+                            (None, None) => format!("{:?}", key),
+                        }
+                    })
+                    .collect();
+                panic!(
+                    "round trip of {} changed {} keys:\n{}",
+                    if packed { "write_to_packed" } else { "write_to" },
+                    changes.len(),
+                    diff.join("\n")
+                );
+            }
+        }
+    }
+
+    /// To take a snapshot of the trie for crash recovery, it is the same as `write_to`. The updates made after
+    /// the snapshot are expected to be recorded with `log_update` and `log_erase`, and `restore` replays them.
+    pub fn snapshot<W: Write>(&self, w: W) -> io::Result<()> {
+        self.write_to(w)
+    }
+
+    /// To append an update record of `key` and `value` to the operation log.
+    pub fn log_update<W: Write>(w: W, key: &str, value: i32) -> io::Result<()> {
+        write_log_record(w, LOG_OP_UPDATE, key.as_bytes(), value)
+    }
+
+    /// To append an erase record of `key` to the operation log.
+    pub fn log_erase<W: Write>(w: W, key: &str) -> io::Result<()> {
+        write_log_record(w, LOG_OP_ERASE, key.as_bytes(), 0)
+    }
+
+    /// To load the `snapshot` and replay the records in `log` onto it in order.
+    pub fn restore<S: Read, L: Read>(snapshot: S, log: L) -> io::Result<Self> {
+        let mut cedar = Cedar::read_from(snapshot)?;
+        cedar.replay_log(log)?;
+        Ok(cedar)
+    }
+
+    /// To apply the update and erase records of an operation log in order, returning the number of records
+    /// applied. A record is laid out as `op: u8, key_len: u32, key bytes, value: i32` in little-endian, where `op` is
+    /// 0 for update and 1 for erase, so the log can be appended to a file as the updates happen.
+    pub fn replay_log<R: Read>(&mut self, mut log: R) -> io::Result<usize> {
+        let mut count = 0;
+        let mut op = [0u8; 1];
+        loop {
+            // the log is allowed to end only at the boundary of a record.
+            if log.read(&mut op)? == 0 {
+                break;
+            }
+
+            let mut len = [0u8; 4];
+            log.read_exact(&mut len)?;
+            let key = read_len(&mut log, u32::from_le_bytes(len) as usize)?;
+            let mut value = [0u8; 4];
+            log.read_exact(&mut value)?;
+
+            if key.is_empty() {
+                return Err(invalid_data("zero-length key in the log"));
+            }
+            if op[0] == LOG_OP_UPDATE && key.contains(&self.terminal) {
+                return Err(invalid_data("terminal label in a key of the log"));
+            }
+
+            match op[0] {
+                LOG_OP_UPDATE => {
+                    self.update_(&key, i32::from_le_bytes(value), 0, 0);
+                }
+                LOG_OP_ERASE => self.erase_(&key),
+                _ => return Err(invalid_data("unknown op in the log")),
+            }
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// To write every key and its value in lexicographical order to `w`, as the little-endian u32 length of the
+    /// key, the key and the little-endian i32 value. It is a compact and portable alternative to `write_to`
+    /// that has to be rebuilt by `import_entries`.
+    pub fn export_entries<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let mut buf: Vec<u8> = Vec::new();
+        for (key, value) in self.keys() {
+            buf.extend_from_slice(&(key.len() as u32).to_le_bytes());
+            buf.extend_from_slice(&key);
+            buf.extend_from_slice(&value.to_le_bytes());
+        }
+
+        w.write_all(&buf)
+    }
+
+    /// To build the trie from the entries written by `export_entries`.
+    pub fn import_entries<R: Read>(mut r: R) -> io::Result<Self> {
+        let mut cedar = Cedar::new();
+        while let Some((key, value)) = read_entry(&mut r)? {
+            if key.is_empty() {
+                return Err(invalid_data("zero-length key in the entries"));
+            }
+            if key.contains(&cedar.terminal) {
+                return Err(invalid_data("terminal label in a key of the entries"));
+            }
+
+            cedar.update_(&key, value, 0, 0);
+        }
+
+        Ok(cedar)
+    }
+
+    /// To insert the entries read from `r`, one `key\tvalue` per line, in lexicographical order of the keys,
+    /// returning the number of entries read. The entries are sorted in runs of a bounded size spilled to files in
+    /// `temp_dir`, which are then merged while inserting, so the input is never held in memory whole. A key given
+    /// more than once ends up with its last value like in `build`, and the run files are removed at the end.
+    pub fn build_external_sorted<R: BufRead>(&mut self, r: R, temp_dir: &Path) -> io::Result<usize> {
+        self.build_external_sorted_(r, temp_dir, EXTERNAL_SORT_RUN_LEN)
+    }
+
+    fn build_external_sorted_<R: BufRead>(&mut self, r: R, temp_dir: &Path, run_len: usize) -> io::Result<usize> {
+        let mut paths = Vec::new();
+        let result = self.sort_runs(r, temp_dir, run_len, &mut paths);
+        for path in paths.iter() {
+            let _ = fs::remove_file(path);
+        }
+
+        result
+    }
+
+    fn sort_runs<R: BufRead>(
+        &mut self,
+        mut r: R,
+        temp_dir: &Path,
+        run_len: usize,
+        paths: &mut Vec<PathBuf>,
+    ) -> io::Result<usize> {
+        let mut count = 0;
+        let mut run: Vec<(Vec<u8>, i32)> = Vec::new();
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let end = r.read_line(&mut line)? == 0;
+            if !run.is_empty() && (end || run.len() == run_len) {
+                // the sort is stable, so the entries of the same key stay in the order they were read.
+                run.sort_by(|a, b| a.0.cmp(&b.0));
+                let path = temp_dir.join(format!("cedarwood-run-{}-{}.bin", process::id(), paths.len()));
+                let mut w = io::BufWriter::new(fs::File::create(&path)?);
+                paths.push(path);
+                for (key, value) in run.drain(..) {
+                    w.write_all(&(key.len() as u32).to_le_bytes())?;
+                    w.write_all(&key)?;
+                    w.write_all(&value.to_le_bytes())?;
+                }
+                w.flush()?;
+            }
+            if end {
+                break;
+            }
+
+            let entry = line.trim_end_matches(['\n', '\r']);
+            let (key, value) = entry
+                .rsplit_once('\t')
+                .ok_or_else(|| invalid_data("no tab between the key and the value"))?;
+            let value: i32 = value.parse().map_err(|_| invalid_data("malformed value"))?;
+            if key.is_empty() {
+                return Err(invalid_data("zero-length key in the input"));
+            }
+            if key.as_bytes().contains(&self.terminal) {
+                return Err(invalid_data("terminal label in a key of the input"));
+            }
+
+            run.push((key.as_bytes().to_vec(), value));
+            count += 1;
+        }
+
+        // the k-way merge takes the smallest key among the heads of the runs, the earlier run first on a tie.
+        let mut runs = Vec::with_capacity(paths.len());
+        let mut heads = BinaryHeap::new();
+        for (i, path) in paths.iter().enumerate() {
+            let mut run = io::BufReader::new(fs::File::open(path)?);
+            if let Some((key, value)) = read_entry(&mut run)? {
+                heads.push(Reverse((key, i, value)));
+            }
+            runs.push(run);
+        }
+
+        while let Some(Reverse((key, i, value))) = heads.pop() {
+            self.update_(&key, value, 0, 0);
+            if let Some((key, value)) = read_entry(&mut runs[i])? {
+                heads.push(Reverse((key, i, value)));
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// To erase the keys read from `r`, one per line without the line ending, returning the number of keys that
+    /// were in the dictionary and got removed. The lines are read one at a time, so the list is never loaded whole.
+    pub fn erase_lines<R: BufRead>(&mut self, mut r: R) -> io::Result<usize> {
+        let mut count = 0;
+        let mut line = String::new();
+        while r.read_line(&mut line)? > 0 {
+            let key = line.trim_end_matches(['\n', '\r']);
+            if self.exact_match_search(key).is_some() {
+                self.erase(key);
+                count += 1;
+            }
+            line.clear();
+        }
+
+        Ok(count)
+    }
+
+    // To move from the node `from` by following `label`, without inserting anything. The label 0 is the
+    // terminal slot and never a transition.
+    fn step(&self, from: usize, label: u8) -> Option<usize> {
+        let label = self.label(label);
+        if label == 0 {
+            return None;
+        }
+
+        #[cfg(feature = "reduced-trie")]
+        {
+            if self.array[from].base_ >= 0 {
+                return None;
+            }
+        }
+
+        let base = self.array[from].base();
+        if base < 0 {
+            return None;
+        }
+
+        let to = (base ^ (label as i32)) as usize;
+        if to < self.size && self.array[to].check == from as i32 {
+            Some(to)
+        } else {
+            None
+        }
+    }
+
+    // To check if the node id `from` supplied by the caller is the root or a node in use, so that it is safe to
+    // start a traversal from it.
+    fn is_node(&self, from: usize) -> bool {
+        from == 0 || (from < self.size && self.array[from].check >= 0)
+    }
+
+    // To iterate through the children of `from`, including the terminal slot labelled 0.
+    fn children(&self, from: usize) -> Children<'_> {
+        let base = self.array[from].base();
+
+        #[cfg(feature = "reduced-trie")]
+        let is_leaf = self.array[from].base_ >= 0;
+        #[cfg(not(feature = "reduced-trie"))]
+        let is_leaf = base < 0;
+
+        Children {
+            cedar: self,
+            from,
+            base,
+            c: if is_leaf { None } else { Some(self.n_infos[from].child) },
+        }
+    }
+
+    // To get the value of the key ending at the node `from`, if there is one.
+    fn terminal_value(&self, from: usize) -> Option<i32> {
+        #[cfg(feature = "reduced-trie")]
+        {
+            let v = self.array[from].base_;
+            if v >= 0 {
+                return if v == CEDAR_VALUE_LIMIT { None } else { Some(v) };
+            }
+        }
+
+        let base = self.array[from].base();
+        if base < 0 || base as usize >= self.size {
+            return None;
+        }
+
+        let n = &self.array[base as usize];
+        #[cfg(feature = "reduced-trie")]
+        let has_value = n.check == from as i32 && n.base_ != CEDAR_VALUE_LIMIT && n.base_ != CEDAR_NO_VALUE;
+        #[cfg(not(feature = "reduced-trie"))]
+        let has_value = n.check == from as i32 && n.base_ != CEDAR_NO_VALUE;
+
+        if has_value {
+            Some(n.base_)
+        } else {
+            None
+        }
+    }
+
+    // To get the cursor of the first leaf node starting by `from`
+    fn begin(&self, mut from: usize, mut p: usize) -> (Option<i32>, usize, usize) {
+        let base = self.array[from].base();
+        let mut c = self.n_infos[from].child;
+
+        if from == 0 {
+            c = self.n_infos[(base ^ (c as i32)) as usize].sibling;
+
+            // if no sibling couldn be found from the virtual root, then we are done.
+            if c == 0 {
+                return (None, from, p);
+            }
+        }
+
+        // recursively traversing down to look for the first leaf.
+        while c != 0 {
+            from = (self.array[from].base() ^ (c as i32)) as usize;
+            c = self.n_infos[from].child;
+            p += 1;
+        }
+
+        #[cfg(feature = "reduced-trie")]
+        {
+            if self.array[from].base_ >= 0 {
+                return (Some(self.array[from].base_), from, p);
+            }
+        }
+
+        // To return the value of the leaf.
+        let v = self.array[(self.array[from].base() ^ (c as i32)) as usize].base_;
+        (Some(v), from, p)
+    }
+
+    // To bounds-check the node id `i` read from the array, a corrupted trie can point anywhere.
+    fn checked(&self, i: i32) -> Result<usize, CedarError> {
+        if i >= 0 && (i as usize) < self.size {
+            Ok(i as usize)
+        } else {
+            Err(CedarError::Corrupted)
+        }
+    }
+
+    // The same as `find`, with the node ids computed from the array checked.
+    fn try_find(&self, key: &[u8], from: &mut usize) -> Result<Option<i32>, CedarError> {
+        for &label in key {
+            #[cfg(feature = "reduced-trie")]
+            {
+                if self.array[*from].base_ >= 0 {
+                    return Ok(None);
+                }
+            }
+
+            let label = self.label(label);
+            if label == 0 {
+                return Ok(None);
+            }
+
+            let to = self.checked(self.array[*from].base() ^ (label as i32))?;
+            if self.array[to].check != (*from as i32) {
+                return Ok(None);
+            }
+            *from = to;
+        }
+
+        #[cfg(feature = "reduced-trie")]
+        {
+            if self.array[*from].base_ >= 0 {
+                return Ok(Some(self.array[*from].base_));
+            }
+        }
+
+        let n = &self.array[self.checked(self.array[*from].base())?];
+        if n.check != (*from as i32) {
+            Ok(Some(CEDAR_NO_VALUE))
+        } else {
+            Ok(Some(n.base_))
+        }
+    }
+
+    // The same as `begin`, with the node ids checked and the depth bounded against the cycles.
+    fn try_begin(&self, mut from: usize, mut p: usize) -> Result<(Option<i32>, usize, usize), CedarError> {
+        let mut c = self.n_infos[from].child;
+
+        if from == 0 {
+            c = self.n_infos[self.checked(self.array[from].base() ^ (c as i32))?].sibling;
+            if c == 0 {
+                return Ok((None, from, p));
+            }
+        }
+
+        while c != 0 {
+            from = self.checked(self.array[from].base() ^ (c as i32))?;
+            c = self.n_infos[from].child;
+            p += 1;
+            if p > self.size {
+                return Err(CedarError::Corrupted);
+            }
+        }
+
+        #[cfg(feature = "reduced-trie")]
+        {
+            if self.array[from].base_ >= 0 {
+                return Ok((Some(self.array[from].base_), from, p));
+            }
+        }
+
+        let v = self.array[self.checked(self.array[from].base() ^ (c as i32))?].base_;
+        Ok((Some(v), from, p))
+    }
+
+    // The same as `next`, with the node ids checked and the depth bounded against the cycles.
+    fn try_next(&self, mut from: usize, mut p: usize, root: usize) -> Result<(Option<i32>, usize, usize), CedarError> {
+        #[allow(unused_assignments)]
+        let mut c: u8 = 0;
+
+        #[cfg(feature = "reduced-trie")]
+        {
+            if self.array[from].base_ < 0 {
+                c = self.n_infos[self.checked(self.array[from].base())?].sibling;
+            }
+        }
+        #[cfg(not(feature = "reduced-trie"))]
+        {
+            c = self.n_infos[self.checked(self.array[from].base())?].sibling;
+        }
+
+        while c == 0 && from != root {
+            c = self.n_infos[from].sibling;
+            from = self.checked(self.array[from].check)?;
+            p = p.checked_sub(1).ok_or(CedarError::Corrupted)?;
+        }
+
+        if c != 0 {
+            from = self.checked(self.array[from].base() ^ (c as i32))?;
+            self.try_begin(from, p + 1)
+        } else {
+            Ok((None, from, p))
+        }
+    }
+
+    // To move the cursor from one leaf to the next for the common_prefix_predict.
+    fn next(&self, mut from: usize, mut p: usize, root: usize) -> (Option<i32>, usize, usize) {
+        #[allow(unused_assignments)]
+        let mut c: u8 = 0;
+
+        #[cfg(feature = "reduced-trie")]
+        {
+            if self.array[from].base_ < 0 {
+                c = self.n_infos[(self.array[from].base()) as usize].sibling;
+            }
+        }
+        #[cfg(not(feature = "reduced-trie"))]
+        {
+            c = self.n_infos[(self.array[from].base()) as usize].sibling;
+        }
+
+        // traversing up until there is a sibling or it has reached the root.
+        while c == 0 && from != root {
+            c = self.n_infos[from].sibling;
+            from = self.array[from].check as usize;
+
+            p -= 1;
+        }
+
+        if c != 0 {
+            // it has a sibling so we leverage on `begin` to traverse the subtree down again.
+            from = (self.array[from].base() ^ (c as i32)) as usize;
+            let (v_, from_, p_) = self.begin(from, p + 1);
+            (v_, from_, p_)
+        } else {
+            // no more work since we couldn't find anything.
+            (None, from, p)
+        }
+    }
+
+    // pop a block at idx from the linked-list of type `from`, specially handled if it is the last
+    // one in the linked-list.
+    fn pop_block(&mut self, idx: i32, from: BlockType, last: bool) {
+        let head: &mut i32 = match from {
+            BlockType::Open => &mut self.blocks_head_open,
+            BlockType::Closed => &mut self.blocks_head_closed,
+            BlockType::Full => &mut self.blocks_head_full,
+        };
+
+        if last {
+            *head = 0;
+        } else {
+            let b = self.blocks[idx as usize].clone();
+            self.blocks[b.prev as usize].next = b.next;
+            self.blocks[b.next as usize].prev = b.prev;
+
+            if idx == *head {
+                *head = b.next;
+            }
+        }
+    }
+
+    // return the block at idx to the linked-list of `to`, specially handled if the linked-list is
+    // empty
+    fn push_block(&mut self, idx: i32, to: BlockType, empty: bool) {
+        let head: &mut i32 = match to {
+            BlockType::Open => &mut self.blocks_head_open,
+            BlockType::Closed => &mut self.blocks_head_closed,
+            BlockType::Full => &mut self.blocks_head_full,
+        };
+
+        if empty {
+            self.blocks[idx as usize].next = idx;
+            self.blocks[idx as usize].prev = idx;
+            *head = idx;
+        } else {
+            self.blocks[idx as usize].prev = self.blocks[*head as usize].prev;
+            self.blocks[idx as usize].next = *head;
+
+            let t = self.blocks[*head as usize].prev;
+            self.blocks[t as usize].next = idx;
+            self.blocks[*head as usize].prev = idx;
+            *head = idx;
+        }
+    }
+
+    /// Reallocate more spaces so that we have more free blocks. The existing nodes are never moved here, which
+    /// keeps the node ids stable across growth.
+    fn add_block(&mut self) -> io::Result<i32> {
+        // the slots from `size` on are the spare capacity, so the new block never overwrites a node in use.
+        debug_assert!(self.size <= self.capacity);
+        self.reserve_blocks(1)?;
+        debug_assert!(self.size + 256 <= self.capacity && self.array.len() >= self.capacity);
+        debug_assert_eq!(self.blocks[self.size >> 8].num, 256);
+
+        self.blocks[self.size >> 8].e_head = self.size as i32;
+
+        // make it a doubley linked list
+        self.array[self.size] = Node {
+            base_: -((self.size as i32) + 255),
+            check: -((self.size as i32) + 1),
+        };
+
+        for i in (self.size + 1)..(self.size + 255) {
+            self.array[i] = Node {
+                base_: -(i as i32 - 1),
+                check: -(i as i32 + 1),
+            };
+        }
+
+        self.array[self.size + 255] = Node {
+            base_: -((self.size as i32) + 254),
+            check: -(self.size as i32),
+        };
+
+        let is_empty = self.blocks_head_open == 0;
+        let idx = (self.size >> 8) as i32;
+        debug_assert!(self.blocks[idx as usize].num > 1);
+        self.push_block(idx, BlockType::Open, is_empty);
+
+        self.size += 256;
+
+        Ok(((self.size >> 8) - 1) as i32)
+    }
+
+    // To double the capacity until `num_blocks` more blocks fit after `size`. The capacity is only raised once the
+    // nodes are there, so a failure to grow them leaves the trie as it was.
+    fn reserve_blocks(&mut self, num_blocks: usize) -> io::Result<()> {
+        let mut capacity = self.capacity;
+        while capacity < self.size + (num_blocks << 8) {
+            capacity += capacity;
+        }
+
+        if capacity > self.capacity {
+            self.array.resize(capacity)?;
+            self.capacity = capacity;
+            self.n_infos.resize(capacity, Default::default());
+            self.blocks.resize(capacity >> 8, Block::new());
+        }
+
+        Ok(())
+    }
+
+    // transfer the block at idx from the linked-list of `from` to the linked-list of `to`,
+    // specially handle the case where the destination linked-list is empty.
+    fn transfer_block(&mut self, idx: i32, from: BlockType, to: BlockType, to_block_empty: bool) {
+        let is_last = idx == self.blocks[idx as usize].next; //it's the last one if the next points to itself
+        let is_empty = to_block_empty && (self.blocks[idx as usize].num != 0);
+
+        self.pop_block(idx, from, is_last);
+        self.push_block(idx, to, is_empty);
+    }
+
+    /// Mark an edge `e` as used in a trie node, it is `base ^ label` unless `from` has no children yet.
+    fn pop_e_node(&mut self, base: i32, e: i32, label: u8, from: i32) -> i32 {
+        let idx = e >> 8;
+        let n = self.array[e as usize].clone();
+
+        self.blocks[idx as usize].num -= 1;
+        // move the block at idx to the correct linked-list depending the free slots it still have.
+        if self.blocks[idx as usize].num == 0 {
+            if idx != 0 {
+                self.transfer_block(idx, BlockType::Closed, BlockType::Full, self.blocks_head_full == 0);
+            }
+        } else {
+            self.array[(-n.base_) as usize].check = n.check;
+            self.array[(-n.check) as usize].base_ = n.base_;
+
+            if e == self.blocks[idx as usize].e_head {
+                self.blocks[idx as usize].e_head = -n.check;
+            }
+
+            if idx != 0 && self.blocks[idx as usize].num == 1 && self.blocks[idx as usize].trial != self.max_trial {
+                self.transfer_block(idx, BlockType::Open, BlockType::Closed, self.blocks_head_closed == 0);
+            }
+        }
+
+        #[cfg(feature = "reduced-trie")]
+        {
+            self.array[e as usize].base_ = CEDAR_VALUE_LIMIT;
+            self.array[e as usize].check = from;
+            if base < 0 {
+                self.array[from as usize].base_ = -(e ^ (label as i32)) - 1;
+            }
+        }
+
+        #[cfg(not(feature = "reduced-trie"))]
+        {
+            if label != 0 {
+                self.array[e as usize].base_ = -1;
+            } else {
+                self.array[e as usize].base_ = 0;
+            }
+            self.array[e as usize].check = from;
+            if base < 0 {
+                self.array[from as usize].base_ = e ^ (label as i32);
+            }
+        }
+
+        e
+    }
+
+    /// Mark an edge `e` as free in a trie node.
+    fn push_e_node(&mut self, e: i32) {
+        let idx = e >> 8;
+        self.blocks[idx as usize].num += 1;
+
+        if self.blocks[idx as usize].num == 1 {
+            self.blocks[idx as usize].e_head = e;
+            self.array[e as usize] = Node { base_: -e, check: -e };
+
+            if idx != 0 {
+                // Move the block from 'Full' to 'Closed' since it has one free slot now.
+                self.transfer_block(idx, BlockType::Full, BlockType::Closed, self.blocks_head_closed == 0);
+            }
+        } else {
+            let prev = self.blocks[idx as usize].e_head;
+
+            let next = -self.array[prev as usize].check;
+
+            // Insert to the edge immediately after the e_head
+            self.array[e as usize] = Node {
+                base_: -prev,
+                check: -next,
+            };
+
+            self.array[prev as usize].check = -e;
+            self.array[next as usize].base_ = -e;
+
+            // Move the block from 'Closed' to 'Open' since it has more than one free slot now.
+            if self.blocks[idx as usize].num == 2 || self.blocks[idx as usize].trial == self.max_trial {
+                debug_assert!(self.blocks[idx as usize].num > 1);
+                if idx != 0 {
+                    self.transfer_block(idx, BlockType::Closed, BlockType::Open, self.blocks_head_open == 0);
+                }
+            }
+
+            // Reset the trial stats
+            self.blocks[idx as usize].trial = 0;
+        }
+
+        if self.blocks[idx as usize].reject < self.reject[self.blocks[idx as usize].num as usize] {
+            self.blocks[idx as usize].reject = self.reject[self.blocks[idx as usize].num as usize];
+        }
+
+        self.n_infos[e as usize] = Default::default();
+    }
+
+    // push the `label` into the sibling chain
+    fn push_sibling(&mut self, from: usize, base: i32, label: u8, has_child: bool) {
+        let keep_order: bool = if self.ordered {
+            label > self.n_infos[from].child
+        } else {
+            self.n_infos[from].child == 0
+        };
+
+        let sibling: u8;
+        {
+            let mut c: &mut u8 = &mut self.n_infos[from].child;
+            if has_child && keep_order {
+                loop {
+                    let code = *c as i32;
+                    c = &mut self.n_infos[(base ^ code) as usize].sibling;
+
+                    if !(self.ordered && (*c != 0) && (*c < label)) {
+                        break;
+                    }
+                }
+            }
+            sibling = *c;
+
+            *c = label;
+        }
+
+        self.n_infos[(base ^ (label as i32)) as usize].sibling = sibling;
+    }
+
+    // remove the `label` from the sibling chain.
+    #[allow(dead_code)]
+    fn pop_sibling(&mut self, from: i32, base: i32, label: u8) {
+        let mut c: *mut u8 = &mut self.n_infos[from as usize].child;
+        unsafe {
+            while *c != label {
+                let code = *c as i32;
+                c = &mut self.n_infos[(base ^ code) as usize].sibling;
+            }
+
+            let code = label as i32;
+            *c = self.n_infos[(base ^ code) as usize].sibling;
+        }
+    }
+
+    // Loop through the siblings to see which one reached the end first, which means it is the one
+    // with smaller in children size, and we should try ti relocate the smaller one.
+    fn consult(&self, base_n: i32, base_p: i32, mut c_n: u8, mut c_p: u8) -> bool {
+        loop {
+            c_n = self.n_infos[(base_n ^ (c_n as i32)) as usize].sibling;
+            c_p = self.n_infos[(base_p ^ (c_p as i32)) as usize].sibling;
+
+            if !(c_n != 0 && c_p != 0) {
+                break;
+            }
+        }
+
+        c_p != 0
+    }
+
+    // Collect the list of the children, and push the label as well if it is not terminal node.
+    fn set_child(&self, base: i32, mut c: u8, label: u8, not_terminal: bool) -> ChildLabels {
+        let mut child = ChildLabels::new();
+
+        if c == 0 {
+            child.push(c);
+            c = self.n_infos[(base ^ (c as i32)) as usize].sibling;
+        }
+
+        if self.ordered {
+            while c != 0 && c <= label {
+                child.push(c);
+                c = self.n_infos[(base ^ (c as i32)) as usize].sibling;
+            }
+        }
+
+        if not_terminal {
+            child.push(label);
+        }
+
+        while c != 0 {
+            child.push(c);
+            c = self.n_infos[(base ^ (c as i32)) as usize].sibling;
+        }
+
+        child
+    }
+
+    // For the case where only one free slot is needed
+    fn find_place(&mut self) -> io::Result<i32> {
+        if self.blocks_head_closed != 0 {
+            return Ok(self.blocks[self.blocks_head_closed as usize].e_head);
+        }
+
+        if self.blocks_head_open != 0 {
+            return Ok(self.blocks[self.blocks_head_open as usize].e_head);
+        }
+
+        // the block is not enough, resize it and allocate it.
+        Ok(self.add_block()? << 8)
+    }
+
+    // For the case where multiple free slots are needed.
+    fn find_places(&mut self, child: &[u8]) -> io::Result<i32> {
+        let mut idx = self.blocks_head_open;
+
+        // we still have available 'Open' blocks.
+        if idx != 0 {
+            debug_assert!(self.blocks[idx as usize].num > 1);
+            let bz = self.blocks[self.blocks_head_open as usize].prev;
+            let nc = child.len() as i16;
+
+            loop {
+                // only proceed if the free slots are more than the number of children. Also, we
+                // save the minimal number of attempts to fail in the `reject`, it only worths to
+                // try out this block if the number of children is less than that number.
+                if self.blocks[idx as usize].num >= nc && nc < self.blocks[idx as usize].reject {
+                    let mut e = self.blocks[idx as usize].e_head;
+                    loop {
+                        let base = e ^ (child[0] as i32);
+
+                        let mut i = 1;
+                        // iterate through the children to see if they are available: (check < 0)
+                        while self.array[(base ^ (child[i] as i32)) as usize].check < 0 {
+                            if i == child.len() - 1 {
+                                // we have found the available block.
+                                self.blocks[idx as usize].e_head = e;
+                                return Ok(e);
+                            }
+                            i += 1;
+                        }
+
+                        // we save the next free block's information in `check`
+                        e = -self.array[e as usize].check;
+                        if e == self.blocks[idx as usize].e_head {
+                            break;
+                        }
+                    }
+                }
+
+                // we broke out of the loop, that means we failed. We save the information in
+                // `reject` for future pruning.
+                self.blocks[idx as usize].reject = nc;
+                if self.blocks[idx as usize].reject < self.reject[self.blocks[idx as usize].num as usize] {
+                    // put this stats into the global array of information as well.
+                    self.reject[self.blocks[idx as usize].num as usize] = self.blocks[idx as usize].reject;
+                }
+
+                let idx_ = self.blocks[idx as usize].next;
+
+                self.blocks[idx as usize].trial += 1;
+
+                // move this block to the 'Closed' block list since it has reached the max_trial
+                if self.blocks[idx as usize].trial == self.max_trial {
+                    self.transfer_block(idx, BlockType::Open, BlockType::Closed, self.blocks_head_closed == 0);
+                }
+
+                // we have finsihed one round of this cyclic doubly-linked-list.
+                if idx == bz {
+                    break;
+                }
+
+                // going to the next in this linked list group
+                idx = idx_;
+            }
+        }
+
+        Ok(self.add_block()? << 8)
+    }
+
+    // resolve the conflict by moving one of the the nodes to a free block.
+    fn resolve(&mut self, mut from_n: usize, base_n: i32, label_n: u8) -> io::Result<i32> {
+        let to_pn = base_n ^ (label_n as i32);
+
+        // the `base` and `from` for the conflicting one.
+        let from_p = self.array[to_pn as usize].check;
+        let base_p = self.array[from_p as usize].base();
+
+        // whether to replace siblings of newly added
+        let flag = self.consult(
+            base_n,
+            base_p,
+            self.n_infos[from_n].child,
+            self.n_infos[from_p as usize].child,
+        );
+
+        // collect the list of children for the block that we are going to relocate.
+        let children = if flag {
+            self.set_child(base_n, self.n_infos[from_n].child, label_n, true)
+        } else {
+            self.set_child(base_p, self.n_infos[from_p as usize].child, 255, false)
+        };
+
+        // decide which algorithm to allocate free block depending on the number of children we
+        // have.
+        let mut base = if children.len() == 1 {
+            self.find_place()?
+        } else {
+            self.find_places(&children)?
+        };
+
+        base ^= children[0] as i32;
+
+        let (from, base_) = if flag {
+            (from_n as i32, base_n)
+        } else {
+            (from_p, base_p)
+        };
+
+        if flag && children[0] == label_n {
+            self.n_infos[from as usize].child = label_n;
+        }
+
+        #[cfg(feature = "reduced-trie")]
+        {
+            self.array[from as usize].base_ = -base - 1;
+        }
+
+        #[cfg(not(feature = "reduced-trie"))]
+        {
+            self.array[from as usize].base_ = base;
+        }
+
+        // the actual work for relocating the chilren
+        for i in 0..(children.len()) {
+            let to = self.pop_e_node(base, base ^ (children[i] as i32), children[i], from);
+            let to_ = base_ ^ (children[i] as i32);
+
+            if i == children.len() - 1 {
+                self.n_infos[to as usize].sibling = 0;
+            } else {
+                self.n_infos[to as usize].sibling = children[i + 1];
+            }
+
+            if flag && to_ == to_pn {
+                continue;
+            }
+
+            self.array[to as usize].base_ = self.array[to_ as usize].base_;
+
+            #[cfg(feature = "reduced-trie")]
+            let condition = self.array[to as usize].base_ < 0 && children[i] != 0;
+            #[cfg(not(feature = "reduced-trie"))]
+            let condition = self.array[to as usize].base_ > 0 && children[i] != 0;
+
+            if condition {
+                let mut c = self.n_infos[to_ as usize].child;
+
+                self.n_infos[to as usize].child = c;
+
+                loop {
+                    let idx = (self.array[to as usize].base() ^ (c as i32)) as usize;
+                    self.array[idx].check = to;
+                    c = self.n_infos[idx].sibling;
+
+                    if c == 0 {
+                        break;
+                    }
+                }
+            }
+
+            if !flag && to_ == (from_n as i32) {
+                from_n = to as usize;
+            }
+
+            // clean up the space that was moved away from.
+            if !flag && to_ == to_pn {
+                self.push_sibling(from_n, to_pn ^ (label_n as i32), label_n, true);
+                self.n_infos[to_ as usize].child = 0;
+
+                #[cfg(feature = "reduced-trie")]
+                {
+                    self.array[to_ as usize].base_ = CEDAR_VALUE_LIMIT;
+                }
+
+                #[cfg(not(feature = "reduced-trie"))]
+                {
+                    if label_n != 0 {
+                        self.array[to_ as usize].base_ = -1;
+                    } else {
+                        self.array[to_ as usize].base_ = 0;
+                    }
+                }
+
+                self.array[to_ as usize].check = from_n as i32;
+            } else {
+                self.push_e_node(to_);
+            }
+        }
+
+        // return the position that is free now.
+        if flag {
+            Ok(base ^ (label_n as i32))
+        } else {
+            Ok(to_pn)
+        }
+    }
+}
+
+// To read exactly `len` bytes from `r`. The buffer grows as the bytes arrive, so a corrupted length cannot allocate
+// more than the input actually has before the short read is noticed.
+fn read_len<R: Read>(r: &mut R, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    r.by_ref().take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() < len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the input is truncated"));
+    }
+
+    Ok(buf)
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// To read the next entry in the format of `export_entries`, or None at the end of `r`. The entries are allowed to
+// end only at the boundary of an entry.
+fn read_entry<R: Read>(r: &mut R) -> io::Result<Option<(Vec<u8>, i32)>> {
+    let mut len = [0u8; 4];
+    let n = r.read(&mut len)?;
+    if n == 0 {
+        return Ok(None);
+    }
+    r.read_exact(&mut len[n..])?;
+
+    let key = read_len(r, u32::from_le_bytes(len) as usize)?;
+    let mut value = [0u8; 4];
+    r.read_exact(&mut value)?;
+
+    Ok(Some((key, i32::from_le_bytes(value))))
+}
+
+// To generate the next pseudorandom number from `state` by splitmix64, it is good enough for sampling.
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// A log record is laid out as `op: u8, key_len: u32, key bytes, value: i32` in little-endian.
+fn write_log_record<W: Write>(mut w: W, op: u8, key: &[u8], value: i32) -> io::Result<()> {
+    let mut buf: Vec<u8> = Vec::with_capacity(9 + key.len());
+    buf.push(op);
+    buf.extend_from_slice(&(key.len() as u32).to_le_bytes());
+    buf.extend_from_slice(key);
+    buf.extend_from_slice(&value.to_le_bytes());
+    w.write_all(&buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::distributions::Alphanumeric;
+    use rand::seq::SliceRandom;
+    use rand::{thread_rng, Rng};
+    use std::env;
+    use std::iter;
+
+    #[test]
+    fn test_insert_and_delete() {
+        let dict = vec!["a"];
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        let result = cedar.exact_match_search("ab").map(|x| x.0);
+        assert_eq!(None, result);
+
+        cedar.update("ab", 1);
+        let result = cedar.exact_match_search("ab").map(|x| x.0);
+        assert_eq!(Some(1), result);
+
+        cedar.erase("ab");
+        let result = cedar.exact_match_search("ab").map(|x| x.0);
+        assert_eq!(None, result);
+
+        cedar.update("abc", 2);
+        let result = cedar.exact_match_search("abc").map(|x| x.0);
+        assert_eq!(Some(2), result);
+
+        cedar.erase("abc");
+        let result = cedar.exact_match_search("abc").map(|x| x.0);
+        assert_eq!(None, result);
+    }
+
+    #[test]
+    fn test_common_prefix_search() {
+        let dict = vec![
+            "a",
+            "ab",
+            "abc",
+            "アルゴリズム",
+            "データ",
+            "構造",
+            "网",
+            "网球",
+            "网球拍",
+            "中",
+            "中华",
+            "中华人民",
+            "中华人民共和国",
+        ];
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        let result: Vec<i32> = cedar
+            .common_prefix_search("abcdefg")
+            .unwrap()
+            .iter()
+            .map(|x| x.0)
+            .collect();
+        assert_eq!(vec![0, 1, 2], result);
+
+        let result: Vec<i32> = cedar
+            .common_prefix_search("网球拍卖会")
+            .unwrap()
+            .iter()
+            .map(|x| x.0)
+            .collect();
+        assert_eq!(vec![6, 7, 8], result);
+
+        let result: Vec<i32> = cedar
+            .common_prefix_search("中华人民共和国")
+            .unwrap()
+            .iter()
+            .map(|x| x.0)
+            .collect();
+        assert_eq!(vec![9, 10, 11, 12], result);
+
+        let result: Vec<i32> = cedar
+            .common_prefix_search("データ構造とアルゴリズム")
+            .unwrap()
+            .iter()
+            .map(|x| x.0)
+            .collect();
+        assert_eq!(vec![4], result);
+    }
+
+    #[test]
+    fn test_common_prefix_iter() {
+        let dict = vec![
+            "a",
+            "ab",
+            "abc",
+            "アルゴリズム",
+            "データ",
+            "構造",
+            "网",
+            "网球",
+            "网球拍",
+            "中",
+            "中华",
+            "中华人民",
+            "中华人民共和国",
+        ];
+
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        let result: Vec<i32> = cedar.common_prefix_iter("abcdefg").map(|x| x.0).collect();
+        assert_eq!(vec![0, 1, 2], result);
+
+        let result: Vec<i32> = cedar.common_prefix_iter("网球拍卖会").map(|x| x.0).collect();
+        assert_eq!(vec![6, 7, 8], result);
+
+        let result: Vec<i32> = cedar.common_prefix_iter("中华人民共和国").map(|x| x.0).collect();
+        assert_eq!(vec![9, 10, 11, 12], result);
+
+        let result: Vec<i32> = cedar
+            .common_prefix_iter("データ構造とアルゴリズム")
+            .map(|x| x.0)
+            .collect();
+        assert_eq!(vec![4], result);
+    }
+
+    #[test]
+    fn test_shared_prefix_len() {
+        let mut cedar = Cedar::new();
+        cedar.build(&[("abc", 0), ("abd", 1), ("x", 2)]);
+
+        assert_eq!(cedar.shared_prefix_len("abc", "abd"), 2);
+        assert_eq!(cedar.shared_prefix_len("abc", "abc"), 3);
+        assert_eq!(cedar.shared_prefix_len("abcz", "abcz"), 3);
+        assert_eq!(cedar.shared_prefix_len("xyz", "xyz"), 1);
+        assert_eq!(cedar.shared_prefix_len("abc", "x"), 0);
+    }
+
+    #[test]
+    fn test_related_keys() {
+        let dict = vec!["a", "ab", "abc", "abcd"];
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        let (prefixes, extensions) = cedar.related_keys("abc");
+        assert_eq!(prefixes, cedar.common_prefix_search("abc").unwrap());
+        assert_eq!(prefixes, vec![(0, 0), (1, 1), (2, 2)]);
+        assert_eq!(extensions, vec![(3, 1)]);
+
+        assert_eq!(cedar.related_keys("abd"), (vec![(0, 0), (1, 1)], vec![]));
+        assert_eq!(
+            cedar.related_keys("abcd"),
+            (vec![(0, 0), (1, 1), (2, 2), (3, 3)], vec![])
+        );
+    }
+
+    #[test]
+    fn test_search_profiled() {
+        let dict = vec!["a", "ab", "abcd", "中华"];
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        assert_eq!(cedar.exact_match_search_profiled("abcd"), (Some(2), 4));
+        assert_eq!(cedar.exact_match_search_profiled("中华"), (Some(3), 6));
+        assert_eq!(cedar.exact_match_search_profiled("abc"), (None, 3));
+        assert_eq!(cedar.exact_match_search_profiled("abx"), (None, 2));
+
+        let (result, transitions) = cedar.common_prefix_search_profiled("abcde");
+        assert_eq!(result, cedar.common_prefix_search("abcde").unwrap());
+        assert_eq!(transitions, 4);
+    }
+
+    #[test]
+    fn test_common_prefix_search_max() {
+        let dict = vec!["a", "ab", "abc", "abcd"];
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        assert_eq!(cedar.common_prefix_search_max("abcde", 2), (vec![(0, 0), (1, 1)], true));
+        assert!(!cedar.common_prefix_search_max("abcde", 4).1);
+        assert_eq!(
+            cedar.common_prefix_search_max("abc", 3),
+            (vec![(0, 0), (1, 1), (2, 2)], false)
+        );
+        assert_eq!(cedar.common_prefix_search_max("abc", 0), (vec![], true));
+        assert_eq!(cedar.common_prefix_search_max("x", 0), (vec![], false));
+    }
+
+    #[test]
+    fn test_shortest_prefix_match() {
+        let dict = vec!["a", "ab", "abc", "中华", "中华人民"];
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        assert_eq!(cedar.shortest_prefix_match("abc"), Some((0, 0)));
+        assert_eq!(cedar.shortest_prefix_match("中华人民共和国"), Some((3, 5)));
+        assert_eq!(cedar.shortest_prefix_match("b"), None);
+    }
+
+    #[test]
+    fn test_common_prefix_predict() {
+        let dict = vec!["a", "ab", "abc"];
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        let result: Vec<i32> = cedar.common_prefix_predict("a").unwrap().iter().map(|x| x.0).collect();
+        assert_eq!(vec![0, 1, 2], result);
+
+        let result: Vec<i32> = cedar.common_prefix_predict("").unwrap().iter().map(|x| x.0).collect();
+        assert_eq!(vec![0, 1, 2], result);
+    }
+
+    #[test]
+    fn test_common_prefix_predict_limit() {
+        let dict = vec!["a", "ab", "abc", "abd", "b"];
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        let result: Vec<i32> = cedar
+            .common_prefix_predict_limit("a", 2)
+            .unwrap()
+            .iter()
+            .map(|x| x.0)
+            .collect();
+        assert_eq!(vec![0, 1], result);
+
+        let all = cedar.common_prefix_predict("a");
+        assert_eq!(all.as_ref().map(|v| v.len()), Some(4));
+        assert_eq!(cedar.common_prefix_predict_limit("a", 10), all);
+        assert_eq!(cedar.common_prefix_predict_limit("a", 0), Some(vec![]));
+    }
+
+    #[test]
+    fn test_ranked_completions() {
+        let dict = vec!["中", "中华", "中华人民", "中华人民共和国", "中华民族", "网球"];
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        let result: Vec<(usize, i32, usize)> = cedar.ranked_completions("中华").collect();
+        let ranks: Vec<usize> = result.iter().map(|x| x.0).collect();
+        assert_eq!(ranks, (0..4).collect::<Vec<_>>());
+        let completions: Vec<(i32, usize)> = result.iter().map(|x| (x.1, x.2)).collect();
+        assert_eq!(completions, cedar.common_prefix_predict("中华").unwrap());
+
+        assert_eq!(cedar.ranked_completions("网").collect::<Vec<_>>(), vec![(0, 5, 3)]);
+        assert_eq!(cedar.ranked_completions("球").count(), 0);
+    }
+
+    #[test]
+    fn test_longest_prefix_match() {
+        let dict = vec!["a", "ab", "abcd", "中", "中华人民"];
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        assert_eq!(cedar.longest_prefix_match("abcde"), Some((2, 4)));
+        assert_eq!(cedar.longest_prefix_match("abc"), Some((1, 2)));
+        assert_eq!(cedar.longest_prefix_match("ax"), Some((0, 1)));
+        assert_eq!(cedar.longest_prefix_match("中华人民共和国"), Some((4, 12)));
+        assert_eq!(cedar.longest_prefix_match("中华"), Some((3, 3)));
+        assert_eq!(cedar.longest_prefix_match("b"), None);
+        assert_eq!(cedar.longest_prefix_match(""), None);
+
+        for key in ["abcde", "abc", "中华人民共和国", "b"].iter() {
+            let last = cedar
+                .common_prefix_search(key)
+                .unwrap()
+                .last()
+                .map(|&(v, len)| (v, len + 1));
+            assert_eq!(cedar.longest_prefix_match(key), last);
+        }
+    }
+
+    #[test]
+    fn test_exact_match_search() {
+        let dict = vec!["a", "ab", "abc"];
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        let result = cedar.exact_match_search("abc").map(|x| x.0);
+        assert_eq!(Some(2), result);
+    }
+
+    #[test]
+    fn test_with_options() {
+        let key_values = [("ab", 0), ("ad", 1), ("ac", 2), ("a", 3)];
+        let predict =
+            |cedar: &Cedar| -> Vec<i32> { cedar.common_prefix_predict("a").unwrap().iter().map(|x| x.0).collect() };
+
+        let mut ordered = Cedar::with_options(4, true);
+        ordered.build(&key_values);
+        assert_eq!(predict(&ordered), vec![3, 0, 2, 1]);
+
+        let mut unordered = Cedar::with_options(4, false);
+        unordered.build(&key_values);
+        assert_eq!(predict(&unordered), vec![3, 2, 1, 0]);
+
+        // the options stay across `clear`.
+        unordered.clear();
+        assert!(!unordered.ordered);
+        assert_eq!(unordered.max_trial, 4);
+    }
+
+    #[test]
+    fn test_build_sorted() {
+        let key_values = [("b", 0), ("ab", 1), ("a", 2), ("b", 3), ("abc", 4)];
+        let mut cedar = Cedar::new();
+        cedar.build_sorted(&key_values);
+
+        let keys: Vec<(Vec<u8>, i32)> = cedar.keys().collect();
+        let expected: Vec<(Vec<u8>, i32)> = [("a", 2), ("ab", 1), ("abc", 4), ("b", 3)]
+            .iter()
+            .map(|&(k, v)| (k.as_bytes().to_vec(), v))
+            .collect();
+        assert_eq!(keys, expected);
+
+        let mut unsorted = Cedar::new();
+        unsorted.build(&key_values);
+        for &(key, _) in key_values.iter() {
+            assert_eq!(
+                cedar.exact_match_search(key).map(|x| x.0),
+                unsorted.exact_match_search(key).map(|x| x.0)
+            );
+        }
+    }
+
+    #[test]
+    fn test_update_with() {
+        let mut cedar = Cedar::new();
+        cedar.update("ab", 10);
+        for text in ["a", "ab", "a", "abc", "a"].iter() {
+            cedar.update_with(text, |count| count.map_or(1, |c| c + 1));
+        }
+
+        assert_eq!(cedar.exact_match_search("a").map(|x| x.0), Some(3));
+        assert_eq!(cedar.exact_match_search("ab").map(|x| x.0), Some(11));
+        assert_eq!(cedar.exact_match_search("abc").map(|x| x.0), Some(1));
+        assert_eq!(cedar.update_with("abc", |_| 7), 7);
+        assert_eq!(cedar.len(), 3);
+    }
+
+    #[test]
+    fn test_contains_key() {
+        let mut cedar = Cedar::new();
+        cedar.build(&[("ab", 0), ("abcd", 1)]);
+
+        assert!(cedar.contains_key("abcd"));
+        assert!(cedar.contains_key("ab"));
+        assert!(!cedar.contains_key("abc"));
+        assert!(!cedar.contains_key("a"));
+        assert!(!cedar.contains_key("abcde"));
+        assert!(!cedar.contains_key(""));
+
+        cedar.erase("ab");
+        assert!(!cedar.contains_key("ab"));
+        assert!(cedar.contains_key("abcd"));
+    }
+
+    #[test]
+    fn test_unicode_han_sip() {
+        let dict = vec!["讥䶯䶰", "讥䶯䶰䶱䶲", "讥䶯䶰䶱䶲䶳䶴䶵𦡦"];
+
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        let result: Vec<i32> = cedar.common_prefix_iter("讥䶯䶰䶱䶲䶳䶴䶵𦡦").map(|x| x.0).collect();
+        assert_eq!(vec![0, 1, 2], result);
+    }
+
+    #[test]
+    fn test_unicode_grapheme_cluster() {
+        let dict = vec!["a", "abc", "abcde\u{0301}"];
+
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        let result: Vec<i32> = cedar
+            .common_prefix_iter("abcde\u{0301}\u{1100}\u{1161}\u{AC00}")
+            .map(|x| x.0)
+            .collect();
+        assert_eq!(vec![0, 1, 2], result);
+    }
+
+    #[test]
+    fn test_erase() {
+        let dict = vec!["a", "ab", "abc"];
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        cedar.erase("abc");
+        assert!(cedar.exact_match_search("abc").is_none());
+        assert!(cedar.exact_match_search("ab").is_some());
+        assert!(cedar.exact_match_search("a").is_some());
+
+        cedar.erase("ab");
+        assert!(cedar.exact_match_search("ab").is_none());
+        assert!(cedar.exact_match_search("a").is_some());
+
+        cedar.erase("a");
+        assert!(cedar.exact_match_search("a").is_none());
+    }
+
+    #[test]
+    fn test_erase_many() {
+        let dict = vec!["a", "ab", "abc", "b", "中华"];
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        assert_eq!(cedar.erase_many(&["abc", "x", "a", "中", "中华", "abc"]), 3);
+        for key in ["a", "abc", "中华"].iter() {
+            assert!(cedar.exact_match_search(key).is_none());
+        }
+        assert_eq!(cedar.exact_match_search("ab").map(|x| x.0), Some(1));
+        assert_eq!(cedar.exact_match_search("b").map(|x| x.0), Some(3));
+        assert_eq!(cedar.len(), 2);
+    }
+
+    #[test]
+    fn test_erase_on_internal_key() {
+        let mut cedar = Cedar::new();
+
+        cedar.update("aa", 0);
+        assert!(cedar.exact_match_search("aa").is_some());
+        cedar.update("ab", 1);
+        assert!(cedar.exact_match_search("ab").is_some());
+
+        cedar.erase("a");
+        assert!(cedar.exact_match_search("a").is_none());
+        cedar.erase("aa");
+        assert!(cedar.exact_match_search("aa").is_none());
+        cedar.erase("ab");
+        assert!(cedar.exact_match_search("ab").is_none());
+    }
+
+    #[test]
+    fn test_update() {
+        let dict = vec!["a", "ab", "abc"];
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        cedar.update("abcd", 3);
+
+        assert!(cedar.exact_match_search("a").is_some());
+        assert!(cedar.exact_match_search("ab").is_some());
+        assert!(cedar.exact_match_search("abc").is_some());
+        assert!(cedar.exact_match_search("abcd").is_some());
+        assert!(cedar.exact_match_search("abcde").is_none());
+
+        let dict = vec!["a", "ab", "abc"];
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+        cedar.update("bachelor", 1);
+        cedar.update("jar", 2);
+        cedar.update("badge", 3);
+        cedar.update("baby", 4);
+
+        assert!(cedar.exact_match_search("bachelor").is_some());
+        assert!(cedar.exact_match_search("jar").is_some());
+        assert!(cedar.exact_match_search("badge").is_some());
+        assert!(cedar.exact_match_search("baby").is_some());
+        assert!(cedar.exact_match_search("abcde").is_none());
+
+        let dict = vec!["a", "ab", "abc"];
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+        cedar.update("中", 1);
+        cedar.update("中华", 2);
+        cedar.update("中华人民", 3);
+        cedar.update("中华人民共和国", 4);
+
+        assert!(cedar.exact_match_search("中").is_some());
+        assert!(cedar.exact_match_search("中华").is_some());
+        assert!(cedar.exact_match_search("中华人民").is_some());
+        assert!(cedar.exact_match_search("中华人民共和国").is_some());
+    }
+
+    #[test]
+    fn test_is_empty() {
+        let mut cedar = Cedar::new();
+        assert!(cedar.is_empty());
+
+        cedar.update("ab", 0);
+        assert!(!cedar.is_empty());
+        cedar.update("b", 1);
+        cedar.erase("ab");
+        assert!(!cedar.is_empty());
+
+        cedar.erase("b");
+        assert!(cedar.is_empty());
+        assert_eq!(cedar.keys().count(), 0);
+
+        // the value -1 is no value, so it leaves the nodes of the key but agrees with `len`.
+        let mut cedar = Cedar::new();
+        cedar.update("a", -1);
+        let len = cedar.len();
+        assert_eq!(len, 0);
+        assert_eq!(cedar.is_empty(), len == 0);
+    }
+
+    #[test]
+    fn test_len() {
+        let mut cedar = Cedar::new();
+        assert_eq!(cedar.len(), 0);
+
+        cedar.build(&[("a", 0), ("ab", 1), ("abc", 2), ("b", 3)]);
+        assert_eq!(cedar.len(), 4);
+
+        // the overwrites keep the count, and so do the erasures of the keys not there.
+        cedar.update("ab", 10);
+        cedar.update("a", 11);
+        assert_eq!(cedar.len(), 4);
+        cedar.erase("abcd");
+        cedar.erase("c");
+        assert_eq!(cedar.len(), 4);
+
+        // a prefix on the path of the other keys is a new key, and erasing it leaves them.
+        cedar.update("abcd", 4);
+        cedar.erase("ab");
+        cedar.erase("ab");
+        assert_eq!(cedar.len(), 4);
+        cedar.erase_and_compact("abc");
+        assert_eq!(cedar.len(), 3);
+        assert_eq!(cedar.len(), cedar.keys().count());
+
+        let mut buf = Vec::new();
+        cedar.write_to(&mut buf).unwrap();
+        assert_eq!(Cedar::read_from(&buf[..]).unwrap().len(), 3);
+        assert_eq!(cedar.clone_shrunk().len(), 3);
+
+        for (key, _) in cedar.keys().collect::<Vec<_>>() {
+            cedar.erase(std::str::from_utf8(&key).unwrap());
+        }
+        assert_eq!(cedar.len(), 0);
+        cedar.update("a", 0);
+        cedar.clear();
+        assert_eq!(cedar.len(), 0);
+    }
+
+    #[test]
+    fn test_rebuild() {
+        let mut rng = thread_rng();
+        let mut cedar = Cedar::new();
+        for k in 0..3000 {
+            let len = rng.gen_range(1..10);
+            let chars: Vec<u8> = iter::repeat(()).map(|()| rng.sample(Alphanumeric)).take(len).collect();
+            let key = String::from_utf8(chars).unwrap();
+            cedar.update(&key, k);
+            if k % 3 == 0 {
+                cedar.erase(&key);
+            }
+        }
+
+        assert_eq!(cedar.len(), cedar.keys().count());
+
+        let mut fresh = Cedar::new();
+        for (key, value) in cedar.keys() {
+            fresh.update(std::str::from_utf8(&key).unwrap(), value);
+        }
+
+        cedar.rebuild();
+        assert_eq!(cedar.len(), fresh.len());
+        let mut rebuilt = Vec::new();
+        cedar.write_to(&mut rebuilt).unwrap();
+        let mut expected = Vec::new();
+        fresh.write_to(&mut expected).unwrap();
+        assert_eq!(rebuilt, expected);
+
+        cedar.clear();
+        assert!(cedar.is_empty());
+        assert_eq!(cedar.reject, Cedar::new().reject);
+    }
+
+    #[test]
+    fn test_clear() {
+        let dict: Vec<String> = (0..5000).map(|i| format!("{:x}", i * 7919)).collect();
+        let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (s.as_ref(), k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+        let heap_capacity = cedar.array.heap_capacity();
+        assert!(heap_capacity > 256);
+
+        cedar.clear();
+        for (key, _) in key_values.iter() {
+            assert_eq!(cedar.exact_match_search(key), None);
+        }
+        assert!(cedar.is_empty());
+        assert_eq!(cedar.keys().count(), 0);
+        assert_eq!(cedar.array.heap_capacity(), heap_capacity);
+
+        // the cleared trie lays the keys out the same as a fresh one, in the memory it kept.
+        cedar.build(&key_values);
+        for (key, value) in key_values.iter() {
+            assert_eq!(cedar.exact_match_search(key).map(|x| x.0), Some(*value));
+        }
+        let mut fresh = Cedar::new();
+        fresh.build(&key_values);
+        let (mut rebuilt, mut expected) = (Vec::new(), Vec::new());
+        cedar.write_to(&mut rebuilt).unwrap();
+        fresh.write_to(&mut expected).unwrap();
+        assert_eq!(rebuilt, expected);
+        assert_eq!(cedar.array.heap_capacity(), heap_capacity);
+        assert_eq!(cedar.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut cedar = Cedar::new();
+        cedar.build(&[("a", 0), ("ab", 1), ("abc", 2)]);
+
+        // the keys take a block next to the one of the root, and without the spare capacity the two blocks are
+        // 512 nodes of 8 bytes and infos of 2 bytes, two blocks of 20 bytes and 257 rejects of 2 bytes.
+        // the three labels and the terminal slots of the keys, but the leaf holds its value itself when reduced.
+        let num_nodes = if REDUCED_TRIE { 5 } else { 6 };
+        let stats = cedar.clone_shrunk().stats();
+        assert_eq!(
+            (stats.num_keys, stats.num_nodes, stats.size, stats.num_blocks),
+            (3, num_nodes, 512, 2)
+        );
+        assert_eq!(stats.capacity, 512);
+        assert_eq!(stats.estimated_bytes, 512 * 8 + 512 * 2 + 2 * 20 + 257 * 2);
+
+        let dict: Vec<String> = (0..2000).map(|i| format!("{:x}", i * 7919)).collect();
+        let mut num_nodes = stats.num_nodes;
+        for (k, s) in dict.iter().enumerate() {
+            cedar.update(s, k as i32);
+            let stats = cedar.stats();
+            assert!(stats.num_nodes > num_nodes);
+            num_nodes = stats.num_nodes;
+        }
+
+        let stats = cedar.stats();
+        assert_eq!(stats.num_keys, 2003);
+        assert_eq!(stats.num_blocks * 256, stats.size);
+        assert!(stats.size <= stats.capacity);
+        assert_eq!(stats.estimated_bytes, cedar.heap_size());
+    }
+
+    #[test]
+    fn test_from_iter() {
+        let dict = ["a", "ab", "abc", "中华", "网球", "ab"];
+        let cedar: Cedar = dict.iter().enumerate().map(|(k, s)| (*s, k as i32)).collect();
+        let mut expected = Cedar::new();
+        expected.build(&dict.iter().enumerate().map(|(k, s)| (*s, k as i32)).collect::<Vec<_>>());
+        assert_eq!(cedar.keys().collect::<Vec<_>>(), expected.keys().collect::<Vec<_>>());
+        assert_eq!(cedar.exact_match_search("ab").map(|x| x.0), Some(5));
+        assert_eq!(cedar.len(), 5);
+
+        let cedar: Cedar = dict.iter().map(|s| (s.to_string(), s.len() as i32)).collect();
+        assert_eq!(cedar.exact_match_search("中华").map(|x| x.0), Some(6));
+        assert_eq!(cedar.len(), 5);
+    }
+
+    #[test]
+    fn test_extend() {
+        let mut cedar: Cedar = vec![("a", 0), ("ab", 1)].into_iter().collect();
+        cedar.extend(vec![("abc", 2), ("a", 3), ("b", 4)]);
+        let result: Vec<(Vec<u8>, i32)> = cedar.keys().collect();
+        assert_eq!(
+            result,
+            vec![
+                (b"a".to_vec(), 3),
+                (b"ab".to_vec(), 1),
+                (b"abc".to_vec(), 2),
+                (b"b".to_vec(), 4)
+            ]
+        );
+
+        cedar.extend(std::iter::empty());
+        assert_eq!(cedar.len(), 4);
+    }
+
+    #[test]
+    fn test_from_map() {
+        let mut map: HashMap<String, i32> = HashMap::new();
+        for (k, s) in ["a", "ab", "abc", "中华", "网球"].iter().enumerate() {
+            map.insert(s.to_string(), k as i32);
+        }
+
+        let cedar = Cedar::from_map(&map);
+        for (key, value) in map.iter() {
+            assert_eq!(cedar.exact_match_search(key).map(|x| x.0), Some(*value));
+        }
+        assert_eq!(cedar.keys().count(), map.len());
+    }
+
+    #[test]
+    fn test_get_or_insert_with() {
+        let mut cedar = Cedar::new();
+        cedar.update("a", 0);
+
+        let mut calls = 0;
+        for _ in 0..3 {
+            let value = cedar.get_or_insert_with("ab", || {
+                calls += 1;
+                1
+            });
+            assert_eq!(value, 1);
+        }
+        assert_eq!(calls, 1);
+
+        assert_eq!(cedar.get_or_insert_with("a", || unreachable!()), 0);
+        assert_eq!(cedar.exact_match_search("ab").map(|x| x.0), Some(1));
+    }
+
+    #[test]
+    #[cfg(feature = "reduced-trie")]
+    fn test_update_extending_a_leaf_value() {
+        let mut cedar = Cedar::new();
+        cedar.update("ab", 0);
+        cedar.update("abc", 2);
+        cedar.update("a", 3);
+
+        assert_eq!(cedar.exact_match_search("ab").map(|x| x.0), Some(0));
+        assert_eq!(cedar.exact_match_search("abc").map(|x| x.0), Some(2));
+        assert_eq!(cedar.exact_match_search("a").map(|x| x.0), Some(3));
+
+        // the leaf "abc" holding its value in `base_` is extended in turn, and then overwritten.
+        cedar.update("abcd", 4);
+        cedar.update("abc", 5);
+        let result: Vec<(Vec<u8>, i32)> = cedar.keys().collect();
+        assert_eq!(
+            result,
+            vec![
+                (b"a".to_vec(), 3),
+                (b"ab".to_vec(), 0),
+                (b"abc".to_vec(), 5),
+                (b"abcd".to_vec(), 4)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_node_ids_stable_across_growth() {
+        let mut cedar = Cedar::new();
+        cedar.update("abc", 7);
+        let (_, _, id) = cedar.exact_match_search("abc").unwrap();
+        let capacity = cedar.capacity;
+
+        // every new node of a long chain has no children yet, so it takes a free slot without any conflict and
+        // the array grows by appending blocks.
+        for (i, c) in ["x", "y", "z"].iter().enumerate() {
+            cedar.update(&c.repeat(500), i as i32);
+        }
+        assert!(cedar.capacity > capacity);
+
+        assert_eq!(cedar.exact_match_search("abc").unwrap().2, id);
+        assert_eq!(cedar.value_at(id), Some(7));
+        assert_eq!(cedar.find_from(0, b"abc"), Some(id));
+    }
+
+    #[test]
+    fn test_custom_terminal() {
+        let dict = ["a\0b", "a\0", "a", "\0", "\0\0c", "ab"];
+        let mut cedar = Cedar::with_terminal(0xff);
+        for (k, s) in dict.iter().enumerate() {
+            cedar.update(s, k as i32);
+        }
+
+        for (k, s) in dict.iter().enumerate() {
+            assert_eq!(cedar.exact_match_search(s).map(|x| x.0), Some(k as i32));
+        }
+        assert!(cedar.exact_match_search("a\0\0").is_none());
+
+        let result: Vec<i32> = cedar
+            .common_prefix_predict("a\0")
+            .unwrap()
+            .iter()
+            .map(|x| x.0)
+            .collect();
+        assert_eq!(vec![1, 0], result);
+        assert_eq!(
+            cedar.common_prefix_search("a\0b").unwrap(),
+            vec![(2, 0), (1, 1), (0, 2)]
+        );
+
+        let mut keys: Vec<Vec<u8>> = cedar.keys().map(|(k, _)| k).collect();
+        keys.sort();
+        let mut expected: Vec<Vec<u8>> = dict.iter().map(|s| s.as_bytes().to_vec()).collect();
+        expected.sort();
+        assert_eq!(expected, keys);
+
+        let mut buf = Vec::new();
+        cedar.write_to(&mut buf).unwrap();
+        let loaded = Cedar::read_from(&buf[..]).unwrap();
+        assert_eq!(loaded.exact_match_search("\0\0c").map(|x| x.0), Some(4));
+
+        // with the default terminal a key with 0 is never found instead of hitting the terminal slot.
+        let mut cedar = Cedar::new();
+        cedar.update("a", 0);
+        assert!(cedar.exact_match_search("a\0").is_none());
+    }
+
+    #[test]
+    fn test_try_update_invalid_key() {
+        let mut cedar = Cedar::new();
+        assert_eq!(cedar.try_update("a\0b", 1), Err(CedarError::InvalidKey));
+        assert_eq!(cedar.try_update("", 1), Err(CedarError::InvalidKey));
+        assert_eq!(cedar.try_update("ab", 1), Ok(()));
+        assert_eq!(cedar.exact_match_search("ab").map(|x| x.0), Some(1));
+
+        // none of the keys is inserted if one of them is invalid.
+        let mut cedar = Cedar::with_terminal(b'#');
+        assert_eq!(
+            cedar.try_build(&[("a", 1), ("b#c", 2), ("d", 3)]),
+            Err(CedarError::InvalidKey)
+        );
+        assert!(cedar.is_empty());
+        assert_eq!(cedar.try_build(&[("a", 1), ("b\0c", 2)]), Ok(()));
+        assert_eq!(cedar.exact_match_search("b\0c").map(|x| x.0), Some(2));
+        cedar.validate().unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "terminal label")]
+    fn test_custom_terminal_in_key() {
+        let mut cedar = Cedar::with_terminal(b'#');
+        cedar.update("a#b", 0);
+    }
+
+    #[test]
+    fn test_quickcheck_like() {
+        let mut rng = thread_rng();
+        let mut dict: Vec<String> = Vec::with_capacity(1000);
+        for _ in 0..1000 {
+            let chars: Vec<u8> = iter::repeat(()).map(|()| rng.sample(Alphanumeric)).take(30).collect();
+            let s = String::from_utf8(chars).unwrap();
+            dict.push(s);
+        }
+
+        let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (s.as_ref(), k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        for (k, s) in dict.iter().enumerate() {
+            assert_eq!(cedar.exact_match_search(s).map(|x| x.0), Some(k as i32));
+        }
+    }
+
+    #[test]
+    fn test_quickcheck_like_with_deep_trie() {
+        let mut rng = thread_rng();
+        let mut dict: Vec<String> = Vec::with_capacity(1000);
+        let mut s = String::new();
+        for _ in 0..1000 {
+            let c: char = rng.sample(Alphanumeric) as char;
+            s.push(c);
+            dict.push(s.clone());
+        }
+
+        let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (s.as_ref(), k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        for (k, s) in dict.iter().enumerate() {
+            assert_eq!(cedar.exact_match_search(s).map(|x| x.0), Some(k as i32));
+        }
+    }
+
+    #[test]
+    fn test_mass_erase() {
+        let mut rng = thread_rng();
+        let mut dict: Vec<String> = Vec::with_capacity(1000);
+        for _ in 0..1000 {
+            let chars: Vec<u8> = iter::repeat(()).map(|()| rng.sample(Alphanumeric)).take(30).collect();
+            let s = String::from_utf8(chars).unwrap();
+
+            dict.push(s);
+        }
+
+        let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (s.as_ref(), k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        for s in dict.iter() {
+            cedar.erase(s);
+            assert!(cedar.exact_match_search(s).is_none());
+        }
+    }
+
+    #[test]
+    fn test_validate_after_growth() {
+        let mut cedar = Cedar::new();
+        assert_eq!(cedar.validate(), Ok(()));
+
+        // every key of a new first byte and second byte needs a new block, until the array has doubled 4 times.
+        let mut growths = 0;
+        let mut k = 0;
+        while growths < 10 {
+            let size = cedar.size;
+            let capacity = cedar.capacity;
+            cedar.update(&format!("{:04x}/{}", k * 7919, k), k);
+            k += 1;
+
+            if cedar.size != size {
+                growths += 1;
+                assert_eq!(cedar.size, size + 256);
+                assert!(cedar.capacity == capacity || capacity == size);
+                assert_eq!(cedar.validate(), Ok(()));
+            }
+        }
+        assert!(cedar.capacity >= 256 * 11);
+
+        for j in (0..k).step_by(2) {
+            cedar.erase(&format!("{:04x}/{}", j * 7919, j));
+        }
+        assert_eq!(cedar.validate(), Ok(()));
+
+        let (_, _, from) = cedar.exact_match_search(&format!("{:04x}/1", 7919)).unwrap();
+        cedar.array[from].check = cedar.size as i32;
+        assert_eq!(cedar.validate(), Err(CedarError::Corrupted));
+    }
+
+    #[test]
+    fn test_find_orphans() {
+        let mut rng = thread_rng();
+        let mut cedar = Cedar::new();
+        assert!(cedar.find_orphans().is_empty());
+
+        let mut dict: Vec<String> = Vec::new();
+        for round in 0..20 {
+            for _ in 0..200 {
+                let len = rng.gen_range(1..8);
+                let chars: Vec<u8> = iter::repeat(()).map(|()| rng.sample(Alphanumeric)).take(len).collect();
+                let s = String::from_utf8(chars).unwrap();
+                cedar.update(&s, round);
+                dict.push(s);
+            }
+
+            for _ in 0..150 {
+                let s = dict.swap_remove(rng.gen_range(0..dict.len()));
+                cedar.erase(&s);
+            }
+            assert_eq!(cedar.find_orphans(), Vec::<usize>::new());
+        }
+
+        for s in dict.iter() {
+            cedar.erase(s);
+        }
+        assert!(cedar.find_orphans().is_empty());
+    }
+
+    #[test]
+    fn test_try_iterators_on_corruption() {
+        let dict = ["a", "ab", "abc", "b"];
+        let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (*s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        let result: Result<Vec<(i32, usize)>, CedarError> = cedar.try_common_prefix_iter("abc").collect();
+        assert_eq!(result, Ok(cedar.common_prefix_search("abc").unwrap()));
+        let result: Result<Vec<(i32, usize)>, CedarError> = cedar.try_common_prefix_predict_iter("a").collect();
+        assert_eq!(result, Ok(cedar.common_prefix_predict("a").unwrap()));
+        assert_eq!(cedar.try_exact_match_search("ab"), Ok(cedar.exact_match_search("ab")));
+
+        // to point the base of "ab" far beyond the array.
+        let (_, _, ab) = cedar.exact_match_search("ab").unwrap();
+        #[cfg(feature = "reduced-trie")]
+        {
+            cedar.array[ab].base_ = -(1 << 30) - 1;
+        }
+        #[cfg(not(feature = "reduced-trie"))]
+        {
+            cedar.array[ab].base_ = 1 << 30;
+        }
+
+        let result: Vec<Result<(i32, usize), CedarError>> = cedar.try_common_prefix_iter("abc").collect();
+        assert_eq!(result, vec![Ok((0, 0)), Err(CedarError::Corrupted)]);
+        let result: Vec<Result<(i32, usize), CedarError>> = cedar.try_common_prefix_predict_iter("a").collect();
+        assert_eq!(result, vec![Ok((0, 0)), Err(CedarError::Corrupted)]);
+        assert_eq!(cedar.try_exact_match_search("abc"), Err(CedarError::Corrupted));
+        assert_eq!(cedar.try_exact_match_search("b").map(|x| x.map(|x| x.0)), Ok(Some(3)));
+    }
+
+    #[test]
+    fn test_duplication() {
+        let dict = vec!["些许端", "些須", "些须", "亜", "亝", "亞", "亞", "亞丁", "亞丁港"];
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        assert_eq!(cedar.exact_match_search("亞").map(|t| t.0), Some(6));
+        assert_eq!(cedar.exact_match_search("亞丁港").map(|t| t.0), Some(8));
+        assert_eq!(cedar.exact_match_search("亝").map(|t| t.0), Some(4));
+        assert_eq!(cedar.exact_match_search("些須").map(|t| t.0), Some(1));
+    }
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        let dict = vec!["a", "ab", "abc", "中", "中华", "中华人民"];
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        let mut snapshot: Vec<u8> = Vec::new();
+        cedar.snapshot(&mut snapshot).unwrap();
+
+        let mut log: Vec<u8> = Vec::new();
+        cedar.update("abcd", 6);
+        Cedar::log_update(&mut log, "abcd", 6).unwrap();
+        cedar.erase("ab");
+        Cedar::log_erase(&mut log, "ab").unwrap();
+        cedar.update("中华", 7);
+        Cedar::log_update(&mut log, "中华", 7).unwrap();
+        cedar.erase("abcd");
+        Cedar::log_erase(&mut log, "abcd").unwrap();
+        cedar.update("abcd", 8);
+        Cedar::log_update(&mut log, "abcd", 8).unwrap();
+
+        let restored = Cedar::restore(snapshot.as_slice(), log.as_slice()).unwrap();
+        for key in &["a", "ab", "abc", "abcd", "中", "中华", "中华人民", "中华人"] {
+            assert_eq!(
+                cedar.exact_match_search(key).map(|x| x.0),
+                restored.exact_match_search(key).map(|x| x.0)
+            );
+        }
+        assert_eq!(restored.exact_match_search("abcd").map(|x| x.0), Some(8));
+        assert_eq!(restored.exact_match_search("ab"), None);
+
+        let result: Vec<i32> = restored
+            .common_prefix_predict("a")
+            .unwrap()
+            .iter()
+            .map(|x| x.0)
+            .collect();
+        assert_eq!(vec![0, 2, 8], result);
+
+        // a torn record at the end of the log is reported instead of being silently dropped.
+        log.pop();
+        assert!(Cedar::restore(snapshot.as_slice(), log.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_write_to_and_read_from() {
+        let mut rng = thread_rng();
+        let mut dict: Vec<String> = Vec::with_capacity(1000);
+        for _ in 0..1000 {
+            let chars: Vec<u8> = iter::repeat(()).map(|()| rng.sample(Alphanumeric)).take(30).collect();
+            dict.push(String::from_utf8(chars).unwrap());
+        }
+
+        let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (s.as_ref(), k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        let mut buf: Vec<u8> = Vec::new();
+        cedar.write_to(&mut buf).unwrap();
+        let mut loaded = Cedar::read_from(buf.as_slice()).unwrap();
+
+        for (k, s) in dict.iter().enumerate() {
+            assert_eq!(loaded.exact_match_search(s).map(|x| x.0), Some(k as i32));
+        }
+
+        // the loaded trie keeps working for updates.
+        loaded.update("0123456789", 1000);
+        assert_eq!(loaded.exact_match_search("0123456789").map(|x| x.0), Some(1000));
+
+        assert!(Cedar::read_from(&buf[1..]).is_err());
+    }
+
+    #[test]
+    fn test_read_from_lying_size() {
+        let mut cedar = Cedar::new();
+        cedar.build(&[("a", 0), ("ab", 1)]);
+
+        for &packed in [false, true].iter() {
+            let mut buf = Vec::new();
+            cedar.write_to_(&mut buf, packed).unwrap();
+
+            // the sizes overflow or are far beyond the input, neither of them is allocated.
+            for &size in [1u64 << 61, 1 << 44, u64::MAX << 8].iter() {
+                buf[16..24].copy_from_slice(&size.to_le_bytes());
+                assert!(Cedar::read_from(&buf[..]).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_read_from_corrupted() {
+        let mut cedar = Cedar::new();
+        cedar.build(&[("a", 0), ("ab", 1), ("中华", 2)]);
+        let mut buf = Vec::new();
+        cedar.write_to(&mut buf).unwrap();
+
+        let corrupt = |offset: usize, bytes: [u8; 4]| {
+            let mut buf = buf.clone();
+            buf[offset..offset + 4].copy_from_slice(&bytes);
+            Cedar::read_from(&buf[..])
+        };
+
+        // the base of the root, the check of the first node in use, a list head and `max_trial`.
+        #[cfg(feature = "reduced-trie")]
+        let root_base = (-0x7fff_0000i32).to_le_bytes();
+        #[cfg(not(feature = "reduced-trie"))]
+        let root_base = 0x7fff_0000i32.to_le_bytes();
+        assert!(corrupt(48, root_base).is_err());
+        let used = (1..cedar.size).find(|&i| cedar.array[i].check >= 0).unwrap();
+        assert!(corrupt(48 + used * 8 + 4, 0x7fff_0000i32.to_le_bytes()).is_err());
+        assert!(corrupt(32, 1000i32.to_le_bytes()).is_err());
+        assert!(corrupt(36, 0i32.to_le_bytes()).is_err());
+
+        // whatever byte is flipped, the load never panics.
+        for i in 0..buf.len() {
+            let mut flipped = buf.clone();
+            flipped[i] ^= 0xff;
+            let _ = Cedar::read_from(&flipped[..]);
+        }
+    }
+
+    #[test]
+    fn test_assert_roundtrip() {
+        let dict = vec![
+            "a",
+            "ab",
+            "abc",
+            "アルゴリズム",
+            "データ",
+            "構造",
+            "网",
+            "网球",
+            "网球拍",
+            "中",
+            "中华",
+            "中华人民",
+            "中华人民共和国",
+        ];
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+        cedar.assert_roundtrip();
+
+        cedar.erase("网球");
+        cedar.assert_roundtrip();
+        Cedar::new().assert_roundtrip();
+    }
+
+    #[test]
+    fn test_build_external_sorted() {
+        let mut rng = thread_rng();
+        let dict: Vec<String> = (0..500).map(|i| format!("{:x}", i * 7919)).collect();
+        let mut lines: Vec<String> = dict.iter().enumerate().map(|(k, s)| format!("{}\t{}", s, k)).collect();
+        lines.shuffle(&mut rng);
+        // the later value of a key replaces the earlier one even across the runs.
+        lines.insert(0, format!("{}\t-5", dict[7]));
+        lines.push(format!("{}\t9999", dict[8]));
+        let input = lines.join("\n");
+
+        let mut cedar = Cedar::new();
+        let count = cedar
+            .build_external_sorted_(input.as_bytes(), &env::temp_dir(), 64)
+            .unwrap();
+        assert_eq!(count, 502);
+        for (k, s) in dict.iter().enumerate() {
+            let expected = match k {
+                8 => 9999,
+                k => k as i32,
+            };
+            assert_eq!(cedar.exact_match_search(s).map(|x| x.0), Some(expected));
+        }
+        assert_eq!(cedar.keys().count(), 500);
+        assert_eq!(cedar.validate(), Ok(()));
+
+        // the run files are cleaned up, and the malformed lines are rejected.
+        let runs = fs::read_dir(env::temp_dir())
+            .unwrap()
+            .filter(|e| {
+                let name = e.as_ref().unwrap().file_name();
+                name.to_string_lossy()
+                    .starts_with(&format!("cedarwood-run-{}-", process::id()))
+            })
+            .count();
+        assert_eq!(runs, 0);
+        assert!(Cedar::new()
+            .build_external_sorted(&b"abc\n"[..], &env::temp_dir())
+            .is_err());
+        assert!(Cedar::new()
+            .build_external_sorted(&b"abc\tx\n"[..], &env::temp_dir())
+            .is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_roundtrip() {
+        let mut rng = thread_rng();
+        let mut dict: Vec<String> = Vec::with_capacity(1000);
+        for _ in 0..1000 {
+            let len = rng.gen_range(1..20);
+            let chars: Vec<u8> = iter::repeat(()).map(|()| rng.sample(Alphanumeric)).take(len).collect();
+            dict.push(String::from_utf8(chars).unwrap());
+        }
+        let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (s.as_ref(), k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+        for s in dict.iter().step_by(5) {
+            cedar.erase(s);
+        }
+
+        let mut buf: Vec<u8> = Vec::new();
+        ciborium::ser::into_writer(&cedar, &mut buf).unwrap();
+        let mut loaded: Cedar = ciborium::de::from_reader(&buf[..]).unwrap();
+        for s in dict.iter() {
+            assert_eq!(loaded.exact_match_search(s), cedar.exact_match_search(s));
+        }
+        assert_eq!(loaded.len(), cedar.len());
+        assert_eq!(loaded.validate(), Ok(()));
+
+        // the bookkeeping comes along, so the loaded trie keeps working for updates.
+        loaded.update("deserialized", 1000);
+        assert_eq!(loaded.exact_match_search("deserialized").map(|x| x.0), Some(1000));
+    }
+
+    #[test]
+    fn test_keys() {
+        let dict = vec!["a", "ab", "abc", "网", "网球", "网球拍", "中", "中华", "中华人民"];
+        let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (*s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        let result: Vec<(Vec<u8>, i32)> = cedar.keys().collect();
+        let mut expected: Vec<(Vec<u8>, i32)> = key_values.iter().map(|(s, v)| (s.as_bytes().to_vec(), *v)).collect();
+        expected.sort();
+        assert_eq!(expected, result);
+
+        assert_eq!(Cedar::new().keys().count(), 0);
+    }
+
+    #[test]
+    fn test_for_each_completion() {
+        let dict = vec!["a", "ab", "abc", "abd", "b", "中华", "中华人民"];
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        for prefix in ["", "a", "ab", "abc", "中", "x"].iter() {
+            let mut result: Vec<(String, i32)> = Vec::new();
+            cedar.for_each_completion(prefix, |key, value| {
+                result.push((String::from_utf8(key.to_vec()).unwrap(), value))
+            });
+
+            let expected: Vec<(String, i32)> = cedar
+                .prefix_stream(prefix)
+                .map(|(key, value)| (String::from_utf8(key).unwrap(), value as i32))
+                .collect();
+            assert_eq!(expected, result);
+        }
+    }
+
+    #[test]
+    fn test_prefix_cover() {
+        let dict = ["abcd", "abce", "abx", "bcd", "bcde", "cdef", "中华", "中国"];
+        let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (*s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        let cover = cedar.prefix_cover();
+        let mut expected: Vec<Vec<u8>> = vec![b"ab".to_vec(), b"bcd".to_vec(), b"cdef".to_vec()];
+        // "华" and "国" share their first byte in UTF-8.
+        expected.push("中华".as_bytes()[..4].to_vec());
+        assert_eq!(cover, expected);
+
+        // every key has exactly one prefix in the cover.
+        for s in dict.iter() {
+            assert_eq!(cover.iter().filter(|p| s.as_bytes().starts_with(p)).count(), 1);
+        }
+
+        assert!(Cedar::new().prefix_cover().is_empty());
+    }
+
+    #[test]
+    fn test_leaf_keys() {
+        let mut cedar = Cedar::new();
+        cedar.build(&[("a", 0), ("ab", 1), ("abc", 2)]);
+        assert_eq!(cedar.leaf_keys().collect::<Vec<_>>(), vec![(b"abc".to_vec(), 2)]);
+
+        cedar.build(&[("b", 3), ("abd", 4)]);
+        let result: Vec<i32> = cedar.leaf_keys().map(|x| x.1).collect();
+        assert_eq!(result, vec![2, 4, 3]);
+    }
+
+    #[test]
+    fn test_changed_since() {
+        let mut base = Cedar::new();
+        base.build(&[("a", 0), ("ab", 1), ("中华", 2), ("网球", 3)]);
+        let mut cedar = base.clone();
+        cedar.update("abc", 4);
+        cedar.update("中华", 5);
+        cedar.erase("ab");
+
+        let changes = cedar.changed_since(&base);
+        assert_eq!(
+            changes,
+            vec![
+                (b"ab".to_vec(), None),
+                (b"abc".to_vec(), Some(4)),
+                ("中华".as_bytes().to_vec(), Some(5)),
+            ]
+        );
+        assert!(cedar.changed_since(&cedar).is_empty());
+
+        for (key, value) in changes {
+            let key = String::from_utf8(key).unwrap();
+            match value {
+                Some(value) => base.update(&key, value),
+                None => base.erase(&key),
+            }
+        }
+        assert_eq!(base.keys().collect::<Vec<_>>(), cedar.keys().collect::<Vec<_>>());
+
+        // with a custom terminal the keys iterate with 0 after the other bytes, while it is the first in byte order.
+        let mut base = Cedar::with_terminal(0xff);
+        base.build(&[("a", 0), ("a\0", 1)]);
+        let mut cedar = base.clone();
+        cedar.update("ab", 2);
+        assert_eq!(cedar.changed_since(&base), vec![(b"ab".to_vec(), Some(2))]);
+        assert_eq!(base.changed_since(&cedar), vec![(b"ab".to_vec(), None)]);
+        cedar.assert_roundtrip();
+    }
+
+    #[test]
+    fn test_jaccard_similarity() {
+        let mut a = Cedar::new();
+        a.build(&[("a", 0), ("ab", 1), ("中华", 2), ("网球", 3)]);
+        let mut b = Cedar::new();
+        b.build(&[("ab", 10), ("abc", 11), ("中华", 12), ("网", 13)]);
+        let mut c = Cedar::new();
+        c.build(&[("x", 0), ("abcd", 1)]);
+
+        assert_eq!(
+            a.keys_intersection(&b),
+            vec![(b"ab".to_vec(), 1), ("中华".as_bytes().to_vec(), 2)]
+        );
+        assert_eq!(a.jaccard_similarity(&a.clone()), 1.0);
+        assert_eq!(a.jaccard_similarity(&c), 0.0);
+        assert!((a.jaccard_similarity(&b) - 2.0 / 6.0).abs() < 1e-9);
+        assert_eq!(a.jaccard_similarity(&b), b.jaccard_similarity(&a));
+        assert_eq!(Cedar::new().jaccard_similarity(&Cedar::new()), 1.0);
+    }
+
+    #[test]
+    fn test_keys_containing() {
+        let dict = vec![
+            "a",
+            "ab",
+            "abc",
+            "アルゴリズム",
+            "データ",
+            "構造",
+            "网",
+            "网球",
+            "网球拍",
+            "中",
+            "中华",
+            "中华人民",
+            "中华人民共和国",
+        ];
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        let result = cedar.keys_containing("球");
+        assert_eq!(
+            result,
+            vec![("网球".as_bytes().to_vec(), 7), ("网球拍".as_bytes().to_vec(), 8)]
+        );
+
+        let result: Vec<i32> = cedar.keys_containing("人民").iter().map(|x| x.1).collect();
+        assert_eq!(result, vec![11, 12]);
+        assert_eq!(cedar.keys_containing("b").len(), 2);
+        assert_eq!(cedar.keys_containing("").len(), 13);
+        assert!(cedar.keys_containing("xyz").is_empty());
+    }
+
+    #[test]
+    fn test_nth_key() {
+        let dict = ["中华", "a", "abc", "b", "网球", "ab"];
+        let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (*s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        assert_eq!(cedar.nth_key(0), None);
+        cedar.build(&key_values);
+
+        let num_keys = cedar.keys().count();
+        assert_eq!(cedar.nth_key(0), Some((b"a".to_vec(), 1)));
+        assert_eq!(cedar.nth_key(2), Some((b"abc".to_vec(), 2)));
+        assert_eq!(cedar.nth_key(num_keys - 1), Some(("网球".as_bytes().to_vec(), 4)));
+        assert_eq!(cedar.nth_key(num_keys), None);
+    }
+
+    #[test]
+    fn test_assign_ranks() {
+        let dict = ["中华", "a", "abc", "b", "网球", "ab"];
+        let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (*s, 100 + k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+        let num_keys = dict.len();
+
+        assert_eq!(cedar.rank_of("a"), Some(0));
+        assert_eq!(cedar.rank_of("ab"), Some(1));
+        assert_eq!(cedar.rank_of("网球"), Some(num_keys - 1));
+        assert_eq!(cedar.rank_of("中"), None);
+
+        let ranked = cedar.assign_ranks();
+        for s in dict.iter() {
+            assert_eq!(ranked.exact_match_search(s).map(|x| x.0 as usize), cedar.rank_of(s));
+            assert_eq!(ranked.rank_of(s), cedar.rank_of(s));
+        }
+        let values: Vec<i32> = ranked.keys().map(|x| x.1).collect();
+        assert_eq!(values, (0..num_keys as i32).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_fst_style_lookup() {
+        let key_values = vec![("november", 11), ("october", 10), ("oct", 3), ("september", 9)];
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        assert_eq!(cedar.get("october"), Some(10u64));
+        assert_eq!(cedar.get("octo"), None);
+
+        let result: Vec<(Vec<u8>, u64)> = cedar.prefix_stream("oct").collect();
+        assert_eq!(result, vec![(b"oct".to_vec(), 3), (b"october".to_vec(), 10)]);
+        assert_eq!(cedar.prefix_stream("").count(), 4);
+        assert_eq!(cedar.prefix_stream("x").count(), 0);
+    }
+
+    #[test]
+    fn test_duplicate_values() {
+        let key_values = vec![
+            ("color", 5),
+            ("colour", 5),
+            ("red", 1),
+            ("green", 2),
+            ("grey", 3),
+            ("gray", 3),
+        ];
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        let result = cedar.duplicate_values();
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[&5], vec![b"color".to_vec(), b"colour".to_vec()]);
+        assert_eq!(result[&3], vec![b"gray".to_vec(), b"grey".to_vec()]);
+        assert!(!result.contains_key(&1));
+    }
+
+    #[test]
+    fn test_predict_session() {
+        let dict = vec!["a", "ab", "abc", "abd", "b"];
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        let mut session = cedar.predict_session();
+        assert_eq!(session.completions(10).len(), 5);
+
+        assert!(session.push_byte(b'a'));
+        let result: Vec<i32> = session.completions(10).iter().map(|x| x.0).collect();
+        assert_eq!(vec![0, 1, 2, 3], result);
+
+        assert!(session.push_byte(b'b'));
+        let result: Vec<i32> = session.completions(10).iter().map(|x| x.0).collect();
+        assert_eq!(vec![1, 2, 3], result);
+        assert_eq!(session.completions(2).len(), 2);
+
+        assert!(session.push_byte(b'c'));
+        assert_eq!(session.completions(10), vec![(2, 0)]);
+
+        assert!(!session.push_byte(b'x'));
+        assert!(session.completions(10).is_empty());
+        assert!(!session.push_byte(b'a'));
+    }
+
+    #[test]
+    fn test_trace() {
+        let dict = vec!["a", "ab", "abc"];
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        let ids: Vec<usize> = ["a", "ab", "abc"]
+            .iter()
+            .map(|s| cedar.exact_match_search(s).unwrap().2)
+            .collect();
+        assert_eq!(cedar.trace("abc"), vec![(b'a', ids[0]), (b'b', ids[1]), (b'c', ids[2])]);
+        assert_eq!(cedar.trace("abx"), vec![(b'a', ids[0]), (b'b', ids[1])]);
+        assert!(cedar.trace("x").is_empty());
+    }
+
+    #[test]
+    fn test_shrink_to_fit() {
+        let dict: Vec<String> = (0..20000).map(|i| format!("{:x}", i * 7919)).collect();
+        let mut cedar = Cedar::new();
+        for (k, s) in dict.iter().enumerate() {
+            cedar.update(s, k as i32);
+        }
+        for s in dict.iter().skip(100) {
+            cedar.erase(s);
+        }
+
+        let capacity = cedar.capacity;
+        cedar.shrink_to_fit();
+        assert!(cedar.capacity < capacity);
+        assert_eq!(cedar.capacity, cedar.size);
+        assert_eq!(cedar.validate(), Ok(()));
+        for (k, s) in dict.iter().enumerate() {
+            let expected = if k < 100 { Some(k as i32) } else { None };
+            assert_eq!(cedar.exact_match_search(s).map(|x| x.0), expected);
+        }
+
+        // the trie grows again from the shrunk size.
+        for (k, s) in dict.iter().enumerate().skip(100) {
+            cedar.update(s, k as i32);
+        }
+        assert_eq!(cedar.validate(), Ok(()));
+        assert_eq!(cedar.len(), dict.len());
+    }
+
+    #[test]
+    fn test_clone_shrunk() {
+        let mut cedar = Cedar::new();
+        let mut n = 0;
+        while cedar.size == cedar.capacity {
+            cedar.update(&format!("key{}", n), n);
+            n += 1;
+        }
+
+        let cloned = cedar.clone();
+        let mut shrunk = cedar.clone_shrunk();
+        assert!(shrunk.heap_size() < cloned.heap_size());
+
+        for i in 0..n {
+            let key = format!("key{}", i);
+            assert_eq!(shrunk.exact_match_search(&key), cloned.exact_match_search(&key));
+        }
+        assert_eq!(shrunk.keys().count(), n as usize);
+
+        // the shrunk clone still grows on demand.
+        for i in n..(n + 1000) {
+            shrunk.update(&format!("key{}", i), i);
+        }
+        for i in 0..(n + 1000) {
+            assert_eq!(shrunk.exact_match_search(&format!("key{}", i)).map(|x| x.0), Some(i));
+        }
+    }
+
+    #[test]
+    fn test_common_prefix_scan() {
+        let dict = vec!["fo", "foo", "oo", "bar", "中华", "华人"];
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        let result: Vec<(i32, usize, usize)> = cedar.common_prefix_scan("foobar").collect();
+        assert_eq!(vec![(0, 0, 2), (1, 0, 3), (2, 1, 3), (3, 3, 6)], result);
+
+        let result: Vec<(i32, usize, usize)> = cedar.common_prefix_scan("中华人").collect();
+        assert_eq!(vec![(4, 0, 6), (5, 3, 9)], result);
+
+        assert_eq!(cedar.common_prefix_scan("").count(), 0);
+        assert_eq!(cedar.common_prefix_scan("xyz").count(), 0);
+    }
+
+    #[test]
+    fn test_scan_merged_spans() {
+        let mut cedar = Cedar::new();
+        cedar.build(&[("foo", 0), ("oob", 1), ("bar", 2), ("baz", 3), ("qux", 4), ("中华", 5)]);
+
+        assert_eq!(cedar.scan_merged_spans("foobar"), vec![(0, 6)]);
+        assert_eq!(cedar.scan_merged_spans("xfoo-bar"), vec![(1, 4), (5, 8)]);
+        assert_eq!(cedar.scan_merged_spans("bazqux"), vec![(0, 6)]);
+        assert_eq!(cedar.scan_merged_spans("的中华的"), vec![(3, 9)]);
+        assert!(cedar.scan_merged_spans("none").is_empty());
+    }
+
+    #[test]
+    fn test_scan_fast() {
+        let mut rng = thread_rng();
+        let mut cedar = Cedar::new();
+        for k in 0..300 {
+            let len = rng.gen_range(1..5);
+            let key: String = iter::repeat(())
+                .map(|()| rng.gen_range(b'a'..b'e') as char)
+                .take(len)
+                .collect();
+            cedar.update(&key, k);
+        }
+        cedar.update("中华", 300);
+        cedar.update("华人", 301);
+
+        let sorted = |mut matches: Vec<(i32, usize, usize)>| {
+            matches.sort_by_key(|&(_, start, end)| (start, end));
+            matches
+        };
+        for _ in 0..50 {
+            let len = rng.gen_range(0..30);
+            let mut text: String = iter::repeat(())
+                .map(|()| rng.gen_range(b'a'..b'f') as char)
+                .take(len)
+                .collect();
+            text.push_str("中华人");
+            let expected: Vec<(i32, usize, usize)> = cedar.common_prefix_scan(&text).collect();
+            assert_eq!(sorted(cedar.scan_fast(&text)), expected);
+        }
+
+        // the failure links are dropped by a mutation, and the next scan sees the new key.
+        cedar.update("人", 302);
+        assert!(cedar.automaton.get().is_none());
+        assert!(cedar.scan_fast("中华人").contains(&(302, 6, 9)));
+    }
+
+    #[test]
+    fn test_serialized_len() {
+        let dict = vec![
+            "a",
+            "ab",
+            "abc",
+            "アルゴリズム",
+            "データ",
+            "構造",
+            "网",
+            "网球",
+            "网球拍",
+            "中",
+            "中华",
+            "中华人民",
+            "中华人民共和国",
+        ];
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
 
-        base ^= children[0] as i32;
+        let mut buf = Vec::new();
+        cedar.write_to(&mut buf).unwrap();
+        assert_eq!(cedar.serialized_len(), buf.len());
 
-        let (from, base_) = if flag {
-            (from_n as i32, base_n)
-        } else {
-            (from_p, base_p)
-        };
+        // the failure links of `scan_fast` are written as well once built.
+        cedar.build_scan_automaton();
+        let mut buf = Vec::new();
+        cedar.write_to(&mut buf).unwrap();
+        assert_eq!(cedar.serialized_len(), buf.len());
+    }
 
-        if flag && children[0] == label_n {
-            self.n_infos[from as usize].child = label_n;
+    #[test]
+    fn test_write_to_packed() {
+        let dict: Vec<String> = (0..1000).map(|i| format!("{:x}", i * 7919)).collect();
+        let mut cedar = Cedar::new();
+        for (k, s) in dict.iter().enumerate() {
+            cedar.update(s, (k * 60) as i32);
         }
+        cedar.erase(&dict[10]);
+
+        let mut plain = Vec::new();
+        cedar.write_to(&mut plain).unwrap();
+        let mut packed = Vec::new();
+        cedar.write_to_packed(&mut packed).unwrap();
+        assert!(packed.len() < plain.len());
+
+        let loaded = Cedar::read_from(&packed[..]).unwrap();
+        let mut reloaded = Vec::new();
+        loaded.write_to(&mut reloaded).unwrap();
+        assert_eq!(reloaded, plain);
+        assert_eq!(loaded.keys().collect::<Vec<_>>(), cedar.keys().collect::<Vec<_>>());
+
+        // the values need all of the 4 bytes once one of them does not fit in u16.
+        cedar.update("large", 70000);
+        let (mut plain, mut packed) = (Vec::new(), Vec::new());
+        cedar.write_to(&mut plain).unwrap();
+        cedar.write_to_packed(&mut packed).unwrap();
+        assert_eq!(packed, plain);
+
+        let mut small = Cedar::new();
+        small.build(&[("a", 0), ("ab", 255)]);
+        let mut packed = Vec::new();
+        small.write_to_packed(&mut packed).unwrap();
+        assert_eq!(
+            u32::from_le_bytes(packed[12..16].try_into().unwrap()) & CEDAR_FLAG_VALUES_U8,
+            CEDAR_FLAG_VALUES_U8
+        );
+        let loaded = Cedar::read_from(&packed[..]).unwrap();
+        assert_eq!(loaded.exact_match_search("ab").map(|x| x.0), Some(255));
+    }
 
-        #[cfg(feature = "reduced-trie")]
+    #[test]
+    fn test_scan_automaton_serialization() {
+        let mut cedar = Cedar::new();
+        cedar.build(&[("he", 0), ("she", 1), ("his", 2), ("hers", 3)]);
+        let expected = vec![(1, 1, 4), (0, 2, 4), (3, 2, 6)];
+
+        let mut buf = Vec::new();
+        cedar.write_to(&mut buf).unwrap();
+        cedar.build_scan_automaton();
+        let mut buf_with_links = Vec::new();
+        cedar.write_to(&mut buf_with_links).unwrap();
+        assert_eq!(buf_with_links.len(), buf.len() + cedar.size * 12);
+
+        let loaded = Cedar::read_from(&buf_with_links[..]).unwrap();
+        assert!(loaded.automaton.get().is_some());
+        assert_eq!(loaded.scan_fast("ushers"), expected);
+
+        let loaded = Cedar::read_from(&buf[..]).unwrap();
+        assert!(loaded.automaton.get().is_none());
+        assert_eq!(loaded.scan_fast("ushers"), expected);
+        assert!(loaded.automaton.get().is_some());
+
+        // a node failing to itself, an output out of the array and a wrong depth drop the links on the load.
+        let node = cedar.exact_match_search("hers").unwrap().2;
+        let link = |section: usize, i: usize| buf.len() + section * cedar.size * 4 + i * 4;
+        for &(offset, x) in [
+            (link(0, node), node as u32),
+            (link(1, node), 1 << 30),
+            (link(2, node), 1),
+        ]
+        .iter()
         {
-            self.array[from as usize].base_ = -base - 1;
+            let mut corrupted = buf_with_links.clone();
+            corrupted[offset..offset + 4].copy_from_slice(&x.to_le_bytes());
+            let loaded = Cedar::read_from(&corrupted[..]).unwrap();
+            assert!(loaded.automaton.get().is_none());
+            assert_eq!(loaded.scan_fast("ushers"), expected);
         }
 
-        #[cfg(not(feature = "reduced-trie"))]
-        {
-            self.array[from as usize].base_ = base;
+        for i in buf.len()..buf_with_links.len() {
+            let mut corrupted = buf_with_links.clone();
+            corrupted[i] ^= 0xff;
+            let _ = Cedar::read_from(&corrupted[..]).unwrap().scan_fast("ushers");
         }
+    }
 
-        // the actual work for relocating the chilren
-        for i in 0..(children.len()) {
-            let to = self.pop_e_node(base, children[i], from);
-            let to_ = base_ ^ (children[i] as i32);
+    #[test]
+    fn test_common_prefix_scan_annotated() {
+        let dict = vec!["fo", "foo", "oo"];
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
 
-            if i == children.len() - 1 {
-                self.n_infos[to as usize].sibling = 0;
+        let result: Vec<(i32, usize, usize, bool)> = cedar.common_prefix_scan_annotated("foo").collect();
+        assert_eq!(vec![(0, 0, 2, true), (1, 0, 3, false), (2, 1, 3, false)], result);
+    }
+
+    #[test]
+    fn test_common_prefix_scan_longest() {
+        let dict = vec!["fo", "foo", "foobar", "ob", "bar"];
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        let result: Vec<(i32, usize, usize)> = cedar.common_prefix_scan_longest("foobarxfoobfox").collect();
+        assert_eq!(vec![(2, 0, 6), (1, 7, 10), (0, 11, 13)], result);
+        assert_eq!(cedar.common_prefix_scan_longest("xyz").next(), None);
+    }
+
+    #[test]
+    fn test_entries_with_ids() {
+        let dict = vec!["a", "ab", "abc", "中华", "中华人民"];
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        for (key, value, id) in cedar.entries_with_ids() {
+            let key = String::from_utf8(key).unwrap();
+            assert_eq!(cedar.exact_match_search(&key), Some((value, key.len(), id)));
+        }
+    }
+
+    #[test]
+    fn test_build_prioritized() {
+        let priority = vec![("hot", 0), ("hotter", 1), ("hottest", 2)];
+        let keys: Vec<String> = (0..1000).map(|i| format!("key{}", i)).collect();
+        let rest: Vec<(&str, i32)> = keys
+            .iter()
+            .enumerate()
+            .map(|(k, s)| (s.as_ref(), k as i32 + 3))
+            .collect();
+        let mut cedar = Cedar::new();
+        cedar.build_prioritized(&priority, &rest);
+
+        let (mut hot_sum, mut hot_count, mut rest_sum, mut rest_count) = (0, 0, 0, 0);
+        for (key, _, id) in cedar.entries_with_ids() {
+            if key.starts_with(b"hot") {
+                hot_sum += id;
+                hot_count += 1;
             } else {
-                self.n_infos[to as usize].sibling = children[i + 1];
+                rest_sum += id;
+                rest_count += 1;
             }
+        }
 
-            if flag && to_ == to_pn {
-                continue;
-            }
+        assert_eq!(hot_count, 3);
+        assert_eq!(rest_count, 1000);
+        assert!(hot_sum / hot_count < rest_sum / rest_count);
+    }
 
-            self.array[to as usize].base_ = self.array[to_ as usize].base_;
+    #[test]
+    fn test_bytes_query_through_multibyte_chars() {
+        let dict = vec!["中", "中华", "中华人民"];
+        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
 
-            #[cfg(feature = "reduced-trie")]
-            let condition = self.array[to as usize].base_ < 0 && children[i] != 0;
-            #[cfg(not(feature = "reduced-trie"))]
-            let condition = self.array[to as usize].base_ > 0 && children[i] != 0;
+        let bytes = "中华人民".as_bytes();
+        assert_eq!(cedar.exact_match_search_bytes(&bytes[..3]).map(|x| x.0), Some(0));
+        assert_eq!(cedar.exact_match_search_bytes(&bytes[..4]), None);
+        assert_eq!(cedar.exact_match_search_bytes(&bytes[..5]), None);
 
-            if condition {
-                let mut c = self.n_infos[to_ as usize].child;
+        // the prefix search stops in the middle of "华" with the byte offset of the last byte of "中".
+        assert_eq!(cedar.common_prefix_search_bytes(&bytes[..5]), Some(vec![(0, 2)]));
+        assert_eq!(
+            cedar.common_prefix_search_bytes(&bytes[..10]),
+            Some(vec![(0, 2), (1, 5)])
+        );
+        assert_eq!(cedar.common_prefix_search_bytes(&bytes[1..]), Some(vec![]));
+        assert_eq!(cedar.common_prefix_search_bytes(&[0xff, 0xfe]), Some(vec![]));
+    }
 
-                self.n_infos[to as usize].child = c;
+    #[test]
+    fn test_update_bytes() {
+        let keys: Vec<&[u8]> = vec![&[0xff], &[0xff, 0xfe], b"a\xffb", &[0x80, 0x81, 0x82], b"abc"];
+        let mut cedar = Cedar::new();
+        for (k, key) in keys.iter().enumerate() {
+            cedar.update_bytes(key, k as i32);
+        }
 
-                loop {
-                    let idx = (self.array[to as usize].base() ^ (c as i32)) as usize;
-                    self.array[idx].check = to;
-                    c = self.n_infos[idx].sibling;
+        for (k, key) in keys.iter().enumerate() {
+            assert_eq!(
+                cedar.exact_match_search_bytes(key),
+                Some((k as i32, key.len(), cedar.find_from(0, key).unwrap()))
+            );
+        }
+        assert_eq!(cedar.exact_match_search_bytes(&[0x80, 0x81]), None);
+        assert_eq!(cedar.exact_match_search("abc").map(|x| x.0), Some(4));
+        assert_eq!(
+            cedar.common_prefix_search_bytes(&[0xff, 0xfe, 0xfd]),
+            Some(vec![(0, 0), (1, 1)])
+        );
 
-                    if c == 0 {
-                        break;
-                    }
-                }
-            }
+        cedar.erase_bytes(&[0xff]);
+        assert_eq!(cedar.exact_match_search_bytes(&[0xff]), None);
+        assert_eq!(cedar.exact_match_search_bytes(&[0xff, 0xfe]).map(|x| x.0), Some(1));
+        assert_eq!(cedar.len(), 4);
+    }
 
-            if !flag && to_ == (from_n as i32) {
-                from_n = to as usize;
-            }
+    #[test]
+    fn test_build_checked() {
+        let mut cedar = Cedar::new();
+        assert_eq!(cedar.build_checked(&[("a", 0), ("ab", 1), ("中华", 2)]), Ok(()));
 
-            // clean up the space that was moved away from.
-            if !flag && to_ == to_pn {
-                self.push_sibling(from_n, to_pn ^ (label_n as i32), label_n, true);
-                self.n_infos[to_ as usize].child = 0;
+        let result = cedar.build_checked(&[("abc", 3), ("ab", 4), ("abc", 5), ("b", 6)]);
+        assert_eq!(result, Err(vec![("ab", 1, 4), ("abc", 3, 5)]));
+        assert_eq!(cedar.exact_match_search("ab").map(|x| x.0), Some(4));
+        assert_eq!(cedar.exact_match_search("abc").map(|x| x.0), Some(5));
+        assert_eq!(cedar.exact_match_search("b").map(|x| x.0), Some(6));
+    }
 
-                #[cfg(feature = "reduced-trie")]
-                {
-                    self.array[to_ as usize].base_ = CEDAR_VALUE_LIMIT;
-                }
+    #[test]
+    fn test_auto_tune_max_trial() {
+        let dict: Vec<String> = (0..2000).map(|i| format!("{:x}", i * 7919)).collect();
+        let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (s.as_ref(), k as i32)).collect();
 
-                #[cfg(not(feature = "reduced-trie"))]
-                {
-                    if label_n != 0 {
-                        self.array[to_ as usize].base_ = -1;
-                    } else {
-                        self.array[to_ as usize].base_ = 0;
-                    }
-                }
+        let mut cedar = Cedar::new();
+        let max_trial = cedar.auto_tune_max_trial(&key_values[..500]);
+        assert!([1, 2, 4, 8, 16].contains(&max_trial));
+        assert_eq!(cedar.max_trial, max_trial);
+        assert_eq!(cedar.num_nodes(), 0);
 
-                self.array[to_ as usize].check = from_n as i32;
-            } else {
-                self.push_e_node(to_);
-            }
+        cedar.build(&key_values);
+        for (key, value) in key_values.iter() {
+            assert_eq!(cedar.exact_match_search(key).map(|x| x.0), Some(*value));
         }
+        assert_eq!(cedar.validate(), Ok(()));
+
+        // the densest within twice the fastest time, with the densities within the tolerance as a tie.
+        let ms = std::time::Duration::from_millis;
+        let trials = [
+            (1, ms(10), 0.90),
+            (2, ms(12), 0.905),
+            (4, ms(15), 0.95),
+            (8, ms(25), 0.99),
+        ];
+        assert_eq!(Cedar::pick_max_trial(&trials), 4);
+        let trials = [(1, ms(10), 0.90), (2, ms(12), 0.905), (4, ms(30), 0.95)];
+        assert_eq!(Cedar::pick_max_trial(&trials), 1);
+    }
 
-        // return the position that is free now.
-        if flag {
-            base ^ (label_n as i32)
-        } else {
-            to_pn
+    #[test]
+    fn test_max_trial_density() {
+        // the samples on which probing a block up to 4 times saves a block, the effect depends on the keys.
+        #[cfg(feature = "reduced-trie")]
+        let num_keys = 1500;
+        #[cfg(not(feature = "reduced-trie"))]
+        let num_keys = 1000;
+        let dict: Vec<String> = (0..num_keys).map(|i| format!("{:x}", i * 7919)).collect();
+        let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (s.as_ref(), k as i32)).collect();
+
+        let size = |max_trial| {
+            let mut cedar = Cedar::with_options(max_trial, true);
+            cedar.build(&key_values);
+            cedar.size
+        };
+        assert_eq!(size(1) - size(4), 256);
+    }
+
+    #[test]
+    fn test_build_cancellable() {
+        let dict: Vec<String> = (0..500_000u64).map(|i| format!("{:x}", i * 7919)).collect();
+        let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (s.as_ref(), k as i32)).collect();
+
+        let cancel = AtomicBool::new(false);
+        let mut cedar = Cedar::new();
+        let result = std::thread::scope(|s| {
+            s.spawn(|| {
+                std::thread::sleep(std::time::Duration::from_millis(10));
+                cancel.store(true, Ordering::Relaxed);
+            });
+            cedar.build_cancellable(&key_values, &cancel)
+        });
+        assert_eq!(result, Err(CedarError::Cancelled));
+
+        // the keys are inserted in order, so the partial trie holds exactly the ones before the cut.
+        let n = cedar.keys().count();
+        assert!(n < key_values.len());
+        for (key, value) in key_values[..n].iter() {
+            assert_eq!(cedar.exact_match_search(key).map(|x| x.0), Some(*value));
         }
+        assert_eq!(cedar.exact_match_search(key_values[n].0), None);
+        assert_eq!(cedar.validate(), Ok(()));
+
+        let mut cedar = Cedar::new();
+        assert_eq!(
+            cedar.build_cancellable(&key_values[..10], &cancel),
+            Err(CedarError::Cancelled)
+        );
+        assert_eq!(cedar.num_nodes(), 0);
+        cancel.store(false, Ordering::Relaxed);
+        assert_eq!(cedar.build_cancellable(&key_values[..10], &cancel), Ok(()));
+        assert_eq!(cedar.keys().count(), 10);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use rand::distributions::Alphanumeric;
-    use rand::{thread_rng, Rng};
-    use std::iter;
+    #[test]
+    fn test_build_tracking_peak() {
+        let dict: Vec<String> = (0..3000).map(|i| format!("{:x}", i * 7919)).collect();
+        let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (s.as_ref(), k as i32)).collect();
+
+        let mut cedar = Cedar::new();
+        let peak = cedar.build_tracking_peak(&key_values);
+        assert!(peak >= cedar.capacity);
+        assert!(peak > 256);
+        assert_eq!(peak % 256, 0);
+        assert!((peak / 256).is_power_of_two());
+        for (key, value) in key_values.iter() {
+            assert_eq!(cedar.exact_match_search(key).map(|x| x.0), Some(*value));
+        }
+    }
 
     #[test]
-    fn test_insert_and_delete() {
-        let dict = vec!["a"];
-        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+    fn test_map_rebuild() {
+        let key_values = vec![("a", 1), ("ab", 2), ("abc", 3), ("b", 0), ("bc", 5), ("中华", 2)];
         let mut cedar = Cedar::new();
         cedar.build(&key_values);
 
-        let result = cedar.exact_match_search("ab").map(|x| x.0);
-        assert_eq!(None, result);
+        let mapped = cedar.map_rebuild(|_, value| if value < 2 { None } else { Some(value * 2) });
+        let result: Vec<(Vec<u8>, i32)> = mapped.keys().collect();
+        let expected: Vec<(Vec<u8>, i32)> = vec![
+            (b"ab".to_vec(), 4),
+            (b"abc".to_vec(), 6),
+            (b"bc".to_vec(), 10),
+            ("中华".as_bytes().to_vec(), 4),
+        ];
+        assert_eq!(result, expected);
+        assert!(mapped.num_nodes() < cedar.num_nodes());
+        assert_eq!(cedar.keys().count(), 6);
 
-        cedar.update("ab", 1);
-        let result = cedar.exact_match_search("ab").map(|x| x.0);
-        assert_eq!(Some(1), result);
+        // the key is there to filter on too.
+        let mapped = cedar.map_rebuild(|key, value| if key.starts_with(b"a") { Some(value) } else { None });
+        assert_eq!(mapped.keys().map(|x| x.1).collect::<Vec<_>>(), vec![1, 2, 3]);
+    }
 
-        cedar.erase("ab");
-        let result = cedar.exact_match_search("ab").map(|x| x.0);
-        assert_eq!(None, result);
+    #[test]
+    fn test_build_with() {
+        let dict = vec!["a", "ab", "abc", "中华"];
+        let mut cedar = Cedar::new();
+        cedar.build_with(&dict, |k| k.len() as i32);
 
-        cedar.update("abc", 2);
-        let result = cedar.exact_match_search("abc").map(|x| x.0);
-        assert_eq!(Some(2), result);
+        assert_eq!(cedar.exact_match_search("abc").map(|x| x.0), Some(3));
+        assert_eq!(cedar.exact_match_search("a").map(|x| x.0), Some(1));
+        assert_eq!(cedar.exact_match_search("中华").map(|x| x.0), Some(6));
+    }
 
-        cedar.erase("abc");
-        let result = cedar.exact_match_search("abc").map(|x| x.0);
-        assert_eq!(None, result);
+    #[test]
+    fn test_build_namespaced() {
+        let mut cedar = Cedar::new();
+        cedar.build_namespaced("t1:", &[("abc", 1), ("中华", 2)]);
+        cedar.build_namespaced("t2:", &[("abc", 3)]);
+
+        assert_eq!(cedar.exact_match_search("t1:abc").map(|x| x.0), Some(1));
+        assert_eq!(cedar.exact_match_search("t1:中华").map(|x| x.0), Some(2));
+        assert_eq!(cedar.exact_match_search("t2:abc").map(|x| x.0), Some(3));
+        assert_eq!(cedar.exact_match_search("abc"), None);
+        assert_eq!(cedar.exact_match_search("t2:中华"), None);
     }
 
     #[test]
-    fn test_common_prefix_search() {
-        let dict = vec![
-            "a",
-            "ab",
-            "abc",
-            "アルゴリズム",
-            "データ",
-            "構造",
-            "网",
-            "网球",
-            "网球拍",
-            "中",
-            "中华",
-            "中华人民",
-            "中华人民共和国",
-        ];
+    fn test_common_prefix_search_chars() {
+        let dict = ["a", "中", "中华", "中华人民", "中华人民共和国"];
+        let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (*s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        let key = "中华人民共和国";
+        assert_eq!(
+            cedar.common_prefix_search(key).unwrap(),
+            vec![(1, 2), (2, 5), (3, 11), (4, 20)]
+        );
+        assert_eq!(
+            cedar.common_prefix_search_chars(key),
+            vec![(1, 0), (2, 1), (3, 3), (4, 6)]
+        );
+        assert_eq!(cedar.common_prefix_search_chars("ab"), vec![(0, 0)]);
+        assert!(cedar.common_prefix_search_chars("华").is_empty());
+    }
+
+    #[test]
+    fn test_common_prefix_search_verbose() {
+        let dict = vec!["a", "ab", "abc", "中", "中华", "中华人民"];
         let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
         let mut cedar = Cedar::new();
         cedar.build(&key_values);
 
-        let result: Vec<i32> = cedar
-            .common_prefix_search("abcdefg")
-            .unwrap()
-            .iter()
-            .map(|x| x.0)
-            .collect();
-        assert_eq!(vec![0, 1, 2], result);
+        let result = cedar.common_prefix_search_verbose("abcd");
+        let values: Vec<(i32, usize)> = result.iter().map(|x| (x.1, x.2)).collect();
+        assert_eq!(vec![(0, 1), (1, 2), (2, 3)], values);
 
-        let result: Vec<i32> = cedar
-            .common_prefix_search("网球拍卖会")
-            .unwrap()
-            .iter()
-            .map(|x| x.0)
-            .collect();
-        assert_eq!(vec![6, 7, 8], result);
+        let key = "中华人民共和国";
+        let result = cedar.common_prefix_search_verbose(key);
+        assert_eq!(result.len(), 3);
+        for (id, value, len) in result {
+            assert_eq!(cedar.find_from(0, &key.as_bytes()[..len]), Some(id));
+            assert_eq!(cedar.value_at(id), Some(value));
+        }
 
-        let result: Vec<i32> = cedar
-            .common_prefix_search("中华人民共和国")
-            .unwrap()
-            .iter()
-            .map(|x| x.0)
-            .collect();
-        assert_eq!(vec![9, 10, 11, 12], result);
+        assert_eq!(cedar.find_from(0, b"abd"), None);
+        assert_eq!(cedar.find_from(0, b""), Some(0));
+        let id = cedar.find_from(0, b"a").unwrap();
+        assert_eq!(cedar.find_from(id, b"bc"), cedar.exact_match_search("abc").map(|x| x.2));
+    }
 
-        let result: Vec<i32> = cedar
-            .common_prefix_search("データ構造とアルゴリズム")
-            .unwrap()
-            .iter()
-            .map(|x| x.0)
-            .collect();
-        assert_eq!(vec![4], result);
+    #[test]
+    fn test_invalid_cursor() {
+        let mut cedar = Cedar::new();
+        cedar.build(&[("a", 0), ("ab", 1)]);
+
+        assert_eq!(cedar.find_from(usize::MAX, b"ab"), None);
+        assert_eq!(cedar.find_from(usize::MAX, b""), None);
+        assert_eq!(cedar.value_at(usize::MAX), None);
+
+        // a free slot inside the array is not a cursor either.
+        let free = (1..cedar.size).find(|&i| cedar.array[i].check < 0).unwrap();
+        assert_eq!(cedar.find_from(free, b""), None);
+        assert_eq!(cedar.value_at(free), None);
+    }
+
+    #[test]
+    fn test_export_and_import_entries() {
+        let dict: Vec<String> = (0..1000).map(|i| format!("entry{:04}", i)).collect();
+        let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (s.as_ref(), k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        let mut entries = Vec::new();
+        cedar.export_entries(&mut entries).unwrap();
+        let mut array = Vec::new();
+        cedar.write_to(&mut array).unwrap();
+        assert!(entries.len() < array.len());
+
+        let imported = Cedar::import_entries(&entries[..]).unwrap();
+        assert_eq!(imported.keys().collect::<Vec<_>>(), cedar.keys().collect::<Vec<_>>());
+
+        assert!(Cedar::import_entries(&entries[..entries.len() - 1]).is_err());
+        assert_eq!(Cedar::import_entries(&[][..]).unwrap().keys().count(), 0);
+
+        // a corrupted length is an error instead of an allocation of the 4 GiB it claims.
+        let err = Cedar::import_entries(&[0xffu8, 0xff, 0xff, 0xff, b'a', 0, 0, 0, 0][..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
     }
 
     #[test]
-    fn test_common_prefix_iter() {
-        let dict = vec![
-            "a",
-            "ab",
-            "abc",
-            "アルゴリズム",
-            "データ",
-            "構造",
-            "网",
-            "网球",
-            "网球拍",
-            "中",
-            "中华",
-            "中华人民",
-            "中华人民共和国",
-        ];
-
-        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+    fn test_erase_lines() {
+        let dict = ["a", "ab", "abc", "中华"];
+        let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (*s, k as i32)).collect();
         let mut cedar = Cedar::new();
         cedar.build(&key_values);
 
-        let result: Vec<i32> = cedar.common_prefix_iter("abcdefg").map(|x| x.0).collect();
-        assert_eq!(vec![0, 1, 2], result);
+        let list = "ab\r\nmissing\n中华\n\nab\nabcd";
+        assert_eq!(cedar.erase_lines(io::Cursor::new(list)).unwrap(), 2);
+        assert_eq!(cedar.exact_match_search("ab"), None);
+        assert_eq!(cedar.exact_match_search("中华"), None);
+        assert_eq!(cedar.exact_match_search("a").map(|x| x.0), Some(0));
+        assert_eq!(cedar.exact_match_search("abc").map(|x| x.0), Some(2));
+    }
 
-        let result: Vec<i32> = cedar.common_prefix_iter("网球拍卖会").map(|x| x.0).collect();
-        assert_eq!(vec![6, 7, 8], result);
+    #[test]
+    fn test_replay_log() {
+        let mut log: Vec<u8> = Vec::new();
+        Cedar::log_update(&mut log, "a", 0).unwrap();
+        Cedar::log_update(&mut log, "ab", 1).unwrap();
+        Cedar::log_update(&mut log, "abc", 2).unwrap();
+        Cedar::log_erase(&mut log, "ab").unwrap();
+        Cedar::log_update(&mut log, "a", 3).unwrap();
+        Cedar::log_erase(&mut log, "xyz").unwrap();
 
-        let result: Vec<i32> = cedar.common_prefix_iter("中华人民共和国").map(|x| x.0).collect();
-        assert_eq!(vec![9, 10, 11, 12], result);
+        let mut cedar = Cedar::new();
+        assert_eq!(cedar.replay_log(log.as_slice()).unwrap(), 6);
+        assert_eq!(cedar.exact_match_search("a").map(|x| x.0), Some(3));
+        assert_eq!(cedar.exact_match_search("ab"), None);
+        assert_eq!(cedar.exact_match_search("abc").map(|x| x.0), Some(2));
 
-        let result: Vec<i32> = cedar
-            .common_prefix_iter("データ構造とアルゴリズム")
-            .map(|x| x.0)
-            .collect();
-        assert_eq!(vec![4], result);
+        let mut cedar = Cedar::new();
+        assert!(cedar.replay_log(&[7u8, 1, 0, 0, 0, b'a', 0, 0, 0, 0][..]).is_err());
+        assert_eq!(cedar.replay_log(&[][..]).unwrap(), 0);
+
+        // a corrupted length fails at the end of the log instead of allocating the 4 GiB it claims.
+        let mut log: Vec<u8> = Vec::new();
+        Cedar::log_update(&mut log, "a", 0).unwrap();
+        log.extend_from_slice(&[LOG_OP_UPDATE, 0xff, 0xff, 0xff, 0xff, b'b', 0, 0, 0, 0]);
+        let err = Cedar::new().replay_log(log.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
     }
 
     #[test]
-    fn test_common_prefix_predict() {
-        let dict = vec!["a", "ab", "abc"];
+    fn test_pattern_search() {
+        let dict = vec!["abc", "bbc", "cbc", "axc", "abd", "ab", "abcc"];
         let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
         let mut cedar = Cedar::new();
         cedar.build(&key_values);
 
-        let result: Vec<i32> = cedar.common_prefix_predict("a").unwrap().iter().map(|x| x.0).collect();
-        assert_eq!(vec![0, 1, 2], result);
+        let pattern = vec![ByteClass::Set(b"ab".to_vec()), ByteClass::Any, ByteClass::Exact(b'c')];
+        let result = cedar.pattern_search(&pattern);
+        assert_eq!(
+            vec![(b"abc".to_vec(), 0), (b"axc".to_vec(), 3), (b"bbc".to_vec(), 1)],
+            result
+        );
+
+        let result = cedar.pattern_search(&[ByteClass::Any, ByteClass::Any]);
+        assert_eq!(vec![(b"ab".to_vec(), 5)], result);
+
+        assert!(cedar.pattern_search(&[]).is_empty());
+        assert!(cedar
+            .pattern_search(&[ByteClass::Exact(b'z'), ByteClass::Any])
+            .is_empty());
     }
 
     #[test]
-    fn test_exact_match_search() {
-        let dict = vec!["a", "ab", "abc"];
+    fn test_hamming_search() {
+        let dict = vec!["ABCD", "ABCE", "ABCG", "QBCD", "ABC", "ABCDE"];
         let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
         let mut cedar = Cedar::new();
         cedar.build(&key_values);
 
-        let result = cedar.exact_match_search("abc").map(|x| x.0);
-        assert_eq!(Some(2), result);
+        // 'E' is 'D' with one bit flipped, and 'G' is 'D' with two.
+        assert_eq!(cedar.hamming_search(b"ABCE", 0), vec![(b"ABCE".to_vec(), 1)]);
+        assert_eq!(
+            cedar.hamming_search(b"ABCD", 1),
+            vec![(b"ABCD".to_vec(), 0), (b"ABCE".to_vec(), 1), (b"QBCD".to_vec(), 3)]
+        );
+        assert_eq!(
+            cedar.hamming_search(b"ABCD", 2),
+            vec![
+                (b"ABCD".to_vec(), 0),
+                (b"ABCE".to_vec(), 1),
+                (b"ABCG".to_vec(), 2),
+                (b"QBCD".to_vec(), 3)
+            ]
+        );
+        assert!(cedar.hamming_search(b"XYZW", 1).is_empty());
     }
 
     #[test]
-    fn test_unicode_han_sip() {
-        let dict = vec!["讥䶯䶰", "讥䶯䶰䶱䶲", "讥䶯䶰䶱䶲䶳䶴䶵𦡦"];
-
+    fn test_keys_of_len() {
+        let dict = vec!["a", "ab", "abc", "abd", "b", "bcd", "中", "中华"];
         let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
         let mut cedar = Cedar::new();
         cedar.build(&key_values);
 
-        let result: Vec<i32> = cedar.common_prefix_iter("讥䶯䶰䶱䶲䶳䶴䶵𦡦").map(|x| x.0).collect();
-        assert_eq!(vec![0, 1, 2], result);
+        assert_eq!(cedar.keys_of_len(1), vec![(b"a".to_vec(), 0), (b"b".to_vec(), 4)]);
+        assert_eq!(
+            cedar.keys_of_len(3),
+            vec![
+                (b"abc".to_vec(), 2),
+                (b"abd".to_vec(), 3),
+                (b"bcd".to_vec(), 5),
+                ("中".as_bytes().to_vec(), 6)
+            ]
+        );
+        assert_eq!(cedar.keys_of_len(6), vec![("中华".as_bytes().to_vec(), 7)]);
+        assert!(cedar.keys_of_len(4).is_empty());
+        assert!(cedar.keys_of_len(0).is_empty());
     }
 
     #[test]
-    fn test_unicode_grapheme_cluster() {
-        let dict = vec!["a", "abc", "abcde\u{0301}"];
-
+    fn test_transposition_search() {
+        let dict = vec!["acb", "bac", "abc", "the", "aab"];
         let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
         let mut cedar = Cedar::new();
         cedar.build(&key_values);
 
-        let result: Vec<i32> = cedar
-            .common_prefix_iter("abcde\u{0301}\u{1100}\u{1161}\u{AC00}")
-            .map(|x| x.0)
-            .collect();
-        assert_eq!(vec![0, 1, 2], result);
+        assert_eq!(
+            cedar.transposition_search("abc"),
+            vec![(b"bac".to_vec(), 1), (b"acb".to_vec(), 0)]
+        );
+        assert_eq!(cedar.transposition_search("teh"), vec![(b"the".to_vec(), 3)]);
+        assert_eq!(cedar.transposition_search("aba"), vec![(b"aab".to_vec(), 4)]);
+        assert!(cedar.transposition_search("aa").is_empty());
+        assert!(cedar.transposition_search("a").is_empty());
+        assert!(cedar.transposition_search("").is_empty());
     }
 
     #[test]
-    fn test_erase() {
-        let dict = vec!["a", "ab", "abc"];
-        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+    fn test_prefix_values() {
+        let key_values = vec![
+            ("中", 1),
+            ("中华", 2),
+            ("中华人民", 1),
+            ("中华人民共和国", 3),
+            ("中国", 2),
+            ("网", 4),
+            ("网球", 5),
+            ("a", 6),
+        ];
         let mut cedar = Cedar::new();
         cedar.build(&key_values);
 
-        cedar.erase("abc");
-        assert!(cedar.exact_match_search("abc").is_none());
-        assert!(cedar.exact_match_search("ab").is_some());
-        assert!(cedar.exact_match_search("a").is_some());
+        let expected: HashSet<i32> = key_values
+            .iter()
+            .filter(|(key, _)| key.starts_with("中"))
+            .map(|&(_, value)| value)
+            .collect();
+        assert_eq!(cedar.prefix_values("中"), expected);
+        assert_eq!(cedar.prefix_values("中华人"), [1, 3].iter().cloned().collect());
+        assert_eq!(cedar.prefix_values("").len(), 6);
+        assert!(cedar.prefix_values("球").is_empty());
+    }
 
-        cedar.erase("ab");
-        assert!(cedar.exact_match_search("ab").is_none());
-        assert!(cedar.exact_match_search("a").is_some());
+    #[test]
+    fn test_suggest_next_byte() {
+        let key_values = vec![("a", 0), ("ab", 1), ("abc", 2), ("ax", 3), ("b", 4), ("c", 5)];
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
 
-        cedar.erase("a");
-        assert!(cedar.exact_match_search("a").is_none());
+        assert_eq!(cedar.suggest_next_byte("a"), Some(b'b'));
+        assert_eq!(cedar.suggest_next_byte("ab"), Some(b'c'));
+        // the three bytes from the root lead to 4, 1 and 1 keys.
+        assert_eq!(cedar.suggest_next_byte(""), Some(b'a'));
+        assert_eq!(cedar.suggest_next_byte("abc"), None);
+        assert_eq!(cedar.suggest_next_byte("z"), None);
+
+        cedar.update("ay", 6);
+        cedar.update("ayz", 7);
+        // 'b' and 'y' lead to two keys each, and the smaller byte wins the tie.
+        assert_eq!(cedar.suggest_next_byte("a"), Some(b'b'));
+        cedar.update("axe", 8);
+        cedar.update("axes", 9);
+        assert_eq!(cedar.suggest_next_byte("a"), Some(b'x'));
     }
 
     #[test]
-    fn test_erase_on_internal_key() {
+    fn test_exact_match_search_meta() {
+        let key_values = vec![("中", 0), ("中华", 1), ("abc", 2)];
         let mut cedar = Cedar::new();
+        cedar.build(&key_values);
 
-        cedar.update("aa", 0);
-        assert!(cedar.exact_match_search("aa").is_some());
-        cedar.update("ab", 1);
-        assert!(cedar.exact_match_search("ab").is_some());
+        let m = cedar.exact_match_search_meta("中华").unwrap();
+        assert_eq!((m.value, m.byte_len, m.char_len), (1, 6, 2));
+        assert_eq!(cedar.exact_match_search("中华"), Some((m.value, m.byte_len, m.node_id)));
 
-        cedar.erase("a");
-        assert!(cedar.exact_match_search("a").is_none());
-        cedar.erase("aa");
-        assert!(cedar.exact_match_search("aa").is_none());
-        cedar.erase("ab");
-        assert!(cedar.exact_match_search("ab").is_none());
+        let m = cedar.exact_match_search_meta("abc").unwrap();
+        assert_eq!((m.value, m.byte_len, m.char_len), (2, 3, 3));
+        assert_eq!(cedar.exact_match_search_meta("中华人"), None);
     }
 
     #[test]
-    fn test_update() {
-        let dict = vec!["a", "ab", "abc"];
+    fn test_label_histogram() {
+        let dict = vec!["a", "ab", "abc", "b", "bc", "cab"];
         let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
         let mut cedar = Cedar::new();
         cedar.build(&key_values);
 
-        cedar.update("abcd", 3);
+        // the transitions are a, ab, abc, b, bc, c, ca and cab.
+        let histogram = cedar.label_histogram();
+        assert_eq!(
+            (
+                histogram[b'a' as usize],
+                histogram[b'b' as usize],
+                histogram[b'c' as usize]
+            ),
+            (2, 3, 3)
+        );
+        assert_eq!(histogram.iter().sum::<usize>(), 8);
+
+        cedar.erase("cab");
+        let histogram = cedar.label_histogram();
+        assert_eq!(
+            (
+                histogram[b'a' as usize],
+                histogram[b'b' as usize],
+                histogram[b'c' as usize]
+            ),
+            (1, 2, 2)
+        );
+        assert_eq!(histogram.iter().sum::<usize>(), 5);
+        assert_eq!(Cedar::new().label_histogram().iter().sum::<usize>(), 0);
+    }
 
-        assert!(cedar.exact_match_search("a").is_some());
-        assert!(cedar.exact_match_search("ab").is_some());
-        assert!(cedar.exact_match_search("abc").is_some());
-        assert!(cedar.exact_match_search("abcd").is_some());
-        assert!(cedar.exact_match_search("abcde").is_none());
+    #[test]
+    fn test_bfs() {
+        let dict = ["a", "ab", "abc", "b", "bcd", "中华"];
+        let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (*s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        let nodes: Vec<(usize, Vec<u8>)> = cedar.bfs().collect();
+        assert_eq!(nodes[0], (0, vec![]));
+        assert!(nodes.windows(2).all(|w| w[0].1.len() <= w[1].1.len()));
+
+        // every prefix of every key is visited exactly once, at the node the key leads to.
+        let mut paths: Vec<Vec<u8>> = nodes.iter().map(|(_, path)| path.clone()).collect();
+        paths.sort();
+        paths.dedup();
+        assert_eq!(paths.len(), nodes.len());
+        for s in dict.iter() {
+            let (_, _, id) = cedar.exact_match_search(s).unwrap();
+            assert!(nodes.iter().any(|(from, path)| path == s.as_bytes() && *from == id));
+        }
+        assert_eq!(nodes.len(), 1 + 3 + 3 + "中华".len());
+    }
+
+    #[test]
+    fn test_is_prefix_closed() {
+        let mut cedar = Cedar::new();
+        cedar.build(&[("a", 0), ("ab", 1), ("abc", 2)]);
+        assert!(cedar.is_prefix_closed());
+
+        cedar.erase("ab");
+        assert!(!cedar.is_prefix_closed());
 
-        let dict = vec!["a", "ab", "abc"];
-        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.update("abc", 0);
+        assert!(!cedar.is_prefix_closed());
+
+        assert!(Cedar::new().is_prefix_closed());
+    }
+
+    #[test]
+    fn test_sample_keys() {
+        let dict: Vec<String> = (0..500).map(|i| format!("key{}", i)).collect();
+        let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (s.as_ref(), k as i32)).collect();
         let mut cedar = Cedar::new();
         cedar.build(&key_values);
-        cedar.update("bachelor", 1);
-        cedar.update("jar", 2);
-        cedar.update("badge", 3);
-        cedar.update("baby", 4);
 
-        assert!(cedar.exact_match_search("bachelor").is_some());
-        assert!(cedar.exact_match_search("jar").is_some());
-        assert!(cedar.exact_match_search("badge").is_some());
-        assert!(cedar.exact_match_search("baby").is_some());
-        assert!(cedar.exact_match_search("abcde").is_none());
+        let sample = cedar.sample_keys(20, 42);
+        assert_eq!(sample.len(), 20);
+        assert_eq!(sample, cedar.sample_keys(20, 42));
+        assert_ne!(sample, cedar.sample_keys(20, 43));
+        assert!(sample.iter().all(|k| cedar.exact_match_search_bytes(k).is_some()));
 
-        let dict = vec!["a", "ab", "abc"];
+        let all: Vec<Vec<u8>> = cedar.keys().map(|(k, _)| k).collect();
+        assert_eq!(cedar.sample_keys(1000, 42), all);
+        assert!(cedar.sample_keys(0, 42).is_empty());
+    }
+
+    #[test]
+    fn test_depth_histogram() {
+        let dict = vec![
+            "a",
+            "ab",
+            "abc",
+            "アルゴリズム",
+            "データ",
+            "構造",
+            "网",
+            "网球",
+            "网球拍",
+            "中",
+            "中华",
+            "中华人民",
+            "中华人民共和国",
+        ];
         let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
         let mut cedar = Cedar::new();
         cedar.build(&key_values);
-        cedar.update("中", 1);
-        cedar.update("中华", 2);
-        cedar.update("中华人民", 3);
-        cedar.update("中华人民共和国", 4);
 
-        assert!(cedar.exact_match_search("中").is_some());
-        assert!(cedar.exact_match_search("中华").is_some());
-        assert!(cedar.exact_match_search("中华人民").is_some());
-        assert!(cedar.exact_match_search("中华人民共和国").is_some());
+        let histogram = cedar.depth_histogram();
+        assert_eq!(histogram.iter().sum::<usize>(), cedar.keys().count());
+        assert_eq!(histogram.len(), 22);
+        assert_eq!(histogram[0], 0);
+        assert_eq!(histogram[1], 1);
+        assert_eq!(histogram[3], 3);
+        assert_eq!(histogram[21], 1);
+
+        assert!(Cedar::new().depth_histogram().is_empty());
     }
 
     #[test]
-    fn test_quickcheck_like() {
-        let mut rng = thread_rng();
-        let mut dict: Vec<String> = Vec::with_capacity(1000);
-        for _ in 0..1000 {
-            let chars: Vec<u8> = iter::repeat(()).map(|()| rng.sample(Alphanumeric)).take(30).collect();
-            let s = String::from_utf8(chars).unwrap();
-            dict.push(s);
-        }
-
+    fn test_defragment_values() {
+        let dict: Vec<String> = (0..5000).map(|i| format!("{:x}", i * 7919)).collect();
         let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (s.as_ref(), k as i32)).collect();
         let mut cedar = Cedar::new();
         cedar.build(&key_values);
 
-        for (k, s) in dict.iter().enumerate() {
-            assert_eq!(cedar.exact_match_search(s).map(|x| x.0), Some(k as i32));
+        for s in dict.iter().skip(100) {
+            cedar.erase(s);
         }
+        let last_id = |cedar: &Cedar| cedar.entries_with_ids().iter().map(|x| x.2).max().unwrap();
+        let before = last_id(&cedar);
+
+        cedar.defragment_values();
+        assert!(last_id(&cedar) < before);
+
+        let result: Vec<(Vec<u8>, i32)> = cedar.keys().collect();
+        let mut expected: Vec<(Vec<u8>, i32)> = key_values[..100]
+            .iter()
+            .map(|(s, v)| (s.as_bytes().to_vec(), *v))
+            .collect();
+        expected.sort();
+        assert_eq!(expected, result);
+
+        cedar.update("new", 1);
+        assert_eq!(cedar.exact_match_search("new").map(|x| x.0), Some(1));
     }
 
     #[test]
-    fn test_quickcheck_like_with_deep_trie() {
-        let mut rng = thread_rng();
-        let mut dict: Vec<String> = Vec::with_capacity(1000);
-        let mut s = String::new();
-        for _ in 0..1000 {
-            let c: char = rng.sample(Alphanumeric) as char;
-            s.push(c);
-            dict.push(s.clone());
+    fn test_would_resize() {
+        // the fresh trie only has the block 0 of the root, so a new chain needs a new block.
+        let mut cedar = Cedar::new();
+        assert!(cedar.would_resize("abc"));
+        cedar.update("abc", 0);
+        assert!(cedar.size > 256);
+        assert!(!cedar.would_resize("abc"));
+
+        // after erasing most of the keys there is plenty of room.
+        let dict: Vec<String> = (0..2000).map(|i| format!("key{}", i)).collect();
+        for (k, s) in dict.iter().enumerate() {
+            cedar.update(s, k as i32);
+        }
+        for s in dict.iter().skip(10) {
+            cedar.erase(s);
         }
+        let size = cedar.size;
+        assert!(!cedar.would_resize("fresh"));
+        cedar.update("fresh", 1);
+        assert_eq!(cedar.size, size);
+    }
 
-        let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (s.as_ref(), k as i32)).collect();
+    #[test]
+    fn test_prefix_node() {
+        let dict = ["a", "ab", "中", "中华", "中华人民", "中华民族", "中国"];
+        let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (*s, k as i32)).collect();
         let mut cedar = Cedar::new();
         cedar.build(&key_values);
 
-        for (k, s) in dict.iter().enumerate() {
-            assert_eq!(cedar.exact_match_search(s).map(|x| x.0), Some(k as i32));
+        // to collect the suffixes and values of the keys below `from` with the public node interface only.
+        fn collect(cedar: &Cedar, from: usize, suffix: &mut Vec<u8>, result: &mut Vec<(Vec<u8>, i32)>) {
+            if let Some(value) = cedar.value_at(from) {
+                result.push((suffix.clone(), value));
+            }
+            for label in 1..=255u8 {
+                if let Some(to) = cedar.find_from(from, &[label]) {
+                    suffix.push(label);
+                    collect(cedar, to, suffix, result);
+                    suffix.pop();
+                }
+            }
         }
+
+        let node = cedar.prefix_node("中华").unwrap();
+        let mut result = Vec::new();
+        collect(&cedar, node, &mut Vec::new(), &mut result);
+        let keys: Vec<(String, i32)> = result
+            .into_iter()
+            .map(|(suffix, value)| (format!("中华{}", String::from_utf8(suffix).unwrap()), value))
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                ("中华".to_string(), 3),
+                ("中华人民".to_string(), 4),
+                ("中华民族".to_string(), 5)
+            ]
+        );
+
+        assert_eq!(cedar.prefix_node(""), Some(0));
+        assert_eq!(cedar.prefix_node("中国人"), None);
     }
 
     #[test]
-    fn test_mass_erase() {
-        let mut rng = thread_rng();
-        let mut dict: Vec<String> = Vec::with_capacity(1000);
-        for _ in 0..1000 {
-            let chars: Vec<u8> = iter::repeat(()).map(|()| rng.sample(Alphanumeric)).take(30).collect();
-            let s = String::from_utf8(chars).unwrap();
+    fn test_max_fanout_node() {
+        let mut cedar = Cedar::new();
+        assert_eq!(cedar.max_fanout_node(), (0, 0));
 
-            dict.push(s);
+        // "x" branches into 10 labels, more than the 3 of the root.
+        let mut dict: Vec<String> = (0..10).map(|i| format!("x{}", i)).collect();
+        dict.push("x".to_string());
+        dict.push("ab".to_string());
+        dict.push("b".to_string());
+        for (k, s) in dict.iter().enumerate() {
+            cedar.update(s, k as i32);
         }
 
-        let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (s.as_ref(), k as i32)).collect();
+        let (_, _, x) = cedar.exact_match_search("x").unwrap();
+        assert_eq!(cedar.max_fanout_node(), (x, 10));
+    }
+
+    #[test]
+    fn test_is_reduced() {
+        let cedar = Cedar::new();
+        assert_eq!(cedar.is_reduced(), cfg!(feature = "reduced-trie"));
+        assert_eq!(REDUCED_TRIE, cfg!(feature = "reduced-trie"));
+
+        let mut buf = Vec::new();
+        cedar.write_to(&mut buf).unwrap();
+        let flags = u32::from_le_bytes(buf[12..16].try_into().unwrap());
+        assert_eq!(flags & CEDAR_FLAG_REDUCED != 0, cedar.is_reduced());
+    }
+
+    #[test]
+    fn test_fuzzy_predict() {
+        let dict = ["nest", "netball", "netballer", "netbook", "network"];
+        let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (*s, k as i32)).collect();
         let mut cedar = Cedar::new();
         cedar.build(&key_values);
 
-        for s in dict.iter() {
-            cedar.erase(s);
-            assert!(cedar.exact_match_search(s).is_none());
+        let expected = vec![(b"netball".to_vec(), 1), (b"netballer".to_vec(), 2)];
+        assert_eq!(cedar.fuzzy_predict("netbal", 0, 10), expected);
+        for typo in ["netbsl", "netbll", "nettbal", "metbal"].iter() {
+            assert_eq!(cedar.fuzzy_predict(typo, 1, 10), expected, "{}", typo);
         }
+        assert!(cedar.fuzzy_predict("netbsl", 0, 10).is_empty());
+
+        assert_eq!(cedar.fuzzy_predict("netbook", 1, 10), vec![(b"netbook".to_vec(), 3)]);
+        assert_eq!(cedar.fuzzy_predict("netb", 1, 10).len(), 4);
+        assert_eq!(cedar.fuzzy_predict("netb", 1, 2), expected);
     }
 
     #[test]
-    fn test_duplication() {
-        let dict = vec!["些许端", "些須", "些须", "亜", "亝", "亞", "亞", "亞丁", "亞丁港"];
-        let key_values: Vec<(&str, i32)> = dict.into_iter().enumerate().map(|(k, s)| (s, k as i32)).collect();
+    fn test_fuzzy_search() {
+        let dict = ["cat", "cart", "cast", "at", "dog", "doge", "catalog"];
+        let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (*s, k as i32)).collect();
         let mut cedar = Cedar::new();
         cedar.build(&key_values);
 
-        assert_eq!(cedar.exact_match_search("亞").map(|t| t.0), Some(6));
-        assert_eq!(cedar.exact_match_search("亞丁港").map(|t| t.0), Some(8));
-        assert_eq!(cedar.exact_match_search("亝").map(|t| t.0), Some(4));
-        assert_eq!(cedar.exact_match_search("些須").map(|t| t.0), Some(1));
+        assert_eq!(cedar.fuzzy_search("cat", 0), vec![(0, b"cat".to_vec(), 0)]);
+        assert!(cedar.fuzzy_search("cst", 0).is_empty());
+
+        let mut values: Vec<i32> = cedar.fuzzy_search("cat", 1).iter().map(|x| x.0).collect();
+        values.sort_unstable();
+        assert_eq!(values, vec![0, 1, 2, 3]);
+        for typo in ["cst", "cats", "ct"].iter() {
+            let result = cedar.fuzzy_search(typo, 1);
+            assert!(result.contains(&(0, b"cat".to_vec(), 1)), "{}", typo);
+            assert!(result.iter().all(|x| x.2 == 1 && !x.1.starts_with(b"dog")), "{}", typo);
+        }
+
+        // the branch of "dog" is cut right below the root.
+        let row: Vec<usize> = (0..=3).collect();
+        let visited = cedar.fuzzy_search_(0, b"cat", &row, 0, &mut Vec::new(), &mut Vec::new());
+        assert!(visited < cedar.num_nodes() / 2);
+    }
+
+    #[test]
+    fn test_fill_ratio() {
+        let mut rng = thread_rng();
+        let mut cedar = Cedar::new();
+        for k in 0..5000 {
+            let len = rng.gen_range(1..10);
+            let chars: Vec<u8> = iter::repeat(()).map(|()| rng.sample(Alphanumeric)).take(len).collect();
+            cedar.update(&String::from_utf8(chars).unwrap(), k);
+        }
+
+        let ratio = cedar.fill_ratio();
+        assert!(ratio > 0.3 && ratio <= 1.0, "{}", ratio);
+        assert_eq!(Cedar::new().fill_ratio(), 0.0);
+    }
+
+    #[test]
+    fn test_erase_and_compact() {
+        let mut rng = thread_rng();
+        let mut cedar = Cedar::new();
+        let mut kept: Vec<(String, i32)> = Vec::new();
+        let mut peak = 0;
+
+        for round in 0..10 {
+            let mut dict: Vec<String> = Vec::with_capacity(300);
+            for _ in 0..300 {
+                let len = rng.gen_range(1..8);
+                let chars: Vec<u8> = iter::repeat(()).map(|()| rng.sample(Alphanumeric)).take(len).collect();
+                dict.push(String::from_utf8(chars).unwrap());
+            }
+
+            for (k, s) in dict.iter().enumerate() {
+                cedar.update(s, k as i32);
+            }
+            for s in dict.iter() {
+                cedar.erase_and_compact(s);
+            }
+            for (s, v) in kept.iter() {
+                cedar.update(s, *v);
+            }
+            kept.push((format!("kept{}", round), round));
+            cedar.update(&kept[kept.len() - 1].0, round);
+
+            if round == 0 {
+                peak = cedar.num_nodes();
+            }
+            assert!(cedar.num_nodes() <= peak + 10 * kept.len());
+
+            let result: Vec<(Vec<u8>, i32)> = cedar.keys().collect();
+            let mut expected: Vec<(Vec<u8>, i32)> = kept.iter().map(|(s, v)| (s.as_bytes().to_vec(), *v)).collect();
+            expected.sort();
+            assert_eq!(expected, result);
+        }
     }
 }
@@ -0,0 +1,212 @@
+//! The map interface over the trie, in the manner of `BTreeMap<String, i32>`.
+
+use crate::Cedar;
+
+/// `CedarMap` wraps `Cedar` with the familiar interface of the maps in `std`, and it keeps track of the number of
+/// keys. The prefix queries of the trie are kept alongside.
+#[derive(Debug, Clone)]
+pub struct CedarMap {
+    trie: Cedar,
+    len: usize,
+}
+
+/// A view into a single key of the `CedarMap`, which is either occupied or vacant.
+pub enum Entry<'a> {
+    Occupied(OccupiedEntry<'a>),
+    Vacant(VacantEntry<'a>),
+}
+
+/// A view into a key that is in the `CedarMap`.
+pub struct OccupiedEntry<'a> {
+    map: &'a mut CedarMap,
+    key: &'a str,
+    value: i32,
+}
+
+/// A view into a key that is not in the `CedarMap`.
+pub struct VacantEntry<'a> {
+    map: &'a mut CedarMap,
+    key: &'a str,
+}
+
+impl CedarMap {
+    /// Initialize the CedarMap for further use.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        CedarMap {
+            trie: Cedar::new(),
+            len: 0,
+        }
+    }
+
+    /// To return the value of `key` if it is in the map.
+    pub fn get(&self, key: &str) -> Option<i32> {
+        self.trie.exact_match_search(key).map(|(value, _, _)| value)
+    }
+
+    /// To check if `key` is in the map.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// To insert the value for `key`, returning the previous value if there was one.
+    pub fn insert(&mut self, key: &str, value: i32) -> Option<i32> {
+        let old = self.get(key);
+        self.trie.update(key, value);
+        if old.is_none() {
+            self.len += 1;
+        }
+
+        old
+    }
+
+    /// To remove `key` from the map, returning its value if it was there.
+    pub fn remove(&mut self, key: &str) -> Option<i32> {
+        let old = self.get(key);
+        if old.is_some() {
+            self.trie.erase(key);
+            self.len -= 1;
+        }
+
+        old
+    }
+
+    /// To return the number of keys in the map.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// To check if there is no key in the map.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// To get the entry of `key` for the in-place manipulation.
+    pub fn entry<'a>(&'a mut self, key: &'a str) -> Entry<'a> {
+        match self.get(key) {
+            Some(value) => Entry::Occupied(OccupiedEntry { map: self, key, value }),
+            None => Entry::Vacant(VacantEntry { map: self, key }),
+        }
+    }
+
+    /// To return the keys in the map that are prefixes of `key`, see `Cedar::common_prefix_search`.
+    pub fn common_prefix_search(&self, key: &str) -> Option<Vec<(i32, usize)>> {
+        self.trie.common_prefix_search(key)
+    }
+
+    /// To return the keys in the map that has `key` as their prefix, see `Cedar::common_prefix_predict`.
+    pub fn common_prefix_predict(&self, key: &str) -> Option<Vec<(i32, usize)>> {
+        self.trie.common_prefix_predict(key)
+    }
+}
+
+impl<'a> Entry<'a> {
+    /// To return the key of the entry.
+    pub fn key(&self) -> &str {
+        match self {
+            Entry::Occupied(entry) => entry.key,
+            Entry::Vacant(entry) => entry.key,
+        }
+    }
+
+    /// To insert `default` if the entry is vacant, returning the value of the entry.
+    pub fn or_insert(self, default: i32) -> i32 {
+        self.or_insert_with(|| default)
+    }
+
+    /// To insert the value computed by `f` if the entry is vacant, returning the value of the entry.
+    pub fn or_insert_with<F: FnOnce() -> i32>(self, f: F) -> i32 {
+        match self {
+            Entry::Occupied(entry) => entry.value,
+            Entry::Vacant(entry) => entry.insert(f()),
+        }
+    }
+
+    /// To modify the value with `f` if the entry is occupied.
+    pub fn and_modify<F: FnOnce(&mut i32)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(mut entry) => {
+                let mut value = entry.value;
+                f(&mut value);
+                entry.insert(value);
+                Entry::Occupied(entry)
+            }
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+}
+
+impl<'a> OccupiedEntry<'a> {
+    /// To return the value of the entry.
+    pub fn get(&self) -> i32 {
+        self.value
+    }
+
+    /// To set the value of the entry, returning the previous one.
+    pub fn insert(&mut self, value: i32) -> i32 {
+        self.map.trie.update(self.key, value);
+        std::mem::replace(&mut self.value, value)
+    }
+
+    /// To remove the entry from the map, returning its value.
+    pub fn remove(self) -> i32 {
+        self.map.trie.erase(self.key);
+        self.map.len -= 1;
+        self.value
+    }
+}
+
+impl<'a> VacantEntry<'a> {
+    /// To insert the value for the key of the entry, returning it.
+    pub fn insert(self, value: i32) -> i32 {
+        self.map.trie.update(self.key, value);
+        self.map.len += 1;
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_same_usage_as_btree_map() {
+        let mut map = CedarMap::new();
+        let mut expected: BTreeMap<String, i32> = BTreeMap::new();
+        assert!(map.is_empty());
+
+        for (k, s) in ["apple", "app", "banana", "apple", "cherry"].iter().enumerate() {
+            assert_eq!(map.insert(s, k as i32), expected.insert(s.to_string(), k as i32));
+        }
+        assert_eq!(map.len(), expected.len());
+        assert_eq!(map.get("apple"), expected.get("apple").cloned());
+        assert!(map.contains_key("app"));
+        assert!(!map.contains_key("ap"));
+
+        assert_eq!(map.remove("banana"), expected.remove("banana"));
+        assert_eq!(map.remove("banana"), expected.remove("banana"));
+        assert_eq!(map.len(), expected.len());
+
+        for s in ["apple", "date", "date"].iter() {
+            let value = map.entry(s).and_modify(|v| *v += 10).or_insert(1);
+            let expected_value = *expected.entry(s.to_string()).and_modify(|v| *v += 10).or_insert(1);
+            assert_eq!(value, expected_value);
+        }
+        assert_eq!(map.len(), expected.len());
+
+        if let Entry::Occupied(entry) = map.entry("cherry") {
+            assert_eq!(entry.remove(), 4);
+        }
+        expected.remove("cherry");
+        for (key, value) in expected.iter() {
+            assert_eq!(map.get(key), Some(*value));
+        }
+        assert_eq!(map.len(), expected.len());
+
+        // unlike `BTreeMap`, the keys sharing a prefix come from the trie directly.
+        let result: Vec<i32> = map.common_prefix_predict("app").unwrap().iter().map(|x| x.0).collect();
+        assert_eq!(result, vec![1, 13]);
+        assert_eq!(map.common_prefix_search("apples").unwrap(), vec![(1, 2), (13, 4)]);
+    }
+}
@@ -0,0 +1,183 @@
+//! The layout that maps ranges of keys to values, for the dictionaries where runs of sorted keys share a value.
+
+use crate::Cedar;
+
+/// `RangeCedar` is an interval map over strings. Every range `start..end` is stored once by its endpoints, with
+/// the start in the trie pointing to the end and the value in a side vector, and a key falls within a range if it
+/// is at least `start` and less than `end` in lexicographical order of the bytes.
+///
+/// The ranges never overlap, inserting a range replaces the part of the stored ones it covers like `insert` of
+/// the `RangeMap` in the `rangemap` crate.
+#[derive(Debug, Clone)]
+pub struct RangeCedar {
+    trie: Cedar,
+    ranges: Vec<(Vec<u8>, Vec<u8>, i32)>, // the start, the end and the value of every range, indexed by the trie.
+    free: Vec<i32>,                       // the indices released by the removed ranges to be reused.
+}
+
+impl RangeCedar {
+    /// Initialize the RangeCedar for further use.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        RangeCedar {
+            trie: Cedar::new(),
+            ranges: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    /// To map the keys from `start` up to but not including `end` to `value`. The stored ranges overlapping it
+    /// are cut back to the part outside of it, and an empty range does nothing.
+    pub fn insert_range(&mut self, start: &str, end: &str, value: i32) {
+        let (start, end) = (start.as_bytes(), end.as_bytes());
+        if start >= end {
+            return;
+        }
+
+        // the range starting before `start` keeps the part before `start`, and the part after `end` too.
+        if let Some(i) = floor(&self.trie, start, false) {
+            let (_, r_end, r_value) = self.ranges[i as usize].clone();
+            if r_end.as_slice() > start {
+                self.ranges[i as usize].1 = start.to_vec();
+                if r_end.as_slice() > end {
+                    self.add(end, &r_end, r_value);
+                }
+            }
+        }
+
+        // the ranges starting within the new one are removed, keeping the part after `end` of the last one.
+        while let Some(i) = floor(&self.trie, end, false) {
+            let (r_start, r_end, r_value) = self.ranges[i as usize].clone();
+            if r_start.as_slice() < start {
+                break;
+            }
+
+            self.trie.erase_(&r_start);
+            self.free.push(i);
+            if r_end.as_slice() > end {
+                self.add(end, &r_end, r_value);
+            }
+        }
+
+        self.add(start, end, value);
+    }
+
+    /// To return the value of the range that `key` falls within, if there is one.
+    pub fn range_value(&self, key: &str) -> Option<i32> {
+        let key = key.as_bytes();
+        let i = floor(&self.trie, key, true)?;
+        let (_, end, value) = &self.ranges[i as usize];
+        if key < end.as_slice() {
+            Some(*value)
+        } else {
+            None
+        }
+    }
+
+    /// To return the number of ranges stored.
+    pub fn len(&self) -> usize {
+        self.ranges.len() - self.free.len()
+    }
+
+    /// To check if there is no range stored.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // To store the range `start..end`, which must not overlap with the stored ones.
+    fn add(&mut self, start: &[u8], end: &[u8], value: i32) {
+        let range = (start.to_vec(), end.to_vec(), value);
+        let i = match self.free.pop() {
+            Some(i) => {
+                self.ranges[i as usize] = range;
+                i
+            }
+            None => {
+                self.ranges.push(range);
+                (self.ranges.len() - 1) as i32
+            }
+        };
+        self.trie.update_(start, i, 0, 0);
+    }
+}
+
+// To find the greatest key in `trie` that is less than `key`, or equal to it if `inclusive`, returning its value.
+// Along the path of `key`, a key ending at a node is a prefix of `key` and so less than it, and the keys below the
+// smaller siblings of the next label are greater than that prefix, so the deepest candidate is the floor.
+fn floor(trie: &Cedar, key: &[u8], inclusive: bool) -> Option<i32> {
+    let mut best = None;
+    let mut from = 0;
+    for &label in key {
+        if let Some(value) = trie.terminal_value(from) {
+            best = Some(value);
+        }
+
+        let internal = trie.label(label);
+        let smaller = trie
+            .children(from)
+            .filter(|&(c, _)| c != 0 && c < internal)
+            .max_by_key(|&(c, _)| c);
+        if let Some((_, to)) = smaller {
+            best = last_value(trie, to);
+        }
+
+        match trie.step(from, label) {
+            Some(to) => from = to,
+            None => return best,
+        }
+    }
+
+    if inclusive {
+        if let Some(value) = trie.terminal_value(from) {
+            return Some(value);
+        }
+    }
+
+    best
+}
+
+// To return the value of the greatest key below `from`, found by always taking the greatest label.
+fn last_value(trie: &Cedar, mut from: usize) -> Option<i32> {
+    while let Some((_, to)) = trie.children(from).filter(|&(c, _)| c != 0).max_by_key(|&(c, _)| c) {
+        from = to;
+    }
+
+    trie.terminal_value(from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_range_value() {
+        let mut ranges = RangeCedar::new();
+        ranges.insert_range("apple", "apricot", 1);
+        ranges.insert_range("banana", "cherry", 2);
+
+        for key in ["apple", "applesauce", "apply", "appzzz", "apri"].iter() {
+            assert_eq!(ranges.range_value(key), Some(1), "{}", key);
+        }
+        for key in ["appl", "apricot", "apricots", "b", "cherry", "a", ""].iter() {
+            assert_eq!(ranges.range_value(key), None, "{}", key);
+        }
+        assert_eq!(ranges.range_value("blueberry"), Some(2));
+        assert_eq!(ranges.len(), 2);
+
+        // the new range covers the end of the first one and all of the second one, which keeps its tail.
+        ranges.insert_range("applesauce", "bz", 3);
+        assert_eq!(ranges.range_value("apple"), Some(1));
+        assert_eq!(ranges.range_value("apply"), Some(3));
+        assert_eq!(ranges.range_value("blueberry"), Some(3));
+        assert_eq!(ranges.range_value("bzz"), Some(2));
+        assert_eq!(ranges.range_value("cherry"), None);
+        assert_eq!(ranges.len(), 3);
+
+        // and a range inside of another splits it in two.
+        ranges.insert_range("c", "ca", 4);
+        assert_eq!(ranges.range_value("c"), Some(4));
+        assert_eq!(ranges.range_value("bzz"), Some(2));
+        assert_eq!(ranges.range_value("caa"), Some(2));
+        assert_eq!(ranges.len(), 5);
+    }
+}
@@ -0,0 +1,127 @@
+//! The layout that stores an id and a rank for every key, for ranked prefix completion.
+
+use crate::Cedar;
+
+/// `RankedCedar` stores an `(id, rank)` pair for every key in two side vectors indexed by a terminal id, as only
+/// one i32 fits in a terminal node. The completions of a prefix can then be ordered by their rank.
+#[derive(Debug, Clone)]
+pub struct RankedCedar {
+    trie: Cedar,
+    ids: Vec<i32>,
+    ranks: Vec<i32>,
+    free: Vec<i32>, // the terminal ids released by `erase` to be reused.
+}
+
+impl RankedCedar {
+    /// Initialize the RankedCedar for further use.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        RankedCedar {
+            trie: Cedar::new(),
+            ids: Vec::new(),
+            ranks: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    /// Build the trie from the given `(key, id, rank)` triples
+    pub fn build(&mut self, entries: &[(&str, i32, i32)]) {
+        for (key, id, rank) in entries {
+            self.update(key, *id, *rank);
+        }
+    }
+
+    /// Update the key for the id and the rank.
+    pub fn update(&mut self, key: &str, id: i32, rank: i32) {
+        if let Some((t, _, _)) = self.trie.exact_match_search(key) {
+            self.ids[t as usize] = id;
+            self.ranks[t as usize] = rank;
+            return;
+        }
+
+        let t = match self.free.pop() {
+            Some(t) => {
+                self.ids[t as usize] = id;
+                self.ranks[t as usize] = rank;
+                t
+            }
+            None => {
+                self.ids.push(id);
+                self.ranks.push(rank);
+                (self.ids.len() - 1) as i32
+            }
+        };
+        self.trie.update(key, t);
+    }
+
+    /// Delete the key from the trie.
+    pub fn erase(&mut self, key: &str) {
+        if let Some((t, _, _)) = self.trie.exact_match_search(key) {
+            self.trie.erase(key);
+            self.free.push(t);
+        }
+    }
+
+    /// To return the `(id, rank)` of `key` if it is in the dictionary.
+    pub fn exact_match_search(&self, key: &str) -> Option<(i32, i32)> {
+        self.trie
+            .exact_match_search(key)
+            .map(|(t, _, _)| (self.ids[t as usize], self.ranks[t as usize]))
+    }
+
+    /// To return the `limit` completions of `prefix` with the highest rank, as the id, the rank and the length of
+    /// the remaining part of the key like `Cedar::common_prefix_predict`. The completions of equal rank keep the
+    /// lexicographical order.
+    pub fn common_prefix_predict_ranked(&self, prefix: &str, limit: usize) -> Vec<(i32, i32, usize)> {
+        let mut result: Vec<(i32, i32, usize)> = self
+            .trie
+            .common_prefix_predict_iter(prefix)
+            .map(|(t, len)| (self.ids[t as usize], self.ranks[t as usize], len))
+            .collect();
+
+        result.sort_by_key(|x| std::cmp::Reverse(x.1));
+        result.truncate(limit);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_common_prefix_predict_ranked() {
+        let entries = vec![
+            ("apple", 0, 10),
+            ("application", 1, 50),
+            ("apply", 2, 30),
+            ("apt", 3, 50),
+            ("banana", 4, 100),
+        ];
+        let mut cedar = RankedCedar::new();
+        cedar.build(&entries);
+
+        let result = cedar.common_prefix_predict_ranked("ap", 3);
+        assert_eq!(vec![(1, 50, 9), (3, 50, 1), (2, 30, 3)], result);
+
+        let result: Vec<i32> = cedar
+            .common_prefix_predict_ranked("ap", 10)
+            .iter()
+            .map(|x| x.0)
+            .collect();
+        assert_eq!(vec![1, 3, 2, 0], result);
+
+        cedar.update("apple", 0, 60);
+        cedar.erase("apt");
+        let result: Vec<i32> = cedar
+            .common_prefix_predict_ranked("ap", 2)
+            .iter()
+            .map(|x| x.0)
+            .collect();
+        assert_eq!(vec![0, 1], result);
+
+        assert_eq!(cedar.exact_match_search("apple"), Some((0, 60)));
+        assert_eq!(cedar.exact_match_search("apt"), None);
+        assert!(cedar.common_prefix_predict_ranked("x", 10).is_empty());
+    }
+}
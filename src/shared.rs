@@ -0,0 +1,86 @@
+//! The handle that shares a built trie between its clones, for serving many readers of the same dictionary.
+
+use crate::Cedar;
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// `SharedCedar` holds the trie behind an `Arc`, so cloning the handle is a reference count increment and the
+/// node arrays are never copied. The queries of `Cedar` are available through `Deref`, and `make_mut` copies the
+/// trie on write if other handles still share it.
+#[derive(Debug, Clone)]
+pub struct SharedCedar {
+    trie: Arc<Cedar>,
+}
+
+impl SharedCedar {
+    /// To share `trie` without copying it.
+    pub fn new(trie: Cedar) -> Self {
+        SharedCedar { trie: Arc::new(trie) }
+    }
+
+    /// To return the trie for the update, copying it first if it is shared with other handles.
+    pub fn make_mut(&mut self) -> &mut Cedar {
+        Arc::make_mut(&mut self.trie)
+    }
+
+    /// To return the number of handles sharing the trie.
+    pub fn share_count(&self) -> usize {
+        Arc::strong_count(&self.trie)
+    }
+
+    /// To check if the two handles share the same trie.
+    pub fn ptr_eq(&self, other: &SharedCedar) -> bool {
+        Arc::ptr_eq(&self.trie, &other.trie)
+    }
+}
+
+impl Deref for SharedCedar {
+    type Target = Cedar;
+
+    fn deref(&self) -> &Cedar {
+        &self.trie
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_shared_handles() {
+        let dict: Vec<String> = (0..1000).map(|i| format!("{:x}", i * 7919)).collect();
+        let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (s.as_ref(), k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+
+        let shared = cedar.share();
+        let handles: Vec<SharedCedar> = (0..16).map(|_| shared.clone()).collect();
+        assert_eq!(shared.share_count(), 17);
+        assert!(handles.iter().all(|handle| handle.ptr_eq(&shared)));
+
+        let threads: Vec<_> = handles
+            .into_iter()
+            .map(|handle| {
+                let dict = dict.clone();
+                thread::spawn(move || {
+                    for (k, s) in dict.iter().enumerate() {
+                        assert_eq!(handle.exact_match_search(s).map(|x| x.0), Some(k as i32));
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(shared.share_count(), 1);
+
+        // the update copies the trie away from the other handles.
+        let mut updated = shared.clone();
+        updated.make_mut().update("new", 1000);
+        assert!(!updated.ptr_eq(&shared));
+        assert_eq!(updated.exact_match_search("new").map(|x| x.0), Some(1000));
+        assert_eq!(shared.exact_match_search("new"), None);
+        assert_eq!(updated.share_count(), 1);
+    }
+}
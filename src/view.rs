@@ -0,0 +1,285 @@
+//! The view that answers the queries in place from the bytes written by `Cedar::write_to`, owning them.
+
+use crate::{
+    invalid_data, CEDAR_FLAG_REDUCED, CEDAR_FLAG_VALUES_U16, CEDAR_FLAG_VALUES_U8, CEDAR_FORMAT_VERSION, CEDAR_MAGIC,
+    CEDAR_NO_VALUE,
+};
+use std::convert::TryInto;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// the header written by `write_to`, the nodes follow it as the pairs of the little-endian base and check.
+const HEADER_SIZE: usize = 48;
+
+/// `OwnedCedarView` owns the bytes written by `Cedar::write_to` and answers the queries from them in place, without
+/// decoding the arrays into a `Cedar` like `read_from` does. As it holds the buffer rather than borrowing it, the
+/// view can be stored and passed around without a lifetime, and the file it was loaded from can go away.
+///
+/// Only the header and the length are checked when the view is made. Every transition is checked against the
+/// size as the queries go, so corrupted bytes give wrong answers at worst, never a panic. The format of
+/// `write_to_packed` does not keep the nodes in place and is rejected.
+#[derive(Debug, Clone)]
+pub struct OwnedCedarView {
+    bytes: Vec<u8>,
+    size: usize, // the number of nodes after the header.
+    terminal: u8,
+}
+
+impl OwnedCedarView {
+    /// To make the view of `bytes` written by `write_to`, taking the ownership of them.
+    pub fn from_bytes(bytes: Vec<u8>) -> io::Result<Self> {
+        if bytes.len() < HEADER_SIZE || &bytes[0..8] != CEDAR_MAGIC {
+            return Err(invalid_data("not a serialized cedar"));
+        }
+
+        let header = |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        if header(8) != CEDAR_FORMAT_VERSION {
+            return Err(invalid_data("unsupported format version"));
+        }
+
+        let flags = header(12);
+        if (flags & CEDAR_FLAG_REDUCED != 0) != cfg!(feature = "reduced-trie") {
+            return Err(invalid_data("mismatched reduced-trie layout"));
+        }
+        if flags & (CEDAR_FLAG_VALUES_U8 | CEDAR_FLAG_VALUES_U16) != 0 {
+            return Err(invalid_data("the packed values cannot be viewed in place"));
+        }
+
+        let size: usize = u64::from_le_bytes(bytes[16..24].try_into().unwrap())
+            .try_into()
+            .map_err(|_| invalid_data("invalid size"))?;
+        if size == 0 || size & 0xff != 0 {
+            return Err(invalid_data("invalid size"));
+        }
+        match size.checked_mul(8).and_then(|n| n.checked_add(HEADER_SIZE)) {
+            Some(len) if len <= bytes.len() => {}
+            _ => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the input is truncated")),
+        }
+
+        let terminal: u8 = header(44)
+            .try_into()
+            .map_err(|_| invalid_data("invalid terminal label"))?;
+
+        Ok(OwnedCedarView { bytes, size, terminal })
+    }
+
+    /// To load the view from the file at `path` written by `write_to`. The file is read into the view, which keeps
+    /// no handle to it.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        OwnedCedarView::from_bytes(fs::read(path)?)
+    }
+
+    /// To give back the bytes of the view.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// To check if `key` is in the dictionary, see `Cedar::exact_match_search`.
+    pub fn exact_match_search(&self, key: &str) -> Option<(i32, usize, usize)> {
+        self.exact_match_search_bytes(key.as_bytes())
+    }
+
+    /// To check if the raw bytes `key` is in the dictionary, see `Cedar::exact_match_search_bytes`.
+    pub fn exact_match_search_bytes(&self, key: &[u8]) -> Option<(i32, usize, usize)> {
+        let mut from = 0;
+        for &c in key {
+            from = self.step(from, c)?;
+        }
+
+        self.value(from).map(|value| (value, key.len(), from))
+    }
+
+    /// To check if `key` is in the dictionary, for the set membership where the value is not needed.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.exact_match_search(key).is_some()
+    }
+
+    /// To return the collection of the common prefix in the dictionary with the `key` passed in, see
+    /// `Cedar::common_prefix_search`.
+    pub fn common_prefix_search(&self, key: &str) -> Option<Vec<(i32, usize)>> {
+        let mut result = Vec::new();
+        let mut from = 0;
+        for (i, &c) in key.as_bytes().iter().enumerate() {
+            match self.step(from, c) {
+                Some(to) => from = to,
+                None => break,
+            }
+
+            if let Some(value) = self.value(from) {
+                result.push((value, i));
+            }
+        }
+
+        Some(result)
+    }
+
+    // To read the base and the check of the node `i`, which is below `size`.
+    #[inline]
+    fn node(&self, i: usize) -> (i32, i32) {
+        let n = &self.bytes[HEADER_SIZE + i * 8..HEADER_SIZE + i * 8 + 8];
+        (
+            i32::from_le_bytes(n[0..4].try_into().unwrap()),
+            i32::from_le_bytes(n[4..8].try_into().unwrap()),
+        )
+    }
+
+    // To follow the byte `c` of a key from the node `from`, with 0 and the terminal swapped like `Cedar::label`.
+    fn step(&self, from: usize, c: u8) -> Option<usize> {
+        let label = if c == 0 {
+            self.terminal
+        } else if c == self.terminal {
+            0
+        } else {
+            c
+        };
+
+        // the terminal label never occurs in the keys.
+        if label == 0 {
+            return None;
+        }
+
+        self.child(from, label)
+    }
+
+    // To return the child of `from` by `label`, or `None` if there is no such child or it is out of the array.
+    fn child(&self, from: usize, label: u8) -> Option<usize> {
+        let base_ = self.node(from).0;
+        #[cfg(feature = "reduced-trie")]
+        let base = {
+            // the leaf of the reduced trie holds its value instead of a base.
+            if base_ >= 0 {
+                return None;
+            }
+            -(base_ + 1)
+        };
+        #[cfg(not(feature = "reduced-trie"))]
+        let base = base_;
+
+        let to = base ^ (label as i32);
+        if to < 0 || to as usize >= self.size || self.node(to as usize).1 != from as i32 {
+            return None;
+        }
+
+        Some(to as usize)
+    }
+
+    // To return the value of the key ending at the node `from`, if any.
+    fn value(&self, from: usize) -> Option<i32> {
+        #[cfg(feature = "reduced-trie")]
+        {
+            let base_ = self.node(from).0;
+            if from != 0 && base_ >= 0 {
+                return Some(base_);
+            }
+        }
+
+        let value = self.node(self.child(from, 0)?).0;
+        if value == CEDAR_NO_VALUE {
+            None
+        } else {
+            Some(value)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cedar;
+    use rand::distributions::Alphanumeric;
+    use rand::{thread_rng, Rng};
+    use std::{env, iter, process, thread};
+
+    #[test]
+    fn test_same_results_as_cedar() {
+        let mut rng = thread_rng();
+        let mut dict: Vec<String> = Vec::with_capacity(1000);
+        for _ in 0..1000 {
+            let len = rng.gen_range(1..10);
+            let chars: Vec<u8> = iter::repeat(()).map(|()| rng.sample(Alphanumeric)).take(len).collect();
+            dict.push(String::from_utf8(chars).unwrap());
+        }
+        dict.push(String::from("中华人民共和国"));
+
+        let key_values: Vec<(&str, i32)> = dict.iter().enumerate().map(|(k, s)| (s.as_ref(), k as i32)).collect();
+        let mut cedar = Cedar::new();
+        cedar.build(&key_values);
+        for s in dict.iter().step_by(5) {
+            cedar.erase(s);
+        }
+
+        let mut bytes = Vec::new();
+        cedar.write_to(&mut bytes).unwrap();
+        let view = OwnedCedarView::from_bytes(bytes).unwrap();
+        for s in dict.iter().chain(iter::once(&String::from("中华"))) {
+            assert_eq!(view.exact_match_search(s), cedar.exact_match_search(s));
+            assert_eq!(view.common_prefix_search(s), cedar.common_prefix_search(s));
+            assert_eq!(view.contains_key(s), cedar.contains_key(s));
+        }
+
+        // the key with 0 of a custom terminal.
+        let mut cedar = Cedar::with_terminal(0xff);
+        cedar.build(&[("a", 0), ("a\0", 1), ("a\0b", 2)]);
+        let mut bytes = Vec::new();
+        cedar.write_to(&mut bytes).unwrap();
+        let view = OwnedCedarView::from_bytes(bytes).unwrap();
+        assert_eq!(view.exact_match_search("a\0").map(|x| x.0), Some(1));
+        assert_eq!(
+            view.common_prefix_search("a\0bc").unwrap(),
+            vec![(0, 0), (1, 1), (2, 2)]
+        );
+    }
+
+    #[test]
+    fn test_owned_after_the_buffer_is_gone() {
+        let path = env::temp_dir().join(format!("cedarwood-view-{}.bin", process::id()));
+        let view = {
+            let mut cedar = Cedar::new();
+            cedar.build(&[("a", 0), ("ab", 1), ("中华", 2)]);
+            let mut bytes = Vec::new();
+            cedar.write_to(&mut bytes).unwrap();
+            fs::write(&path, &bytes).unwrap();
+            drop(bytes);
+
+            let view = OwnedCedarView::open(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+            view
+        };
+
+        let view = thread::spawn(move || {
+            assert_eq!(view.exact_match_search("中华").map(|x| x.0), Some(2));
+            view
+        })
+        .join()
+        .unwrap();
+        assert_eq!(view.common_prefix_search("abc").unwrap(), vec![(0, 0), (1, 1)]);
+        assert_eq!(view.exact_match_search("b"), None);
+    }
+
+    #[test]
+    fn test_invalid_bytes() {
+        let mut cedar = Cedar::new();
+        cedar.build(&[("a", 0), ("ab", 1)]);
+        let mut bytes = Vec::new();
+        cedar.write_to(&mut bytes).unwrap();
+
+        assert!(OwnedCedarView::from_bytes(bytes[..bytes.len() / 2].to_vec()).is_err());
+        assert!(OwnedCedarView::from_bytes(Vec::new()).is_err());
+        let mut packed = Vec::new();
+        cedar.write_to_packed(&mut packed).unwrap();
+        assert!(OwnedCedarView::from_bytes(packed).is_err());
+        let mut lying = bytes.clone();
+        lying[16..24].copy_from_slice(&(1u64 << 40).to_le_bytes());
+        assert!(OwnedCedarView::from_bytes(lying).is_err());
+
+        // the corrupted nodes give wrong answers at worst.
+        for i in 48..bytes.len().min(48 + 256 * 8) {
+            let mut corrupted = bytes.clone();
+            corrupted[i] ^= 0xa5;
+            let view = OwnedCedarView::from_bytes(corrupted).unwrap();
+            view.exact_match_search("ab");
+            view.common_prefix_search("abc");
+        }
+    }
+}